@@ -0,0 +1,442 @@
+use std::collections::VecDeque;
+use crate::bus::BusDevice;
+
+// Rates of the internal baud rate generator with a 1.8432 MHz crystal, by the
+// low four bits of the control register. 0 selects the external receiver
+// clock, taken as the crystal divided by 16.
+const BAUD_RATES: [f64; 16] = [
+    115_200.0, 50.0, 75.0, 109.92, 134.58, 150.0, 300.0, 600.0,
+    1200.0, 1800.0, 2400.0, 3600.0, 4800.0, 7200.0, 9600.0, 19_200.0,
+];
+
+/// A 6551 ACIA, the serial port of the kit. The host side of the line is
+/// ideal: it never sends with errors and is always ready for data.
+pub struct Acia {
+    pub(crate) frequency: u64, // CPU clock, used to turn the baud rate into cycles
+    // The W65C51N has TDRE stuck at 1 and starts sending as soon as the data
+    // register is written, cutting off any byte still being sent
+    pub(crate) wdc_bug: bool,
+    pub(crate) command: u8,
+    pub(crate) control: u8,
+    rdr: u8,
+    rdrf: bool,
+    tdr: u8,
+    tdre: bool,
+    overrun: bool,
+    irq_flag: bool,
+    receiving: Option<(u8, u64)>, // Byte on the line and cycles until it is in
+    sending: Option<(u8, u64)>, // and the same for the transmitter
+    pub(crate) input: VecDeque<u8>, // Bytes from the host waiting to go on the line
+    // Modem inputs, true when asserted (low)
+    pub(crate) cts: bool,
+    pub(crate) dsr: bool,
+    pub(crate) dcd: bool,
+    pub(crate) output: VecDeque<u8>, // Shifted out, for the host to take
+    pub(crate) cycles: u64, // The cycle the next tick is for
+}
+
+impl Default for Acia {
+    fn default() -> Acia {
+        Acia::new()
+    }
+}
+
+impl Acia {
+    pub const DATA: u8 = 0;
+    pub const STATUS: u8 = 1;
+    pub const COMMAND: u8 = 2;
+    pub const CONTROL: u8 = 3;
+
+    pub const STATUS_IRQ: u8 = 0b1000_0000;
+    pub const STATUS_DSR: u8 = 0b0100_0000;
+    pub const STATUS_DCD: u8 = 0b0010_0000;
+    pub const STATUS_TDRE: u8 = 0b0001_0000;
+    pub const STATUS_RDRF: u8 = 0b0000_1000;
+    pub const STATUS_OVERRUN: u8 = 0b0000_0100;
+
+    pub fn new() -> Acia {
+        Acia {
+            frequency: 1_000_000,
+            wdc_bug: false,
+            command: 0,
+            control: 0,
+            rdr: 0,
+            rdrf: false,
+            tdr: 0,
+            tdre: true,
+            overrun: false,
+            irq_flag: false,
+            receiving: None,
+            sending: None,
+            input: VecDeque::new(),
+            cts: true,
+            dsr: true,
+            dcd: true,
+            output: VecDeque::new(),
+            cycles: 0,
+        }
+    }
+
+    /// Hardware reset, the RES pin.
+    pub fn reset(&mut self) {
+        self.command = 0;
+        self.control = 0;
+        self.rdrf = false;
+        self.tdre = true;
+        self.overrun = false;
+        self.irq_flag = false;
+        self.receiving = None;
+        self.sending = None;
+    }
+
+    /// DTR, also enables the receiver and interrupts.
+    pub fn dtr(&self) -> bool {
+        self.command & 0b0000_0001 > 0
+    }
+
+    /// RTS, asserted unless the transmitter control bits are 00.
+    pub fn rts(&self) -> bool {
+        self.command & 0b0000_1100 != 0
+    }
+
+    fn transmit_irq_enabled(&self) -> bool {
+        self.command & 0b0000_1100 == 0b0000_0100 && !self.wdc_bug
+    }
+
+    fn receive_irq_enabled(&self) -> bool {
+        self.command & 0b0000_0010 == 0
+    }
+
+    fn echo(&self) -> bool {
+        self.command & 0b0001_1100 == 0b0001_0000
+    }
+
+    fn word_length(&self) -> u32 {
+        8 - ((self.control >> 5) & 0b11) as u32
+    }
+
+    /// Cycles a character takes on the line: start bit, data, parity and stop bits.
+    pub fn char_cycles(&self) -> u64 {
+        let word_length = self.word_length() as f64;
+        let parity = self.command & 0b0010_0000 > 0;
+        let stop_bits = match (self.control & 0b1000_0000 > 0, word_length as u32, parity) {
+            (false, _, _) => 1.0,
+            (true, 8, true) => 1.0,
+            (true, 5, false) => 1.5,
+            (true, _, _) => 2.0,
+        };
+        let bits = 1.0 + word_length + parity as u32 as f64 + stop_bits;
+        let baud = BAUD_RATES[(self.control & 0b1111) as usize];
+        ((self.frequency as f64 * bits / baud).round() as u64).max(1)
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq_flag
+    }
+
+    fn interrupt(&mut self) {
+        if self.dtr() {
+            self.irq_flag = true;
+        }
+    }
+
+    pub fn read(&mut self, reg: u8) -> u8 {
+        match reg & 0b11 {
+            Acia::DATA => {
+                self.rdrf = false;
+                self.rdr
+            }
+            Acia::STATUS => {
+                let mut status = 0;
+                if self.irq_flag { status |= Acia::STATUS_IRQ; }
+                if !self.dsr { status |= Acia::STATUS_DSR; }
+                if !self.dcd { status |= Acia::STATUS_DCD; }
+                if self.tdre || self.wdc_bug { status |= Acia::STATUS_TDRE; }
+                if self.rdrf { status |= Acia::STATUS_RDRF; }
+                if self.overrun { status |= Acia::STATUS_OVERRUN; }
+                self.irq_flag = false;
+                status
+            }
+            Acia::COMMAND => self.command,
+            Acia::CONTROL => self.control,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, reg: u8, value: u8) {
+        match reg & 0b11 {
+            Acia::DATA => {
+                let value = value & (0xFF >> (8 - self.word_length()));
+                if self.wdc_bug {
+                    self.sending = Some((value, self.char_cycles()));
+                } else {
+                    self.tdr = value;
+                    self.tdre = false;
+                }
+            }
+            Acia::STATUS => {
+                // Programmed reset, the parity bits and control register are kept
+                self.command &= 0b1110_0000;
+                self.overrun = false;
+            }
+            Acia::COMMAND => self.command = value,
+            Acia::CONTROL => self.control = value,
+            _ => unreachable!(),
+        }
+    }
+
+    /// One CPU cycle. Bytes sent to the host go to `output`, a byte being
+    /// transmitted and an echoed one can finish in the same cycle.
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        if self.receiving.is_none() && self.dtr() {
+            if let Some(byte) = self.input.pop_front() {
+                self.receiving = Some((byte & (0xFF >> (8 - self.word_length())), self.char_cycles()));
+            }
+        }
+        let mut echoed = None;
+        if let Some((byte, cycles)) = self.receiving {
+            if cycles > 1 {
+                self.receiving = Some((byte, cycles - 1));
+            } else {
+                self.receiving = None;
+                if self.rdrf {
+                    // The byte that was not read is kept, the new one is lost
+                    self.overrun = true;
+                } else {
+                    self.rdr = byte;
+                    self.rdrf = true;
+                    self.overrun = false;
+                }
+                if self.receive_irq_enabled() {
+                    self.interrupt();
+                }
+                if self.echo() {
+                    echoed = Some(byte);
+                }
+            }
+        }
+        if self.sending.is_none() && !self.tdre && self.cts {
+            self.sending = Some((self.tdr, self.char_cycles()));
+            self.tdre = true;
+            if self.transmit_irq_enabled() {
+                self.interrupt();
+            }
+        }
+        match self.sending {
+            Some((byte, cycles)) if cycles <= 1 => {
+                self.sending = None;
+                self.output.push_back(byte);
+            }
+            Some((byte, cycles)) => self.sending = Some((byte, cycles - 1)),
+            None => {}
+        }
+        self.output.extend(echoed);
+    }
+
+    /// The first cycle that needs a tick: a byte is due to start or finish
+    /// on the line. `None` while both directions are idle.
+    pub fn next_event(&self) -> Option<u64> {
+        let receive = match self.receiving {
+            Some((_, cycles)) => Some(cycles.saturating_sub(1)),
+            None => (self.dtr() && !self.input.is_empty()).then_some(0),
+        };
+        let send = match self.sending {
+            Some((_, cycles)) => Some(cycles.saturating_sub(1)),
+            None => (!self.tdre && self.cts).then_some(0),
+        };
+        receive.into_iter().chain(send).min().map(|ticks| self.cycles + ticks)
+    }
+
+    /// Runs the bytes on the line on to `cycles`, over cycles `next_event`
+    /// has no tick in.
+    pub fn catch_up(&mut self, cycles: u64) {
+        let elapsed = cycles.saturating_sub(self.cycles);
+        for (_, left) in [&mut self.receiving, &mut self.sending].into_iter().flatten() {
+            *left = left.saturating_sub(elapsed);
+        }
+        self.cycles = self.cycles.max(cycles);
+    }
+}
+
+impl BusDevice for Acia {
+    fn read(&mut self, addr: u16) -> u8 {
+        Acia::read(self, addr as u8)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        Acia::write(self, addr as u8, value)
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        Acia::tick(self)
+    }
+
+    fn next_event(&self, _cycles: u64) -> Option<u64> {
+        Acia::next_event(self)
+    }
+
+    fn catch_up(&mut self, cycles: u64) {
+        Acia::catch_up(self, cycles)
+    }
+
+    fn irq(&self) -> bool {
+        Acia::irq(self)
+    }
+
+    fn reset(&mut self) {
+        Acia::reset(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::acia::Acia;
+
+    // 19200 baud, 8 data bits, 1 stop bit with DTR, no parity and interrupts
+    fn acia() -> Acia {
+        let mut acia = Acia::new();
+        acia.write(Acia::CONTROL, 0b0001_1111);
+        acia.write(Acia::COMMAND, 0b0000_1011);
+        acia
+    }
+
+    fn run(acia: &mut Acia, cycles: u64) -> Vec<u8> {
+        for _ in 0..cycles {
+            acia.tick();
+        }
+        acia.output.drain(..).collect()
+    }
+
+    #[test]
+    fn test_char_cycles() {
+        let mut acia = acia();
+        // 10 bits at 19200 baud
+        assert_eq!(acia.char_cycles(), 521);
+        acia.frequency = 2_000_000;
+        assert_eq!(acia.char_cycles(), 1042);
+        // 7 bits, even parity, 2 stop bits at 300 baud
+        acia.frequency = 1_000_000;
+        acia.write(Acia::CONTROL, 0b1011_0110);
+        acia.write(Acia::COMMAND, 0b0110_1011);
+        assert_eq!(acia.char_cycles(), 36_667);
+    }
+
+    #[test]
+    fn test_transmit() {
+        let mut acia = acia();
+        assert_eq!(acia.read(Acia::STATUS) & Acia::STATUS_TDRE, Acia::STATUS_TDRE);
+        acia.write(Acia::DATA, b'A');
+        assert_eq!(acia.read(Acia::STATUS) & Acia::STATUS_TDRE, 0);
+        // Moves to the shift register right away, the next byte can be written
+        acia.tick();
+        assert_eq!(acia.read(Acia::STATUS) & Acia::STATUS_TDRE, Acia::STATUS_TDRE);
+        acia.write(Acia::DATA, b'B');
+        assert_eq!(run(&mut acia, 519), vec![]);
+        assert_eq!(run(&mut acia, 522), vec![b'A', b'B']);
+        assert!(!acia.irq());
+    }
+
+    #[test]
+    fn test_next_event() {
+        let mut acia = acia();
+        assert_eq!(acia.next_event(), None);
+        acia.write(Acia::DATA, b'A');
+        assert_eq!(acia.next_event(), Some(0));
+        acia.tick();
+        // Nothing to do until the byte is out
+        assert_eq!(acia.next_event(), Some(520));
+        acia.catch_up(520);
+        assert_eq!(run(&mut acia, 1), vec![b'A']);
+        assert_eq!(acia.next_event(), None);
+    }
+
+    #[test]
+    fn test_transmit_irq() {
+        let mut acia = acia();
+        acia.write(Acia::COMMAND, 0b0000_0101);
+        acia.write(Acia::DATA, b'A');
+        acia.tick();
+        assert!(acia.irq());
+        assert_eq!(acia.read(Acia::STATUS) & Acia::STATUS_IRQ, Acia::STATUS_IRQ);
+        assert!(!acia.irq());
+    }
+
+    #[test]
+    fn test_receive() {
+        let mut acia = acia();
+        acia.write(Acia::COMMAND, 0b0000_1001);
+        acia.input.extend(b"hi");
+        run(&mut acia, 520);
+        assert_eq!(acia.read(Acia::STATUS) & Acia::STATUS_RDRF, 0);
+        run(&mut acia, 1);
+        assert!(acia.irq());
+        assert_eq!(acia.read(Acia::STATUS), Acia::STATUS_IRQ | Acia::STATUS_TDRE | Acia::STATUS_RDRF);
+        assert_eq!(acia.read(Acia::DATA), b'h');
+        assert_eq!(acia.read(Acia::STATUS), Acia::STATUS_TDRE);
+        run(&mut acia, 521);
+        assert_eq!(acia.read(Acia::DATA), b'i');
+    }
+
+    #[test]
+    fn test_overrun() {
+        let mut acia = acia();
+        acia.input.extend(b"ab");
+        run(&mut acia, 2 * 521);
+        assert_eq!(acia.read(Acia::STATUS), Acia::STATUS_TDRE | Acia::STATUS_RDRF | Acia::STATUS_OVERRUN);
+        assert_eq!(acia.read(Acia::DATA), b'a');
+        // Cleared by a programmed reset, which also clears DTR
+        acia.write(Acia::STATUS, 0);
+        assert_eq!(acia.read(Acia::STATUS), Acia::STATUS_TDRE);
+        assert!(!acia.dtr());
+    }
+
+    #[test]
+    fn test_receiver_disabled() {
+        let mut acia = Acia::new();
+        acia.input.push_back(b'x');
+        run(&mut acia, 100_000);
+        assert_eq!(acia.input.len(), 1);
+    }
+
+    #[test]
+    fn test_echo() {
+        let mut acia = acia();
+        acia.write(Acia::COMMAND, 0b0001_0011);
+        acia.input.push_back(b'e');
+        assert_eq!(run(&mut acia, 521), vec![b'e']);
+    }
+
+    #[test]
+    fn test_echo_while_sending() {
+        let mut acia = acia();
+        acia.write(Acia::COMMAND, 0b0001_0011);
+        acia.write(Acia::DATA, b'A');
+        acia.input.push_back(b'e');
+        // Both bytes finish in the same cycle, neither is lost
+        assert_eq!(run(&mut acia, 520), vec![]);
+        assert_eq!(run(&mut acia, 1), vec![b'A', b'e']);
+    }
+
+    #[test]
+    fn test_wdc_bug() {
+        let mut acia = acia();
+        acia.wdc_bug = true;
+        acia.write(Acia::COMMAND, 0b0000_0101);
+        acia.write(Acia::DATA, b'A');
+        assert_eq!(acia.read(Acia::STATUS) & Acia::STATUS_TDRE, Acia::STATUS_TDRE);
+        run(&mut acia, 100);
+        // Writing again before the byte is out cuts it off, and there's no interrupt
+        acia.write(Acia::DATA, b'B');
+        assert_eq!(run(&mut acia, 1000), vec![b'B']);
+        assert!(!acia.irq());
+    }
+
+    #[test]
+    fn test_word_length() {
+        let mut acia = acia();
+        acia.write(Acia::CONTROL, 0b0011_1111);
+        acia.write(Acia::DATA, 0xFF);
+        assert_eq!(run(&mut acia, 1000), vec![0x7F]);
+    }
+}
@@ -0,0 +1,445 @@
+use std::any::Any;
+use crate::acia::Acia;
+use crate::keyboard::Keyboard;
+use crate::memory_map::{MemoryMap, RegionKind};
+use crate::scheduler::Scheduler;
+use crate::via::{InterruptLine, Via};
+
+/// A chip on the data bus. Addresses are relative to the start of the range
+/// the device is attached to, and repeat through it when the range mirrors.
+pub trait BusDevice: Any + Send {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+    /// Called after the access in the cycles the device asked for, with the
+    /// cycles since power on.
+    fn tick(&mut self, _cycles: u64) {}
+    /// The first cycle from `cycles` on that the device needs a tick in,
+    /// asked after every access and tick. `None` while it has nothing to do
+    /// on its own. The default is to tick every cycle.
+    fn next_event(&self, cycles: u64) -> Option<u64> {
+        Some(cycles)
+    }
+    /// Called with the current cycle before the device is accessed, ticked
+    /// or handed out by `Bus::device_mut`. Devices that count time run the
+    /// cycles they weren't ticked in here, nothing but counting happens in
+    /// them.
+    fn catch_up(&mut self, _cycles: u64) {}
+    /// True while the device pulls its interrupt output low. Where that goes
+    /// is up to how the device is attached.
+    fn irq(&self) -> bool {
+        false
+    }
+    /// True while the device pulls NMI low directly.
+    fn nmi(&self) -> bool {
+        false
+    }
+    /// True while the device pulls RDY low, to add wait states to an access
+    /// or to keep the CPU off the bus.
+    fn rdy(&self) -> bool {
+        false
+    }
+    /// True while the device pulls SO low. V is set when it goes low.
+    fn so(&self) -> bool {
+        false
+    }
+    /// Called with SYNC high, before the CPU reads the op-code at `addr`.
+    /// Every device sees the fetches wherever it is attached.
+    fn sync(&mut self, _addr: u16) {}
+    /// Called every cycle RES is held low.
+    fn reset(&mut self) {}
+    /// What a read would return, for debuggers. `None` when reading would
+    /// change the device's state.
+    fn peek(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+}
+
+pub struct Ram {
+    pub(crate) data: Vec<u8>,
+}
+
+impl BusDevice for Ram {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.data[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.data[addr as usize] = value;
+    }
+
+    fn next_event(&self, _cycles: u64) -> Option<u64> {
+        None
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.data[addr as usize])
+    }
+}
+
+/// Writes are ignored.
+pub struct Rom {
+    pub(crate) data: Vec<u8>,
+}
+
+impl BusDevice for Rom {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.data[addr as usize]
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {}
+
+    fn next_event(&self, _cycles: u64) -> Option<u64> {
+        None
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.data[addr as usize])
+    }
+}
+
+struct Attachment {
+    base: u16,
+    size: u32,
+    mirror: u32,
+    line: InterruptLine, // Where the device's IRQ output goes
+    device: Box<dyn BusDevice>,
+}
+
+/// The devices on the bus by address range. Ranges are decoded in the order
+/// the devices were attached, the first match wins like in the memory map.
+///
+/// The bus keeps the time. Devices are only ticked in the cycles they ask
+/// for through `next_event`, and asked again whenever they were accessed.
+pub struct Bus {
+    attachments: Vec<Attachment>,
+    pub(crate) open_bus: Option<u8>,
+    data: u8, // Last value on the data bus
+    scheduler: Scheduler,
+    touched: Vec<usize>, // Handed out by `device_mut`, to be asked for their next event
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Bus {
+            attachments: Vec::new(),
+            open_bus: None,
+            data: 0,
+            scheduler: Scheduler::new(),
+            touched: Vec::new(),
+        }
+    }
+
+    /// RAM and ROM with the contents of the memory map, and a VIA, keyboard
+    /// or ACIA for each device region. `frequency` is the nominal clock.
+    pub fn from_memory_map(memory_map: MemoryMap, frequency: u64) -> Result<Bus, String> {
+        let mut bus = Bus::new();
+        bus.open_bus = memory_map.open_bus;
+        for region in memory_map.regions {
+            let (line, device): (InterruptLine, Box<dyn BusDevice>) = match region.kind {
+                RegionKind::Ram => (InterruptLine::Disconnected, Box::new(Ram { data: region.data })),
+                RegionKind::Rom => (InterruptLine::Disconnected, Box::new(Rom { data: region.data })),
+                RegionKind::Device(device) if device.name == "via" => {
+                    (InterruptLine::parse(device.option("irq").unwrap_or("irq"))?, Box::new(Via::new()))
+                }
+                RegionKind::Device(device) if device.name == "keyboard" => {
+                    (InterruptLine::parse(device.option("irq").unwrap_or("none"))?, Box::new(Keyboard::new()))
+                }
+                RegionKind::Device(device) if device.name == "acia" => {
+                    let mut acia = Acia::new();
+                    acia.frequency = frequency;
+                    acia.wdc_bug = matches!(device.option("wdc_bug"), Some("yes" | "true"));
+                    (InterruptLine::parse(device.option("irq").unwrap_or("irq"))?, Box::new(acia))
+                }
+                RegionKind::Device(device) => return Err(format!("Unknown device: {}", device.name)),
+            };
+            bus.push(Attachment { base: region.base, size: region.size, mirror: region.mirror, line, device });
+        }
+        Ok(bus)
+    }
+
+    /// Attaches a device to `size` bytes from `base`, its IRQ output wired to `line`.
+    pub fn attach(&mut self, base: u16, size: u32, line: InterruptLine, device: impl BusDevice) {
+        self.push(Attachment { base, size, mirror: size, line, device: Box::new(device) });
+    }
+
+    fn push(&mut self, mut attachment: Attachment) {
+        // Attached after power on, its time starts now
+        attachment.device.catch_up(self.scheduler.cycles());
+        self.attachments.push(attachment);
+        self.schedule(self.attachments.len() - 1, self.scheduler.cycles());
+    }
+
+    // Asks the device for its next event from `cycles` on
+    fn schedule(&mut self, index: usize, cycles: u64) {
+        self.scheduler.schedule(index, self.attachments[index].device.next_event(cycles));
+    }
+
+    /// Cycles since power on.
+    pub fn cycles(&self) -> u64 {
+        self.scheduler.cycles()
+    }
+
+    /// The cycle a device is due to be ticked in next.
+    pub fn next_event(&mut self) -> Option<u64> {
+        for index in std::mem::take(&mut self.touched) {
+            self.schedule(index, self.scheduler.cycles());
+        }
+        self.scheduler.next_event()
+    }
+
+    // The attachment decoding `addr`, and the address relative to it
+    fn decode(&self, addr: u16) -> Option<(usize, u16)> {
+        self.attachments.iter().position(|attachment| {
+            addr >= attachment.base && ((addr - attachment.base) as u32) < attachment.size
+        }).map(|index| {
+            let attachment = &self.attachments[index];
+            (index, ((addr - attachment.base) as u32 % attachment.mirror) as u16)
+        })
+    }
+
+    /// Reads from the device at `addr`. Nothing drives the bus at unmapped
+    /// addresses, they read `open_bus` or the last value on the bus.
+    pub fn read(&mut self, addr: u16) -> u8 {
+        self.data = match self.decode(addr) {
+            Some((index, offset)) => {
+                self.attachments[index].device.catch_up(self.scheduler.cycles());
+                let data = self.attachments[index].device.read(offset);
+                self.schedule(index, self.scheduler.cycles());
+                data
+            }
+            None => self.open_bus.unwrap_or(self.data),
+        };
+        self.data
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        self.data = value;
+        if let Some((index, offset)) = self.decode(addr) {
+            self.attachments[index].device.catch_up(self.scheduler.cycles());
+            self.attachments[index].device.write(offset, value);
+            self.schedule(index, self.scheduler.cycles());
+        }
+    }
+
+    /// The byte at `addr` without touching the devices, `None` for devices
+    /// that can't be read that way and unmapped addresses.
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        let (index, offset) = self.decode(addr)?;
+        self.attachments[index].device.peek(offset)
+    }
+
+    /// Stores a byte in RAM or ROM, like loading an image.
+    pub fn poke(&mut self, addr: u16, value: u8) -> Result<(), String> {
+        let (index, offset) = self.decode(addr).ok_or(format!("Nothing at {:#06x}", addr))?;
+        let device = self.attachments[index].device.as_mut() as &mut dyn Any;
+        if let Some(ram) = device.downcast_mut::<Ram>() {
+            ram.data[offset as usize] = value;
+        } else if let Some(rom) = device.downcast_mut::<Rom>() {
+            rom.data[offset as usize] = value;
+        } else {
+            return Err(format!("No RAM or ROM at {:#06x}", addr));
+        }
+        Ok(())
+    }
+
+    /// Ticks the devices that are due and ends the cycle. Returns whether
+    /// IRQ and NMI are pulled low.
+    pub fn tick(&mut self) -> (bool, bool) {
+        let cycles = self.scheduler.cycles();
+        self.next_event();
+        while let Some(index) = self.scheduler.pop_due() {
+            self.attachments[index].device.catch_up(cycles);
+            self.attachments[index].device.tick(cycles);
+            self.schedule(index, cycles + 1);
+        }
+        self.scheduler.advance();
+        let mut irq = false;
+        let mut nmi = false;
+        for attachment in self.attachments.iter() {
+            match attachment.line {
+                InterruptLine::Irq => irq |= attachment.device.irq(),
+                InterruptLine::Nmi => nmi |= attachment.device.irq(),
+                InterruptLine::Disconnected => {}
+            }
+            nmi |= attachment.device.nmi();
+        }
+        (irq, nmi)
+    }
+
+    /// True while a device pulls RDY low.
+    pub fn rdy(&self) -> bool {
+        self.attachments.iter().any(|attachment| attachment.device.rdy())
+    }
+
+    /// True while a device pulls SO low.
+    pub fn so(&self) -> bool {
+        self.attachments.iter().any(|attachment| attachment.device.so())
+    }
+
+    /// Tells the devices about an op-code fetch, before the read.
+    pub fn sync(&mut self, addr: u16) {
+        for index in 0..self.attachments.len() {
+            self.attachments[index].device.catch_up(self.scheduler.cycles());
+            self.attachments[index].device.sync(addr);
+            self.touched.push(index);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for index in 0..self.attachments.len() {
+            self.attachments[index].device.catch_up(self.scheduler.cycles());
+            self.attachments[index].device.reset();
+            self.schedule(index, self.scheduler.cycles());
+        }
+    }
+
+    /// The first device of a type, in decoding order. Counters of devices
+    /// that catch up on access may be behind.
+    pub fn device<T: BusDevice>(&self) -> Option<&T> {
+        self.attachments.iter().find_map(|attachment| {
+            (attachment.device.as_ref() as &dyn Any).downcast_ref::<T>()
+        })
+    }
+
+    /// Changes made through it count like an access.
+    pub fn device_mut<T: BusDevice>(&mut self) -> Option<&mut T> {
+        let index = self.attachments.iter().position(|attachment| (attachment.device.as_ref() as &dyn Any).is::<T>())?;
+        self.attachments[index].device.catch_up(self.scheduler.cycles());
+        self.touched.push(index);
+        (self.attachments[index].device.as_mut() as &mut dyn Any).downcast_mut::<T>()
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Bus {
+        Bus::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::{Bus, BusDevice, Ram};
+    use crate::memory_map::MemoryMap;
+    use crate::via::{InterruptLine, Via};
+
+    // Counts cycles and raises its interrupt when it reaches the limit written to it
+    struct Timer {
+        count: u8,
+        limit: u8,
+    }
+
+    impl BusDevice for Timer {
+        fn read(&mut self, _addr: u16) -> u8 {
+            self.count
+        }
+
+        fn write(&mut self, _addr: u16, value: u8) {
+            (self.count, self.limit) = (0, value);
+        }
+
+        fn tick(&mut self, _cycles: u64) {
+            self.count = self.count.saturating_add(1);
+        }
+
+        fn irq(&self) -> bool {
+            self.limit > 0 && self.count >= self.limit
+        }
+
+        fn reset(&mut self) {
+            self.limit = 0;
+        }
+    }
+
+    // Raises its interrupt a number of cycles after it is written, ticked only then
+    struct Alarm {
+        due: Option<u64>,
+        ticks: Vec<u64>,
+        cycles: u64, // When it was last accessed
+        ringing: bool,
+    }
+
+    impl BusDevice for Alarm {
+        fn read(&mut self, _addr: u16) -> u8 {
+            self.ringing = false;
+            0
+        }
+
+        fn write(&mut self, _addr: u16, value: u8) {
+            self.due = Some(self.cycles + value as u64);
+        }
+
+        fn tick(&mut self, cycles: u64) {
+            self.ticks.push(cycles);
+            if self.due.is_some_and(|due| cycles >= due) {
+                (self.due, self.ringing) = (None, true);
+            }
+        }
+
+        fn next_event(&self, _cycles: u64) -> Option<u64> {
+            self.due
+        }
+
+        fn irq(&self) -> bool {
+            self.ringing
+        }
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut memory_map = MemoryMap::parse("ram $0000 $100 mirror=$10\nvia $6000 $10\nrom $8000 $8000\nopen_bus $ea").unwrap();
+        memory_map.load_segment(0xFFFC, &[0x00, 0x80]).unwrap();
+        let mut bus = Bus::from_memory_map(memory_map, 1_000_000).unwrap();
+        bus.write(0x0003, 0x42);
+        assert_eq!(bus.read(0x0013), 0x42);
+        assert_eq!(bus.read(0xFFFD), 0x80);
+        bus.write(0xFFFD, 0x00);
+        assert_eq!(bus.peek(0xFFFD), Some(0x80));
+        assert_eq!(bus.read(0x4000), 0xEA);
+        assert_eq!(bus.peek(0x6000), None);
+        bus.write(0x6000 + Via::DDRB as u16, 0xFF);
+        assert_eq!(bus.device::<Via>().unwrap().ddrb, 0xFF);
+        bus.poke(0xFFFD, 0x90).unwrap();
+        assert_eq!(bus.peek(0xFFFD), Some(0x90));
+        assert!(bus.poke(0x6000, 0).is_err());
+        assert!(bus.poke(0x4000, 0).is_err());
+    }
+
+    #[test]
+    fn test_attach() {
+        let mut bus = Bus::new();
+        bus.attach(0x0000, 0x100, InterruptLine::Disconnected, Ram { data: vec![0; 0x100] });
+        bus.attach(0x0000, 0x200, InterruptLine::Nmi, Timer { count: 0, limit: 0 });
+        // The RAM was there first
+        assert_eq!(bus.peek(0x00FF), Some(0));
+        bus.write(0x0100, 3);
+        assert_eq!(bus.tick(), (false, false));
+        bus.tick();
+        assert_eq!(bus.read(0x0100), 2);
+        assert_eq!(bus.tick(), (false, true));
+        bus.reset();
+        assert_eq!(bus.tick(), (false, false));
+        assert_eq!(bus.device_mut::<Timer>().unwrap().count, 4);
+        assert_eq!(bus.cycles(), 4);
+        // Nothing drives the bus, the last value stays
+        assert_eq!(bus.read(0x8000), 2);
+    }
+
+    #[test]
+    fn test_scheduled_device() {
+        let mut bus = Bus::new();
+        bus.attach(0x0000, 1, InterruptLine::Irq, Alarm { due: None, ticks: Vec::new(), cycles: 0, ringing: false });
+        for _ in 0..10 {
+            bus.tick();
+        }
+        bus.device_mut::<Alarm>().unwrap().cycles = bus.cycles();
+        bus.write(0x0000, 5);
+        assert_eq!(bus.next_event(), Some(15));
+        let lines: Vec<(bool, bool)> = (0..10).map(|_| bus.tick()).collect();
+        assert_eq!(lines[4], (false, false));
+        assert_eq!(lines[5], (true, false));
+        assert_eq!(bus.device::<Alarm>().unwrap().ticks, vec![15]);
+        bus.read(0x0000);
+        assert_eq!(bus.tick(), (false, false));
+        assert_eq!(bus.next_event(), None);
+    }
+}
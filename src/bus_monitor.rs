@@ -0,0 +1,58 @@
+use crate::cpu::{CpuInputPins, CpuOutputPins};
+
+/// Formats one bus cycle the way the Arduino bus monitor prints it:
+/// the address lines in binary, the data lines in binary, the address and data
+/// in hex, `r` or `W` for the RWB pin, SYNC when an op-code is fetched and
+/// RDY when the cycle is stretched by RDY held low.
+pub fn format_cycle(output_pins: &CpuOutputPins, input_pins: &CpuInputPins) -> String {
+    let data = if output_pins.rwb { input_pins.data } else { output_pins.data };
+    format!("{:016b}   {:08b}   {:04x}  {} {:02x}{}{}",
+            output_pins.addr,
+            data,
+            output_pins.addr,
+            if output_pins.rwb { 'r' } else { 'W' },
+            data,
+            if output_pins.sync { "  SYNC" } else { "" },
+            if input_pins.rdy { "" } else { "  RDY" })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus_monitor::format_cycle;
+    use crate::cpu::{CpuInputPins, CpuOutputPins};
+
+    fn input_pins(data: u8) -> CpuInputPins {
+        CpuInputPins {
+            data,
+            irq: true,
+            nmi: true,
+            phi2: true,
+            rdy: true,
+            so: true,
+            res: true,
+            vdd: true,
+        }
+    }
+
+    #[test]
+    fn test_format_read() {
+        let output_pins = CpuOutputPins { addr: 0x8000, data: 0x12, rwb: true, sync: true };
+        assert_eq!(format_cycle(&output_pins, &input_pins(0xa9)),
+                   "1000000000000000   10101001   8000  r a9  SYNC");
+    }
+
+    #[test]
+    fn test_format_write() {
+        let output_pins = CpuOutputPins { addr: 0x6002, data: 0xff, rwb: false, sync: false };
+        assert_eq!(format_cycle(&output_pins, &input_pins(0x00)),
+                   "0110000000000010   11111111   6002  W ff");
+    }
+
+    #[test]
+    fn test_format_wait() {
+        let output_pins = CpuOutputPins { addr: 0x8000, data: 0x12, rwb: true, sync: true };
+        let input_pins = CpuInputPins { rdy: false, ..input_pins(0xa9) };
+        assert_eq!(format_cycle(&output_pins, &input_pins),
+                   "1000000000000000   10101001   8000  r a9  SYNC  RDY");
+    }
+}
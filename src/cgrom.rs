@@ -1,4 +1,129 @@
-pub(crate) static CGROM: [u8; 8 * 256] = [
+use std::fs;
+
+/// The glyphs of the character generator ROM, 10 rows of 5 dots for each of the
+/// 256 character codes. 5x8 characters leave rows 8 and 9 blank.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterRom {
+    glyphs: Vec<[u8; 10]>,
+}
+
+impl CharacterRom {
+    fn from_table(table: &[u8; 8 * 256]) -> CharacterRom {
+        let mut glyphs = vec![[0; 10]; 256];
+        for (code, glyph) in glyphs.iter_mut().enumerate() {
+            glyph[..8].copy_from_slice(&table[code * 8..code * 8 + 8]);
+        }
+        CharacterRom { glyphs }
+    }
+
+    /// The Japanese character set most modules are fitted with.
+    pub fn a00() -> CharacterRom {
+        let mut rom = CharacterRom::from_table(&CGROM_A00);
+        for (code, rows) in A00_DESCENDERS {
+            rom.glyphs[code as usize][8..].copy_from_slice(&rows);
+        }
+        rom
+    }
+
+    /// The European character set.
+    pub fn a02() -> CharacterRom {
+        CharacterRom::from_table(&CGROM_A02)
+    }
+
+    /// `a00`, `a02` or the name of a font file.
+    pub fn parse(name: &str) -> Result<CharacterRom, String> {
+        match name.to_lowercase().as_str() {
+            "a00" => Ok(CharacterRom::a00()),
+            "a02" => Ok(CharacterRom::a02()),
+            _ => CharacterRom::load(name),
+        }
+    }
+
+    /// Reads a binary ROM image of 8 or 16 bytes per character, or a text font.
+    pub fn load(path: &str) -> Result<CharacterRom, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        match bytes.len() {
+            2048 => Ok(CharacterRom::from_binary(&bytes, 8)),
+            4096 => Ok(CharacterRom::from_binary(&bytes, 16)),
+            _ => {
+                let text = String::from_utf8(bytes)
+                    .map_err(|_| format!("{} is neither a 2K or 4K ROM image nor a text font", path))?;
+                CharacterRom::parse_text(&text).map_err(|e| format!("{}: {}", path, e))
+            }
+        }
+    }
+
+    fn from_binary(bytes: &[u8], stride: usize) -> CharacterRom {
+        let mut glyphs = vec![[0; 10]; 256];
+        for (glyph, rows) in glyphs.iter_mut().zip(bytes.chunks(stride)) {
+            let n = rows.len().min(10);
+            for (dots, byte) in glyph.iter_mut().zip(&rows[..n]) {
+                *dots = byte & 0b1_1111;
+            }
+        }
+        CharacterRom { glyphs }
+    }
+
+    /// A text font, starting from an optional `base a00` or `base a02` line
+    /// (blank otherwise), followed by characters like
+    ///
+    /// ```text
+    /// $41    ; or 0x41
+    /// .###.
+    /// #...#
+    /// #####
+    /// ```
+    ///
+    /// with up to 10 rows of `#` and `.` each, missing rows are blank. `;` starts
+    /// a comment.
+    pub fn parse_text(text: &str) -> Result<CharacterRom, String> {
+        let mut rom = CharacterRom { glyphs: vec![[0; 10]; 256] };
+        let mut glyph: Option<(usize, usize)> = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap().trim();
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(base) = line.strip_prefix("base ") {
+                if glyph.is_some() {
+                    return Err(error("base must come before the characters"));
+                }
+                rom = match base.trim().to_lowercase().as_str() {
+                    "a00" => CharacterRom::a00(),
+                    "a02" => CharacterRom::a02(),
+                    _ => return Err(error("the base is a00 or a02")),
+                };
+            } else if line.starts_with(['.', '#']) {
+                let (code, row) = glyph.as_mut().ok_or_else(|| error("dots before a character code"))?;
+                if *row == 10 {
+                    return Err(error("more than 10 rows"));
+                }
+                if line.len() != 5 || !line.chars().all(|c| c == '.' || c == '#') {
+                    return Err(error("expected 5 dots of # or ."));
+                }
+                rom.glyphs[*code][*row] = line.chars().fold(0, |dots, c| (dots << 1) | (c == '#') as u8);
+                *row += 1;
+            } else {
+                let code = line.strip_prefix('$').or_else(|| line.strip_prefix("0x"))
+                    .and_then(|code| u8::from_str_radix(code, 16).ok())
+                    .ok_or_else(|| error("expected a character code like $41"))?;
+                rom.glyphs[code as usize] = [0; 10];
+                glyph = Some((code as usize, 0));
+            }
+        }
+        Ok(rom)
+    }
+
+    /// Dots of one row of a character, the leftmost dot in bit 4.
+    pub fn row(&self, code: u8, row: u8) -> u8 {
+        self.glyphs[code as usize].get(row as usize).copied().unwrap_or(0)
+    }
+}
+
+/// The A00 character set, Japanese with katakana in 0xA0-0xDF. 0xE0-0xFF are
+/// 5x10 characters, see `A00_DESCENDERS` for their last two rows.
+pub(crate) static CGROM_A00: [u8; 8 * 256] = [
     // 0000
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     // 0001
@@ -1929,4 +2054,2505 @@ pub(crate) static CGROM: [u8; 8 * 256] = [
     0b11111,
     0b11111,
     0b11111,
-];
\ No newline at end of file
+];
+
+/// The A02 character set, European with Cyrillic, Greek and Latin-1 letters.
+pub(crate) static CGROM_A02: [u8; 8 * 256] = [
+    // 0000
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    // 0001
+    // ▶
+    0b10000,
+    0b11000,
+    0b11100,
+    0b11110,
+    0b11100,
+    0b11000,
+    0b10000,
+    0,
+
+    // ◀
+    0b00001,
+    0b00011,
+    0b00111,
+    0b01111,
+    0b00111,
+    0b00011,
+    0b00001,
+    0,
+
+    // “
+    0b01001,
+    0b10010,
+    0b11011,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // ”
+    0b11011,
+    0b01001,
+    0b10010,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // ⏫
+    0b00100,
+    0b01110,
+    0b11111,
+    0b00100,
+    0b01110,
+    0b11111,
+    0b00000,
+    0,
+
+    // ⏬
+    0b11111,
+    0b01110,
+    0b00100,
+    0b11111,
+    0b01110,
+    0b00100,
+    0b00000,
+    0,
+
+    // ●
+    0b00000,
+    0b01110,
+    0b11111,
+    0b11111,
+    0b11111,
+    0b01110,
+    0b00000,
+    0,
+
+    // ↲
+    0b00001,
+    0b00001,
+    0b00101,
+    0b01001,
+    0b11111,
+    0b01000,
+    0b00100,
+    0,
+
+    // ↑
+    0b00100,
+    0b01110,
+    0b10101,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0,
+
+    // ↓
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b10101,
+    0b01110,
+    0b00100,
+    0,
+
+    // →
+    0b00000,
+    0b00100,
+    0b00010,
+    0b11111,
+    0b00010,
+    0b00100,
+    0b00000,
+    0,
+
+    // ←
+    0b00000,
+    0b00100,
+    0b01000,
+    0b11111,
+    0b01000,
+    0b00100,
+    0b00000,
+    0,
+
+    // ≤
+    0b00011,
+    0b01100,
+    0b10000,
+    0b01100,
+    0b00011,
+    0b00000,
+    0b11111,
+    0,
+
+    // ≥
+    0b11000,
+    0b00110,
+    0b00001,
+    0b00110,
+    0b11000,
+    0b00000,
+    0b11111,
+    0,
+
+    // ▲
+    0b00000,
+    0b00100,
+    0b00100,
+    0b01110,
+    0b01110,
+    0b11111,
+    0b00000,
+    0,
+
+    // ▼
+    0b00000,
+    0b11111,
+    0b01110,
+    0b01110,
+    0b00100,
+    0b00100,
+    0b00000,
+    0,
+
+    // 0010
+    // space
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+
+    // !
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00000,
+    0b00000,
+    0b00100,
+    0,
+
+    // "
+    0b01010,
+    0b01010,
+    0b01010,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // #
+    0b01010,
+    0b01010,
+    0b11111,
+    0b01010,
+    0b11111,
+    0b01010,
+    0b01010,
+    0,
+
+    // $
+    0b00100,
+    0b01111,
+    0b10100,
+    0b01110,
+    0b00101,
+    0b11110,
+    0b00100,
+    0,
+
+    // %
+    0b11000,
+    0b11001,
+    0b00010,
+    0b00100,
+    0b01000,
+    0b10011,
+    0b00011,
+    0,
+
+    // &
+    0b01100,
+    0b10010,
+    0b10100,
+    0b01000,
+    0b10101,
+    0b10010,
+    0b01101,
+    0,
+
+    // '
+    0b01100,
+    0b00100,
+    0b01000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // (
+    0b00010,
+    0b00100,
+    0b01000,
+    0b01000,
+    0b01000,
+    0b00100,
+    0b00010,
+    0,
+
+    // )
+    0b01000,
+    0b00100,
+    0b00010,
+    0b00010,
+    0b00010,
+    0b00100,
+    0b01000,
+    0,
+
+    // *
+    0b00000,
+    0b00100,
+    0b10101,
+    0b01110,
+    0b10101,
+    0b00100,
+    0b00000,
+    0,
+
+    // +
+    0b00000,
+    0b00100,
+    0b00100,
+    0b11111,
+    0b00100,
+    0b00100,
+    0b00000,
+    0,
+
+    // ,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b01100,
+    0b00100,
+    0b01000,
+    0,
+
+    // -
+    0b00000,
+    0b00000,
+    0b00000,
+    0b11111,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // .
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b01100,
+    0b01100,
+    0,
+
+    // /
+    0b00000,
+    0b00001,
+    0b00010,
+    0b00100,
+    0b01000,
+    0b10000,
+    0b00000,
+    0,
+
+    // 0011
+    // 0
+    0b01110,
+    0b10001,
+    0b10011,
+    0b10101,
+    0b11001,
+    0b10001,
+    0b01110,
+    0,
+
+    // 1
+    0b00100,
+    0b01100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // 2
+    0b01110,
+    0b10001,
+    0b00001,
+    0b00010,
+    0b00100,
+    0b01000,
+    0b11111,
+    0,
+
+    // 3
+    0b11111,
+    0b00010,
+    0b00100,
+    0b00010,
+    0b00001,
+    0b10001,
+    0b01110,
+    0,
+
+    // 4
+    0b00010,
+    0b00110,
+    0b01010,
+    0b10010,
+    0b11111,
+    0b00010,
+    0b00010,
+    0,
+
+    // 5
+    0b11111,
+    0b10000,
+    0b11110,
+    0b00001,
+    0b00001,
+    0b10001,
+    0b01110,
+    0,
+
+    // 6
+    0b00110,
+    0b01000,
+    0b10000,
+    0b11110,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // 7
+    0b11111,
+    0b10001,
+    0b00001,
+    0b00010,
+    0b00100,
+    0b00100,
+    0b00100,
+    0,
+
+    // 8
+    0b01110,
+    0b10001,
+    0b10001,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // 9
+    0b01110,
+    0b10001,
+    0b10001,
+    0b01111,
+    0b00001,
+    0b00010,
+    0b01100,
+    0,
+
+    // :
+    0b00000,
+    0b01100,
+    0b01100,
+    0b00000,
+    0b01100,
+    0b01100,
+    0b00000,
+    0,
+
+    // ;
+    0b00000,
+    0b01100,
+    0b01100,
+    0b00000,
+    0b01100,
+    0b00100,
+    0b01000,
+    0,
+
+    // <
+    0b00010,
+    0b00100,
+    0b01000,
+    0b10000,
+    0b01000,
+    0b00100,
+    0b00010,
+    0,
+
+    // =
+    0b00000,
+    0b00000,
+    0b11111,
+    0b00000,
+    0b11111,
+    0b00000,
+    0b00000,
+    0,
+
+    // >
+    0b01000,
+    0b00100,
+    0b00010,
+    0b00001,
+    0b00010,
+    0b00100,
+    0b01000,
+    0,
+
+    // ?
+    0b01110,
+    0b10001,
+    0b00001,
+    0b00010,
+    0b00100,
+    0b00000,
+    0b00100,
+    0,
+
+    // 0100
+    // @
+    0b01110,
+    0b10001,
+    0b00001,
+    0b01101,
+    0b10101,
+    0b10101,
+    0b01110,
+    0,
+
+    // A
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b11111,
+    0b10001,
+    0b10001,
+    0,
+
+    // B
+    0b11110,
+    0b10001,
+    0b10001,
+    0b11110,
+    0b10001,
+    0b10001,
+    0b11110,
+    0,
+
+    // C
+    0b01110,
+    0b10001,
+    0b10000,
+    0b10000,
+    0b10000,
+    0b10001,
+    0b01110,
+    0,
+
+    // D
+    0b11100,
+    0b10010,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10010,
+    0b11100,
+    0,
+
+    // E
+    0b11111,
+    0b10000,
+    0b10000,
+    0b11110,
+    0b10000,
+    0b10000,
+    0b11111,
+    0,
+
+    // F
+    0b11111,
+    0b10000,
+    0b10000,
+    0b11110,
+    0b10000,
+    0b10000,
+    0b10000,
+    0,
+
+    // G
+    0b01110,
+    0b10001,
+    0b10000,
+    0b10111,
+    0b10001,
+    0b10001,
+    0b01111,
+    0,
+
+    // H
+    0b10001,
+    0b10001,
+    0b10001,
+    0b11111,
+    0b10001,
+    0b10001,
+    0b10001,
+    0,
+
+    // I
+    0b01110,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // J
+    0b00111,
+    0b00010,
+    0b00010,
+    0b00010,
+    0b00010,
+    0b10010,
+    0b01100,
+    0,
+
+    // K
+    0b10001,
+    0b10010,
+    0b10100,
+    0b11000,
+    0b10100,
+    0b10010,
+    0b10001,
+    0,
+
+    // L
+    0b10000,
+    0b10000,
+    0b10000,
+    0b10000,
+    0b10000,
+    0b10000,
+    0b11111,
+    0,
+
+    // M
+    0b10001,
+    0b11011,
+    0b10101,
+    0b10101,
+    0b10001,
+    0b10001,
+    0b10001,
+    0,
+
+    // N
+    0b10001,
+    0b10001,
+    0b11001,
+    0b10101,
+    0b10011,
+    0b10001,
+    0b10001,
+    0,
+
+    // O
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // 0101
+    // P
+    0b11110,
+    0b10001,
+    0b10001,
+    0b11110,
+    0b10000,
+    0b10000,
+    0b10000,
+    0,
+
+    // Q
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10101,
+    0b10010,
+    0b01101,
+    0,
+
+    // R
+    0b11110,
+    0b10001,
+    0b10001,
+    0b11110,
+    0b10100,
+    0b10010,
+    0b10001,
+    0,
+
+    // S
+    0b01111,
+    0b10000,
+    0b10000,
+    0b01110,
+    0b00001,
+    0b00001,
+    0b11110,
+    0,
+
+    // T
+    0b11111,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0,
+
+    // U
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // V
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01010,
+    0b00100,
+    0,
+
+    // W
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10101,
+    0b10101,
+    0b10101,
+    0b01010,
+    0,
+
+    // X
+    0b10001,
+    0b10001,
+    0b01010,
+    0b00100,
+    0b01010,
+    0b10001,
+    0b10001,
+    0,
+
+    // Y
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01010,
+    0b00100,
+    0b00100,
+    0b00100,
+    0,
+
+    // Z
+    0b11111,
+    0b00001,
+    0b00010,
+    0b00100,
+    0b01000,
+    0b10000,
+    0b11111,
+    0,
+
+    // [
+    0b01110,
+    0b01000,
+    0b01000,
+    0b01000,
+    0b01000,
+    0b01000,
+    0b01110,
+    0,
+
+    // \
+    0b00000,
+    0b10000,
+    0b01000,
+    0b00100,
+    0b00010,
+    0b00001,
+    0b00000,
+    0,
+
+    // ]
+    0b01110,
+    0b00010,
+    0b00010,
+    0b00010,
+    0b00010,
+    0b00010,
+    0b01110,
+    0,
+
+    // ^
+    0b00100,
+    0b01010,
+    0b10001,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // _
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b11111,
+    0,
+
+    // 0110
+    // `
+    0b01000,
+    0b00100,
+    0b00010,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // a
+    0b00000,
+    0b00000,
+    0b01110,
+    0b00001,
+    0b01111,
+    0b10001,
+    0b01111,
+    0,
+
+    // b
+    0b10000,
+    0b10000,
+    0b10110,
+    0b11001,
+    0b10001,
+    0b10001,
+    0b11110,
+    0,
+
+    // c
+    0b00000,
+    0b00000,
+    0b01110,
+    0b10000,
+    0b10000,
+    0b10001,
+    0b01110,
+    0,
+
+    // d
+    0b00001,
+    0b00001,
+    0b01101,
+    0b10011,
+    0b10001,
+    0b10001,
+    0b01111,
+    0,
+
+    // e
+    0b00000,
+    0b00000,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10000,
+    0b01110,
+    0,
+
+    // f
+    0b00110,
+    0b01001,
+    0b01000,
+    0b11100,
+    0b01000,
+    0b01000,
+    0b01000,
+    0,
+
+    // g
+    0b00000,
+    0b01111,
+    0b10001,
+    0b10001,
+    0b01111,
+    0b00001,
+    0b01110,
+    0,
+
+    // h
+    0b10000,
+    0b10000,
+    0b10110,
+    0b11001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0,
+
+    // i
+    0b00100,
+    0b00000,
+    0b01100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // j
+    0b00010,
+    0b00000,
+    0b00110,
+    0b00010,
+    0b00010,
+    0b10010,
+    0b01100,
+    0,
+
+    // k
+    0b10000,
+    0b10000,
+    0b10010,
+    0b10100,
+    0b11000,
+    0b10100,
+    0b10010,
+    0,
+
+    // l
+    0b01100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // m
+    0b00000,
+    0b00000,
+    0b11010,
+    0b10101,
+    0b10101,
+    0b10001,
+    0b10001,
+    0,
+
+    // n
+    0b00000,
+    0b00000,
+    0b10110,
+    0b11001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0,
+
+    // o
+    0b00000,
+    0b00000,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // 0111
+    // p
+    0b00000,
+    0b00000,
+    0b11110,
+    0b10001,
+    0b11110,
+    0b10000,
+    0b10000,
+    0,
+
+    // q
+    0b00000,
+    0b00000,
+    0b01101,
+    0b10011,
+    0b01111,
+    0b00001,
+    0b00001,
+    0,
+
+    // r
+    0b00000,
+    0b00000,
+    0b10110,
+    0b11001,
+    0b10000,
+    0b10000,
+    0b10000,
+    0,
+
+    // s
+    0b00000,
+    0b00000,
+    0b01110,
+    0b10000,
+    0b01110,
+    0b00001,
+    0b11110,
+    0,
+
+    // t
+    0b01000,
+    0b01000,
+    0b11100,
+    0b01000,
+    0b01000,
+    0b01001,
+    0b00110,
+    0,
+
+    // u
+    0b00000,
+    0b00000,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10011,
+    0b01101,
+    0,
+
+    // v
+    0b00000,
+    0b00000,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01010,
+    0b00100,
+    0,
+
+    // w
+    0b00000,
+    0b00000,
+    0b10001,
+    0b10101,
+    0b10101,
+    0b10101,
+    0b01010,
+    0,
+
+    // x
+    0b00000,
+    0b00000,
+    0b10001,
+    0b01010,
+    0b00100,
+    0b01010,
+    0b10001,
+    0,
+
+    // y
+    0b00000,
+    0b00000,
+    0b10001,
+    0b10001,
+    0b01111,
+    0b00001,
+    0b01110,
+    0,
+
+    // z
+    0b00000,
+    0b00000,
+    0b11111,
+    0b00010,
+    0b00100,
+    0b01000,
+    0b11111,
+    0,
+
+    // {
+    0b00010,
+    0b00100,
+    0b00100,
+    0b01000,
+    0b00100,
+    0b00100,
+    0b00010,
+    0,
+
+    // |
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0,
+
+    // }
+    0b01000,
+    0b00100,
+    0b00100,
+    0b00010,
+    0b00100,
+    0b00100,
+    0b01000,
+    0,
+
+    // ~
+    0b00000,
+    0b00000,
+    0b01101,
+    0b10010,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // ⌂
+    0b00100,
+    0b01010,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b11111,
+    0b00000,
+    0,
+
+    // 1000
+    // Б
+    0b11111,
+    0b10000,
+    0b10000,
+    0b11110,
+    0b10001,
+    0b10001,
+    0b11110,
+    0,
+
+    // Д
+    0b00110,
+    0b01010,
+    0b01010,
+    0b01010,
+    0b01010,
+    0b11111,
+    0b10001,
+    0,
+
+    // Ж
+    0b10101,
+    0b10101,
+    0b01110,
+    0b00100,
+    0b01110,
+    0b10101,
+    0b10101,
+    0,
+
+    // З
+    0b01110,
+    0b10001,
+    0b00001,
+    0b00110,
+    0b00001,
+    0b10001,
+    0b01110,
+    0,
+
+    // И
+    0b10001,
+    0b10001,
+    0b10011,
+    0b10101,
+    0b11001,
+    0b10001,
+    0b10001,
+    0,
+
+    // Й
+    0b01010,
+    0b00100,
+    0b10001,
+    0b10011,
+    0b10101,
+    0b11001,
+    0b10001,
+    0,
+
+    // Л
+    0b00111,
+    0b01001,
+    0b01001,
+    0b01001,
+    0b01001,
+    0b01001,
+    0b10001,
+    0,
+
+    // П
+    0b11111,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0,
+
+    // У
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01111,
+    0b00001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Ц
+    0b10010,
+    0b10010,
+    0b10010,
+    0b10010,
+    0b10010,
+    0b11111,
+    0b00001,
+    0,
+
+    // Ч
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01111,
+    0b00001,
+    0b00001,
+    0b00001,
+    0,
+
+    // Ш
+    0b10001,
+    0b10001,
+    0b10101,
+    0b10101,
+    0b10101,
+    0b10101,
+    0b11111,
+    0,
+
+    // Щ
+    0b10101,
+    0b10101,
+    0b10101,
+    0b10101,
+    0b10101,
+    0b11111,
+    0b00001,
+    0,
+
+    // Ъ
+    0b11000,
+    0b01000,
+    0b01000,
+    0b01110,
+    0b01001,
+    0b01001,
+    0b01110,
+    0,
+
+    // Ы
+    0b10001,
+    0b10001,
+    0b10001,
+    0b11101,
+    0b10101,
+    0b10101,
+    0b11101,
+    0,
+
+    // Э
+    0b01110,
+    0b10001,
+    0b00001,
+    0b00111,
+    0b00001,
+    0b10001,
+    0b01110,
+    0,
+
+    // 1001
+    // α
+    0b00000,
+    0b00000,
+    0b01101,
+    0b10010,
+    0b10010,
+    0b10010,
+    0b01101,
+    0,
+
+    // ♪
+    0b00100,
+    0b00110,
+    0b00101,
+    0b00100,
+    0b01100,
+    0b11100,
+    0b01000,
+    0,
+
+    // Γ
+    0b11111,
+    0b10000,
+    0b10000,
+    0b10000,
+    0b10000,
+    0b10000,
+    0b10000,
+    0,
+
+    // π
+    0b00000,
+    0b00000,
+    0b11111,
+    0b01010,
+    0b01010,
+    0b01010,
+    0b01001,
+    0,
+
+    // Σ
+    0b11111,
+    0b10000,
+    0b01000,
+    0b00100,
+    0b01000,
+    0b10000,
+    0b11111,
+    0,
+
+    // σ
+    0b00000,
+    0b00000,
+    0b01111,
+    0b10010,
+    0b10010,
+    0b10010,
+    0b01100,
+    0,
+
+    // ♬
+    0b01111,
+    0b01001,
+    0b01001,
+    0b01001,
+    0b11011,
+    0b11011,
+    0b00000,
+    0,
+
+    // τ
+    0b00000,
+    0b00000,
+    0b11111,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00010,
+    0,
+
+    // bell
+    0b00100,
+    0b01110,
+    0b01110,
+    0b01110,
+    0b11111,
+    0b00000,
+    0b00100,
+    0,
+
+    // Θ
+    0b01110,
+    0b10001,
+    0b10001,
+    0b11111,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Ω
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01010,
+    0b01010,
+    0b11011,
+    0,
+
+    // δ
+    0b00110,
+    0b01000,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // ∞
+    0b00000,
+    0b00000,
+    0b01010,
+    0b10101,
+    0b10101,
+    0b01010,
+    0b00000,
+    0,
+
+    // ♥
+    0b00000,
+    0b01010,
+    0b11111,
+    0b11111,
+    0b01110,
+    0b00100,
+    0b00000,
+    0,
+
+    // ε
+    0b00000,
+    0b00000,
+    0b01110,
+    0b10000,
+    0b01100,
+    0b10000,
+    0b01110,
+    0,
+
+    // ∩
+    0b00000,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b00000,
+    0,
+
+    // 1010
+    // no-break space
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+
+    // ¡
+    0b00100,
+    0b00000,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0,
+
+    // ¢
+    0b00100,
+    0b01111,
+    0b10100,
+    0b10100,
+    0b10100,
+    0b01111,
+    0b00100,
+    0,
+
+    // £
+    0b00110,
+    0b01001,
+    0b01000,
+    0b11100,
+    0b01000,
+    0b01001,
+    0b10110,
+    0,
+
+    // ¤
+    0b00000,
+    0b10001,
+    0b01110,
+    0b01010,
+    0b01110,
+    0b10001,
+    0b00000,
+    0,
+
+    // ¥
+    0b10001,
+    0b01010,
+    0b11111,
+    0b00100,
+    0b11111,
+    0b00100,
+    0b00100,
+    0,
+
+    // ¦
+    0b00100,
+    0b00100,
+    0b00100,
+    0b00000,
+    0b00100,
+    0b00100,
+    0b00100,
+    0,
+
+    // §
+    0b01110,
+    0b10000,
+    0b01110,
+    0b10001,
+    0b01110,
+    0b00001,
+    0b01110,
+    0,
+
+    // ¨
+    0b00011,
+    0b00100,
+    0b00100,
+    0b01110,
+    0b00100,
+    0b00100,
+    0b11000,
+    0,
+
+    // ©
+    0b01110,
+    0b10001,
+    0b10111,
+    0b10101,
+    0b10111,
+    0b10001,
+    0b01110,
+    0,
+
+    // ª
+    0b01110,
+    0b00001,
+    0b01111,
+    0b10001,
+    0b01111,
+    0b00000,
+    0b11111,
+    0,
+
+    // «
+    0b00000,
+    0b00101,
+    0b01010,
+    0b10100,
+    0b01010,
+    0b00101,
+    0b00000,
+    0,
+
+    // Ю
+    0b10010,
+    0b10101,
+    0b10101,
+    0b11101,
+    0b10101,
+    0b10101,
+    0b10010,
+    0,
+
+    // Я
+    0b01111,
+    0b10001,
+    0b10001,
+    0b01111,
+    0b00101,
+    0b01001,
+    0b10001,
+    0,
+
+    // ®
+    0b01110,
+    0b11101,
+    0b11011,
+    0b11101,
+    0b11011,
+    0b10001,
+    0b01110,
+    0,
+
+    // ¯
+    0b00100,
+    0b01000,
+    0b01100,
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // 1011
+    // °
+    0b01100,
+    0b10010,
+    0b10010,
+    0b01100,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // ±
+    0b00100,
+    0b00100,
+    0b11111,
+    0b00100,
+    0b00100,
+    0b00000,
+    0b11111,
+    0,
+
+    // ²
+    0b01100,
+    0b00010,
+    0b00100,
+    0b01110,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // ³
+    0b01100,
+    0b00010,
+    0b00100,
+    0b00010,
+    0b01100,
+    0b00000,
+    0b00000,
+    0,
+
+    // Pt
+    0b11000,
+    0b10100,
+    0b11010,
+    0b10111,
+    0b10010,
+    0b10010,
+    0b00000,
+    0,
+
+    // µ
+    0b00000,
+    0b00000,
+    0b10001,
+    0b10001,
+    0b10011,
+    0b11101,
+    0b10000,
+    0,
+
+    // ¶
+    0b01111,
+    0b11101,
+    0b11101,
+    0b01101,
+    0b00101,
+    0b00101,
+    0b00101,
+    0,
+
+    // ·
+    0b00000,
+    0b00000,
+    0b00000,
+    0b00100,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // ω
+    0b00000,
+    0b00000,
+    0b01010,
+    0b10001,
+    0b10101,
+    0b10101,
+    0b01010,
+    0,
+
+    // ¹
+    0b00100,
+    0b01100,
+    0b00100,
+    0b01110,
+    0b00000,
+    0b00000,
+    0b00000,
+    0,
+
+    // º
+    0b01110,
+    0b10001,
+    0b10001,
+    0b01110,
+    0b00000,
+    0b11111,
+    0b00000,
+    0,
+
+    // »
+    0b00000,
+    0b10100,
+    0b01010,
+    0b00101,
+    0b01010,
+    0b10100,
+    0b00000,
+    0,
+
+    // ¼
+    0b10001,
+    0b10010,
+    0b10100,
+    0b01010,
+    0b10110,
+    0b01111,
+    0b00010,
+    0,
+
+    // ½
+    0b10001,
+    0b10010,
+    0b10100,
+    0b01011,
+    0b10001,
+    0b00010,
+    0b00111,
+    0,
+
+    // ¾
+    0b11001,
+    0b01010,
+    0b11010,
+    0b00101,
+    0b01011,
+    0b10111,
+    0b00001,
+    0,
+
+    // ¿
+    0b00100,
+    0b00000,
+    0b00100,
+    0b01000,
+    0b10000,
+    0b10001,
+    0b01110,
+    0,
+
+    // 1100
+    // À
+    0b01000,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10001,
+    0b10001,
+    0,
+
+    // Á
+    0b00010,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10001,
+    0b10001,
+    0,
+
+    // Â
+    0b00100,
+    0b01010,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10001,
+    0b10001,
+    0,
+
+    // Ã
+    0b01101,
+    0b10110,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10001,
+    0b10001,
+    0,
+
+    // Ä
+    0b01010,
+    0b00000,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10001,
+    0b10001,
+    0,
+
+    // Å
+    0b00100,
+    0b01010,
+    0b00100,
+    0b01010,
+    0b10001,
+    0b11111,
+    0b10001,
+    0,
+
+    // Æ
+    0b01111,
+    0b10100,
+    0b10100,
+    0b11111,
+    0b10100,
+    0b10100,
+    0b10111,
+    0,
+
+    // Ç
+    0b01110,
+    0b10001,
+    0b10000,
+    0b10001,
+    0b01110,
+    0b00100,
+    0b01100,
+    0,
+
+    // È
+    0b01000,
+    0b00100,
+    0b11111,
+    0b10000,
+    0b11110,
+    0b10000,
+    0b11111,
+    0,
+
+    // É
+    0b00010,
+    0b00100,
+    0b11111,
+    0b10000,
+    0b11110,
+    0b10000,
+    0b11111,
+    0,
+
+    // Ê
+    0b00100,
+    0b01010,
+    0b11111,
+    0b10000,
+    0b11110,
+    0b10000,
+    0b11111,
+    0,
+
+    // Ë
+    0b01010,
+    0b00000,
+    0b11111,
+    0b10000,
+    0b11110,
+    0b10000,
+    0b11111,
+    0,
+
+    // Ì
+    0b01000,
+    0b00100,
+    0b01110,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // Í
+    0b00010,
+    0b00100,
+    0b01110,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // Î
+    0b00100,
+    0b01010,
+    0b01110,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // Ï
+    0b01010,
+    0b00000,
+    0b01110,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // 1101
+    // Ð
+    0b11100,
+    0b10010,
+    0b10001,
+    0b11101,
+    0b10001,
+    0b10010,
+    0b11100,
+    0,
+
+    // Ñ
+    0b01101,
+    0b10110,
+    0b10001,
+    0b11001,
+    0b10101,
+    0b10011,
+    0b10001,
+    0,
+
+    // Ò
+    0b01000,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Ó
+    0b00010,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Ô
+    0b00100,
+    0b01010,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Õ
+    0b01101,
+    0b10110,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Ö
+    0b01010,
+    0b00000,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // ×
+    0b00000,
+    0b10001,
+    0b01010,
+    0b00100,
+    0b01010,
+    0b10001,
+    0b00000,
+    0,
+
+    // Ø
+    0b01111,
+    0b10011,
+    0b10101,
+    0b10101,
+    0b10101,
+    0b11001,
+    0b11110,
+    0,
+
+    // Ù
+    0b01000,
+    0b00100,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Ú
+    0b00010,
+    0b00100,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Û
+    0b00100,
+    0b01010,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Ü
+    0b01010,
+    0b00000,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // Ý
+    0b00010,
+    0b00100,
+    0b10001,
+    0b01010,
+    0b00100,
+    0b00100,
+    0b00100,
+    0,
+
+    // Þ
+    0b10000,
+    0b11110,
+    0b10001,
+    0b10001,
+    0b11110,
+    0b10000,
+    0b10000,
+    0,
+
+    // ß
+    0b01100,
+    0b10010,
+    0b10010,
+    0b10100,
+    0b10010,
+    0b10010,
+    0b10100,
+    0,
+
+    // 1110
+    // à
+    0b01000,
+    0b00100,
+    0b01110,
+    0b00001,
+    0b01111,
+    0b10001,
+    0b01111,
+    0,
+
+    // á
+    0b00010,
+    0b00100,
+    0b01110,
+    0b00001,
+    0b01111,
+    0b10001,
+    0b01111,
+    0,
+
+    // â
+    0b00100,
+    0b01010,
+    0b01110,
+    0b00001,
+    0b01111,
+    0b10001,
+    0b01111,
+    0,
+
+    // ã
+    0b01101,
+    0b10110,
+    0b01110,
+    0b00001,
+    0b01111,
+    0b10001,
+    0b01111,
+    0,
+
+    // ä
+    0b01010,
+    0b00000,
+    0b01110,
+    0b00001,
+    0b01111,
+    0b10001,
+    0b01111,
+    0,
+
+    // å
+    0b00100,
+    0b01010,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b01111,
+    0,
+
+    // æ
+    0b00000,
+    0b00000,
+    0b11010,
+    0b00101,
+    0b01111,
+    0b10100,
+    0b01111,
+    0,
+
+    // ç
+    0b00000,
+    0b01110,
+    0b10000,
+    0b10000,
+    0b01110,
+    0b00100,
+    0b01100,
+    0,
+
+    // è
+    0b01000,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10000,
+    0b01110,
+    0,
+
+    // é
+    0b00010,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10000,
+    0b01110,
+    0,
+
+    // ê
+    0b00100,
+    0b01010,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10000,
+    0b01110,
+    0,
+
+    // ë
+    0b01010,
+    0b00000,
+    0b01110,
+    0b10001,
+    0b11111,
+    0b10000,
+    0b01110,
+    0,
+
+    // ì
+    0b01000,
+    0b00100,
+    0b01100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // í
+    0b00010,
+    0b00100,
+    0b01100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // î
+    0b00100,
+    0b01010,
+    0b01100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // ï
+    0b01010,
+    0b00000,
+    0b01100,
+    0b00100,
+    0b00100,
+    0b00100,
+    0b01110,
+    0,
+
+    // 1111
+    // ð
+    0b01100,
+    0b00010,
+    0b01111,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // ñ
+    0b01101,
+    0b10110,
+    0b10110,
+    0b11001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0,
+
+    // ò
+    0b01000,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // ó
+    0b00010,
+    0b00100,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // ô
+    0b00100,
+    0b01010,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // õ
+    0b01101,
+    0b10110,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // ö
+    0b01010,
+    0b00000,
+    0b01110,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b01110,
+    0,
+
+    // ÷
+    0b00000,
+    0b00100,
+    0b00000,
+    0b11111,
+    0b00000,
+    0b00100,
+    0b00000,
+    0,
+
+    // ø
+    0b00000,
+    0b00000,
+    0b01110,
+    0b10011,
+    0b10101,
+    0b11001,
+    0b01110,
+    0,
+
+    // ù
+    0b01000,
+    0b00100,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10011,
+    0b01101,
+    0,
+
+    // ú
+    0b00010,
+    0b00100,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10011,
+    0b01101,
+    0,
+
+    // û
+    0b00100,
+    0b01010,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10011,
+    0b01101,
+    0,
+
+    // ü
+    0b01010,
+    0b00000,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10011,
+    0b01101,
+    0,
+
+    // ý
+    0b00010,
+    0b00100,
+    0b10001,
+    0b10001,
+    0b01111,
+    0b00001,
+    0b01110,
+    0,
+
+    // þ
+    0b00000,
+    0b10000,
+    0b11110,
+    0b10001,
+    0b11110,
+    0b10000,
+    0b10000,
+    0,
+
+    // ÿ
+    0b01010,
+    0b00000,
+    0b10001,
+    0b10001,
+    0b01111,
+    0b00001,
+    0b01110,
+    0,
+];
+
+/// Rows 8 and 9 of the A00 characters with descenders, everything else in
+/// 0xE0-0xFF is blank below row 7.
+static A00_DESCENDERS: [(u8, [u8; 2]); 9] = [
+    (0xE2, [0b10000, 0b10000]), // β
+    (0xE4, [0b10000, 0b10000]), // μ
+    (0xE6, [0b10000, 0b10000]), // ρ
+    (0xE7, [0b00001, 0b01110]), // g
+    (0xEA, [0b10010, 0b01100]), // j
+    (0xF0, [0b10000, 0b10000]), // p
+    (0xF1, [0b00001, 0b00001]), // q
+    (0xF9, [0b00001, 0b01110]), // y
+    (0xFF, [0b11111, 0b11111]),
+];
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::cgrom::CharacterRom;
+
+    #[test]
+    fn test_a00() {
+        let rom = CharacterRom::a00();
+        assert_eq!(rom.row(b'A', 0), 0b01110);
+        assert_eq!(rom.row(b'A', 8), 0);
+        // Katakana ア
+        assert_eq!(rom.row(0xB1, 0), 0b11111);
+        // The 5x10 g has its descender in rows 7-9
+        assert_eq!(rom.row(0xE7, 7), 0b00001);
+        assert_eq!(rom.row(0xE7, 9), 0b01110);
+        assert_eq!(rom.row(0xE7, 10), 0);
+    }
+
+    #[test]
+    fn test_a02() {
+        let rom = CharacterRom::a02();
+        assert_eq!(rom.row(b'A', 0), 0b01110);
+        assert_eq!(rom.row(b'\\', 1), 0b10000);
+        // ▶ and the Cyrillic Б
+        assert_eq!(rom.row(0x10, 3), 0b11110);
+        assert_eq!(rom.row(0x80, 0), 0b11111);
+        // É
+        assert_eq!(rom.row(0xC9, 0), 0b00010);
+        assert_eq!(rom.row(0xC9, 2), 0b11111);
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let rom = CharacterRom::parse_text("base a02\n; a smiley\n$01\n.....\n.#.#.\n.....\n#...#\n.###.\n").unwrap();
+        assert_eq!(rom.row(0x01, 1), 0b01010);
+        assert_eq!(rom.row(0x01, 4), 0b01110);
+        assert_eq!(rom.row(0x01, 5), 0);
+        assert_eq!(rom.row(0x80, 0), CharacterRom::a02().row(0x80, 0));
+        assert_eq!(CharacterRom::parse_text("0x41\n#####").unwrap().row(b'A', 0), 0b11111);
+        assert_eq!(CharacterRom::parse_text("").unwrap().row(b'A', 0), 0);
+    }
+
+    #[test]
+    fn test_parse_text_errors() {
+        assert!(CharacterRom::parse_text("#####").is_err());
+        assert!(CharacterRom::parse_text("$41\n####").is_err());
+        assert!(CharacterRom::parse_text(&format!("$41\n{}", "#####\n".repeat(11))).is_err());
+        assert!(CharacterRom::parse_text("$141").is_err());
+        assert!(CharacterRom::parse_text("base a01").is_err());
+        assert!(CharacterRom::parse_text("$41\nbase a00").is_err());
+    }
+
+    #[test]
+    fn test_load_binary() {
+        let path = std::env::temp_dir().join("cpu6502_test_font.bin");
+        let mut bytes = vec![0; 4096];
+        bytes[0x41 * 16 + 9] = 0xFF;
+        fs::write(&path, &bytes).unwrap();
+        let rom = CharacterRom::parse(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(rom.row(b'A', 9), 0b11111);
+        assert_eq!(rom.row(b'A', 0), 0);
+        assert!(CharacterRom::parse("no/such/font").is_err());
+        assert_eq!(CharacterRom::parse("A02"), Ok(CharacterRom::a02()));
+    }
+}
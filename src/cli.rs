@@ -0,0 +1,306 @@
+use crate::cgrom::CharacterRom;
+use crate::clock;
+use crate::input::KeyMap;
+use crate::leds::SevenSegment;
+use crate::cpu::Variant;
+use crate::lcd_wiring::LcdWiring;
+use crate::loader::ImageFormat;
+use crate::memory_map::{ImageFit, parse_number};
+use crate::ps2::Ps2Wiring;
+use crate::serial::SerialTarget;
+
+pub const USAGE: &str = "\
+Usage: cpu6502 [options] [rom]
+
+Options:
+  --rom <file>            ROM image to load (default inputs/display.out)
+  --format <format>       Image format: raw, ihex, srec, o65 or prg
+                          (default: guessed from the file)
+  --load-addr <addr>      Address a raw image is loaded at (default: start of ROM)
+  --pad                   Allow images smaller than the ROM, the rest reads $FF
+  --mirror                Repeat smaller images through the ROM, like an 8K/16K
+                          EEPROM with unconnected address lines
+  --reset-vector <addr>   Start at <addr> instead of the reset vector or the
+                          entry point in the image
+  --memory-map <file>     Read the address decoding from <file>
+  --cpu <6502|wdc-pins>   CPU variant (default 6502). wdc-pins is the NMOS
+                          instruction set with the pin and flag behaviour of
+                          the WDC 65C02: RDY also halts writes and interrupts
+                          clear D. The 65C02 op-codes aren't emulated
+  --clock <freq>          Clock frequency, e.g. 1MHz, 500kHz, 10Hz or unlimited
+                          (default 1MHz)
+  --step                  Start with the clock halted: space steps one cycle,
+                          n one instruction, h toggles halt/run, +/- change
+                          the speed
+  --headless              Don't open any windows
+  --tui                   Full-screen frontend in the terminal instead of a
+                          window: the LCD, the port LEDs, the registers, a
+                          disassembly and memory, with keys to run, pause,
+                          step and reset. With --step it starts paused
+  --key <key>=<input>     Bind a key in the window to a button on the board: a
+                          port pin of the first VIA (pa0-pb7, pulled low while
+                          pressed), reset or nmi, e.g. --key f5=reset,up=pa0.
+                          May be repeated. Other keys go to the keyboard
+  --attach <list>         Peripherals attached to the VIA, comma separated
+                          (lcd, leds, ps2 or none, default lcd). leds shows
+                          the pins of both ports, lit when driven high
+  --lcd-wiring <wiring>   VIA pins the LCD is wired to: 8bit (D0-D7 on PB0-PB7,
+                          RS/RW/E on PA5-PA7), 4bit (D4-D7 on PB0-PB3, RS/RW/E
+                          on PB4-PB6) or a list like d4=pa0,...,d7=pa3,rs=pb5,
+                          rw=pb6,e=pb7 (default 8bit)
+  --lcd-size <cols>x<rows>
+                          Size of the LCD module: 16x1, 16x2, 20x2, 20x4, 40x2
+                          or anything else up to 80 characters (default 16x2)
+  --lcd-font <font>       Character ROM of the LCD: a00 (Japanese), a02
+                          (European) or a font file, either a 2K/4K ROM image
+                          or a text font (default a00)
+  --lcd-busy-warnings     Report writes to the LCD made while it is busy
+  --seven-segment <port>  A seven-segment display on pa or pb, segments a-g on
+                          bits 0-6 and the decimal point on bit 7. Add :ca for
+                          common anode. May be repeated, shown with the LEDs
+  --ps2-wiring <wiring>   VIA pins the PS/2 keyboard is wired to: parallel
+                          (bytes on port A, strobe on CA1), serial (clock on
+                          CA1, data on PA0) or a list like data=pb,strobe=cb1
+                          or clock=cb1,data=pb0, with an optional inhibit=ca2
+                          the 6502 holds low to stop the keyboard (default
+                          parallel)
+  --serial <target>       Where the ACIA's serial port goes: stdio, pty (a new
+                          pseudo-terminal), tcp:<port> or telnet:<port> on
+                          localhost (default stdio). RTS and CTS are the
+                          flow control, DCD and DSR show a connection
+  --trace                 Print the address and op-code of every instruction
+  --bus-monitor           Print every bus cycle
+  --help                  Show this help
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Peripheral {
+    Lcd,
+    Leds,
+    Ps2,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub rom: Option<String>,
+    pub format: Option<ImageFormat>,
+    pub load_address: Option<u16>,
+    pub fit: ImageFit,
+    pub reset_vector: Option<u16>,
+    pub memory_map: Option<String>,
+    pub cpu: Variant,
+    pub frequency: Option<u64>,
+    pub step: bool,
+    pub headless: bool,
+    pub tui: bool,
+    pub key_map: KeyMap,
+    pub peripherals: Vec<Peripheral>,
+    pub lcd_wiring: LcdWiring,
+    pub lcd_size: (u8, u8),
+    pub lcd_font: CharacterRom,
+    pub lcd_busy_warnings: bool,
+    pub seven_segments: Vec<SevenSegment>,
+    pub ps2_wiring: Ps2Wiring,
+    pub serial: SerialTarget,
+    pub trace: bool,
+    pub bus_monitor: bool,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            rom: None,
+            format: None,
+            load_address: None,
+            fit: ImageFit::Exact,
+            reset_vector: None,
+            memory_map: None,
+            cpu: Variant::Nmos6502,
+            frequency: Some(1_000_000),
+            step: false,
+            headless: false,
+            tui: false,
+            key_map: KeyMap::default(),
+            peripherals: vec![Peripheral::Lcd],
+            lcd_wiring: LcdWiring::eight_bit(),
+            lcd_size: (16, 2),
+            lcd_font: CharacterRom::a00(),
+            lcd_busy_warnings: false,
+            seven_segments: Vec::new(),
+            ps2_wiring: Ps2Wiring::parallel(),
+            serial: SerialTarget::Stdio,
+            trace: false,
+            bus_monitor: false,
+            help: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item=String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+            match arg.as_str() {
+                "--rom" => options.rom = Some(value(&arg)?),
+                "--format" => options.format = Some(ImageFormat::parse(&value(&arg)?)?),
+                "--load-addr" => options.load_address = Some(parse_address(&value(&arg)?)?),
+                "--pad" => options.fit = ImageFit::Pad,
+                "--mirror" => options.fit = ImageFit::Mirror,
+                "--reset-vector" => options.reset_vector = Some(parse_address(&value(&arg)?)?),
+                "--memory-map" => options.memory_map = Some(value(&arg)?),
+                "--cpu" => options.cpu = parse_variant(&value(&arg)?)?,
+                "--clock" => options.frequency = clock::parse_frequency(&value(&arg)?)?,
+                "--step" => options.step = true,
+                "--headless" => options.headless = true,
+                "--tui" => options.tui = true,
+                "--key" => options.key_map.bind(&value(&arg)?)?,
+                "--attach" => options.peripherals = parse_peripherals(&value(&arg)?)?,
+                "--lcd-wiring" => options.lcd_wiring = LcdWiring::parse(&value(&arg)?)?,
+                "--lcd-size" => options.lcd_size = parse_lcd_size(&value(&arg)?)?,
+                "--lcd-font" => options.lcd_font = CharacterRom::parse(&value(&arg)?)?,
+                "--lcd-busy-warnings" => options.lcd_busy_warnings = true,
+                "--seven-segment" => options.seven_segments.push(SevenSegment::parse(&value(&arg)?)?),
+                "--ps2-wiring" => options.ps2_wiring = Ps2Wiring::parse(&value(&arg)?)?,
+                "--serial" => options.serial = SerialTarget::parse(&value(&arg)?)?,
+                "--trace" => options.trace = true,
+                "--bus-monitor" => options.bus_monitor = true,
+                "--help" | "-h" => options.help = true,
+                _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+                _ if options.rom.is_none() => options.rom = Some(arg),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn attached(&self, peripheral: Peripheral) -> bool {
+        self.peripherals.contains(&peripheral)
+    }
+
+    /// The LEDs are shown when attached or when there is a display to show with them.
+    pub fn shows_leds(&self) -> bool {
+        self.attached(Peripheral::Leds) || !self.seven_segments.is_empty()
+    }
+}
+
+fn parse_address(value: &str) -> Result<u16, String> {
+    u16::try_from(parse_number(value)?).map_err(|_| format!("Address out of range: {}", value))
+}
+
+fn parse_variant(value: &str) -> Result<Variant, String> {
+    match value.to_lowercase().as_str() {
+        "6502" | "nmos" => Ok(Variant::Nmos6502),
+        "wdc-pins" => Ok(Variant::WdcPins),
+        "65c02" | "w65c02" | "cmos" => Err(String::from("The 65C02 instruction set isn't emulated, --cpu wdc-pins has its pin and flag behaviour")),
+        _ => Err(format!("Unknown CPU variant: {}", value)),
+    }
+}
+
+/// Columns and rows of a character LCD, like `20x4`. The HD44780 has 80
+/// characters of DDRAM, in rows of 1, 2 or 4.
+fn parse_lcd_size(value: &str) -> Result<(u8, u8), String> {
+    let error = || format!("Expected an LCD size like 16x2, got '{}'", value);
+    let (columns, rows) = value.to_lowercase().split_once('x')
+        .map(|(columns, rows)| (columns.trim().parse::<u8>(), rows.trim().parse::<u8>()))
+        .ok_or_else(error)?;
+    let (columns, rows) = (columns.map_err(|_| error())?, rows.map_err(|_| error())?);
+    if ![1, 2, 4].contains(&rows) || columns == 0 || columns as u32 * rows as u32 > 80 || (rows > 1 && columns > 40) {
+        return Err(format!("Unsupported LCD size: {}", value));
+    }
+    Ok((columns, rows))
+}
+
+fn parse_peripherals(value: &str) -> Result<Vec<Peripheral>, String> {
+    let mut peripherals = Vec::new();
+    for name in value.split(',') {
+        match name.trim() {
+            "lcd" => peripherals.push(Peripheral::Lcd),
+            "leds" => peripherals.push(Peripheral::Leds),
+            "ps2" => peripherals.push(Peripheral::Ps2),
+            "none" | "" => {}
+            _ => return Err(format!("Unknown peripheral: {}", name)),
+        }
+    }
+    Ok(peripherals)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cgrom::CharacterRom;
+    use crate::cli::{Options, Peripheral};
+    use crate::cpu::Variant;
+    use crate::input::KeyTarget;
+    use crate::leds::SevenSegment;
+    use crate::lcd_wiring::LcdWiring;
+    use crate::loader::ImageFormat;
+    use crate::memory_map::ImageFit;
+    use crate::ps2::Ps2Wiring;
+    use crate::serial::SerialTarget;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(parse(&[]), Ok(Options::default()));
+    }
+
+    #[test]
+    fn test_options() {
+        let options = parse(&["--format", "bin", "--load-addr", "$e000", "--mirror", "--reset-vector", "0xe000",
+            "--cpu", "wdc-pins", "--clock", "10hz", "--headless", "--key", "f5=reset", "--key", "up=pa0",
+            "--attach", "ps2,leds", "--seven-segment", "pb:ca", "--lcd-wiring", "4bit", "--ps2-wiring", "serial", "--serial", "telnet:2323",
+            "--lcd-size", "20x4", "--lcd-font", "a02", "--lcd-busy-warnings", "--trace",
+            "hello.bin"]).unwrap();
+        assert_eq!(options.rom, Some(String::from("hello.bin")));
+        assert_eq!(options.format, Some(ImageFormat::Raw));
+        assert_eq!(options.load_address, Some(0xe000));
+        assert_eq!(options.fit, ImageFit::Mirror);
+        assert_eq!(options.reset_vector, Some(0xe000));
+        assert_eq!(options.cpu, Variant::WdcPins);
+        assert_eq!(options.frequency, Some(10));
+        assert!(options.headless);
+        assert!(!options.tui);
+        assert!(parse(&["--tui"]).unwrap().tui);
+        assert_eq!(options.key_map.get("f5"), Some(KeyTarget::Reset));
+        assert!(options.key_map.get("up").is_some());
+        assert!(!options.attached(Peripheral::Lcd));
+        assert!(options.attached(Peripheral::Ps2));
+        assert!(options.shows_leds());
+        assert_eq!(options.seven_segments, vec![SevenSegment::parse("pb:ca").unwrap()]);
+        assert_eq!(options.ps2_wiring, Ps2Wiring::serial());
+        assert_eq!(options.serial, SerialTarget::Telnet(2323));
+        assert_eq!(options.lcd_wiring, LcdWiring::four_bit());
+        assert_eq!(options.lcd_size, (20, 4));
+        assert_eq!(options.lcd_font, CharacterRom::a02());
+        assert!(options.lcd_busy_warnings);
+        assert!(options.trace);
+        assert!(!options.bus_monitor);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(&["--rom"]).is_err());
+        assert!(parse(&["--load-addr", "$10000"]).is_err());
+        assert!(parse(&["--cpu", "z80"]).is_err());
+        assert!(parse(&["--cpu", "65c02"]).is_err());
+        assert!(parse(&["--attach", "printer"]).is_err());
+        assert!(parse(&["--lcd-wiring", "d4=pa0"]).is_err());
+        assert!(parse(&["--lcd-size", "40x4"]).is_err());
+        assert!(parse(&["--lcd-size", "16x3"]).is_err());
+        assert!(parse(&["--lcd-size", "16"]).is_err());
+        assert_eq!(parse(&["--lcd-size", "80x1"]).unwrap().lcd_size, (80, 1));
+        assert!(parse(&["--lcd-font", "no/such/font"]).is_err());
+        assert!(parse(&["--key", "f5"]).is_err());
+        assert!(parse(&["--ps2-wiring", "clock=ca1"]).is_err());
+        assert!(parse(&["--serial", "com1"]).is_err());
+        assert!(parse(&["--seven-segment", "pc"]).is_err());
+        assert!(parse(&["--seven-segment", "pa"]).unwrap().shows_leds());
+        assert!(parse(&["--fast"]).is_err());
+        assert!(parse(&["a.bin", "b.bin"]).is_err());
+    }
+}
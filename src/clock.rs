@@ -0,0 +1,392 @@
+use std::io;
+use std::io::Write;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const MIN_FREQUENCY: u64 = 1;
+pub const MAX_FREQUENCY: u64 = 14_000_000;
+
+// How often the emulated time is compared against the wall clock.
+const SYNC_INTERVAL: Duration = Duration::from_millis(2);
+// If the emulator falls further behind than this it gives up catching up,
+// otherwise it would run flat out for a while after e.g. a debugger pause.
+const MAX_LAG: Duration = Duration::from_millis(100);
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Positions of the speed knob, in Hz. Above the last one the clock is unlimited.
+const SPEED_STEPS: [u64; 23] = [
+    1, 2, 5, 10, 20, 50, 100, 200, 500,
+    1_000, 2_000, 5_000, 10_000, 20_000, 50_000, 100_000, 200_000, 500_000,
+    1_000_000, 2_000_000, 5_000_000, 10_000_000, MAX_FREQUENCY,
+];
+
+/// Control input for the clock, like the buttons on a breadboard clock module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockCommand {
+    /// Single-pulse one cycle while halted.
+    Step,
+    /// Run while halted until the next op-code fetch.
+    StepInstruction,
+    /// Switch between halted and continuous mode.
+    Toggle,
+    Faster,
+    Slower,
+}
+
+impl ClockCommand {
+    /// Maps a key pressed in the host terminal to a command.
+    pub fn from_key(key: u8) -> Option<ClockCommand> {
+        match key {
+            b' ' | b'\n' | b'\r' | b's' => Some(ClockCommand::Step),
+            b'n' => Some(ClockCommand::StepInstruction),
+            b'h' | b'r' => Some(ClockCommand::Toggle),
+            b'+' | b'=' => Some(ClockCommand::Faster),
+            b'-' | b'_' => Some(ClockCommand::Slower),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a clock frequency like `1000000`, `1MHz`, `500khz`, `2.5mhz` or `unlimited`.
+/// Returns `None` for an unlimited clock.
+pub fn parse_frequency(value: &str) -> Result<Option<u64>, String> {
+    let value = value.trim().to_lowercase();
+    if value == "unlimited" || value == "max" {
+        return Ok(None);
+    }
+    let (number, multiplier) = if let Some(number) = value.strip_suffix("mhz") {
+        (number, 1_000_000.0)
+    } else if let Some(number) = value.strip_suffix("khz") {
+        (number, 1_000.0)
+    } else if let Some(number) = value.strip_suffix("hz") {
+        (number, 1.0)
+    } else {
+        (value.as_str(), 1.0)
+    };
+    let frequency = number.trim().parse::<f64>()
+        .map_err(|_| format!("Invalid clock frequency: {}", value))?;
+    let frequency = (frequency * multiplier).round() as u64;
+    if !(MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency) {
+        return Err(format!("Clock frequency must be between {} Hz and {} Hz", MIN_FREQUENCY, MAX_FREQUENCY));
+    }
+    Ok(Some(frequency))
+}
+
+pub fn format_frequency(frequency: f64) -> String {
+    if frequency >= 1_000_000.0 {
+        format!("{:.3} MHz", frequency / 1_000_000.0)
+    } else if frequency >= 1_000.0 {
+        format!("{:.3} kHz", frequency / 1_000.0)
+    } else {
+        format!("{:.1} Hz", frequency)
+    }
+}
+
+/// Paces the emulated phi2 clock to a target frequency.
+///
+/// Sleeping every cycle is far too coarse at MHz speeds, so cycles are counted
+/// and the emulator is put to sleep in batches until the wall clock catches up.
+pub struct Clock {
+    frequency: Option<u64>,
+    batch: u64,
+    batch_cycles: u64,
+    start: Instant,
+    start_cycles: u64,
+    cycles: u64,
+    report_start: Instant,
+    report_cycles: u64,
+    effective_frequency: f64,
+    halted: bool,
+    to_next_instruction: bool, // Halted, but running up to the next SYNC
+    commands: Option<Receiver<ClockCommand>>,
+    pub quiet: bool, // Something else shows the status
+}
+
+impl Clock {
+    pub fn new(frequency: Option<u64>) -> Clock {
+        let now = Instant::now();
+        let mut clock = Clock {
+            frequency: None,
+            batch: 1,
+            batch_cycles: 0,
+            start: now,
+            start_cycles: 0,
+            cycles: 0,
+            report_start: now,
+            report_cycles: 0,
+            effective_frequency: 0.0,
+            halted: false,
+            to_next_instruction: false,
+            commands: None,
+            quiet: false,
+        };
+        clock.set_frequency(frequency);
+        clock
+    }
+
+    pub fn set_frequency(&mut self, frequency: Option<u64>) {
+        self.frequency = frequency.map(|f| f.clamp(MIN_FREQUENCY, MAX_FREQUENCY));
+        self.batch = match self.frequency {
+            Some(f) => (f * SYNC_INTERVAL.as_micros() as u64 / 1_000_000).max(1),
+            // Flat out the commands are still polled as often as at the top speed
+            None => MAX_FREQUENCY * SYNC_INTERVAL.as_micros() as u64 / 1_000_000,
+        };
+        self.resync();
+    }
+
+    /// Lets the clock be halted, single-stepped and sped up or down by `commands`.
+    /// With `halted` set the clock starts out stopped, waiting for a step.
+    pub fn set_controls(&mut self, commands: Receiver<ClockCommand>, halted: bool) {
+        self.commands = Some(commands);
+        self.halted = halted;
+    }
+
+    /// True when the clock is driven by hand rather than free running.
+    pub fn is_manual(&self) -> bool {
+        self.commands.is_some()
+    }
+
+    fn faster(&mut self) {
+        let frequency = match self.frequency {
+            Some(f) => SPEED_STEPS.iter().copied().find(|&step| step > f),
+            None => None,
+        };
+        self.set_frequency(frequency);
+    }
+
+    fn slower(&mut self) {
+        let frequency = match self.frequency {
+            Some(f) => SPEED_STEPS.iter().copied().rev().find(|&step| step < f).unwrap_or(MIN_FREQUENCY),
+            None => MAX_FREQUENCY,
+        };
+        self.set_frequency(Some(frequency));
+    }
+
+    fn apply(&mut self, command: ClockCommand) {
+        match command {
+            ClockCommand::Step | ClockCommand::StepInstruction => {}
+            ClockCommand::Toggle => {
+                self.halted = !self.halted;
+                self.resync();
+            }
+            ClockCommand::Faster => self.faster(),
+            ClockCommand::Slower => self.slower(),
+        }
+        if !matches!(command, ClockCommand::Step | ClockCommand::StepInstruction) {
+            self.print_status();
+        }
+    }
+
+    /// Blocks while the clock is halted, until a single pulse is requested or
+    /// the clock is switched back to continuous mode.
+    fn wait_for_pulse(&mut self) {
+        while self.halted {
+            let command = match &self.commands {
+                Some(commands) => commands.recv(),
+                None => return,
+            };
+            match command {
+                Ok(ClockCommand::Step) => return,
+                Ok(ClockCommand::StepInstruction) => {
+                    self.to_next_instruction = true;
+                    return;
+                }
+                Ok(command) => self.apply(command),
+                Err(_) => self.halted = false,
+            }
+        }
+    }
+
+    fn poll_commands(&mut self) {
+        while let Some(Ok(command)) = self.commands.as_ref().map(|commands| commands.try_recv()) {
+            self.apply(command);
+        }
+    }
+
+    /// Forgets the current reference point, e.g. after the clock has been halted.
+    pub fn resync(&mut self) {
+        self.start = Instant::now();
+        self.start_cycles = self.cycles;
+        self.batch_cycles = 0;
+    }
+
+    /// Counts one cycle, sleeping when the emulation is ahead of the wall clock.
+    /// `sync` is the CPU's SYNC output, high for an op-code fetch.
+    /// Returns true when a new effective frequency has been measured.
+    pub fn tick(&mut self, sync: bool) -> bool {
+        self.advance(1, sync)
+    }
+
+    /// The cycles that can run before the clock is looked at again: one at a
+    /// time while halted, else up to the next comparison with the wall clock.
+    pub fn cycles_until_sync(&self) -> u64 {
+        if self.halted {
+            1
+        } else {
+            self.batch - self.batch_cycles
+        }
+    }
+
+    /// Counts `cycles` that are about to run, no more than `cycles_until_sync`.
+    /// Like `tick`, with `sync` for the first of them.
+    pub fn advance(&mut self, cycles: u64, sync: bool) -> bool {
+        self.cycles += cycles;
+        if self.halted {
+            if self.to_next_instruction && !sync {
+                return false;
+            }
+            self.to_next_instruction = false;
+            self.wait_for_pulse();
+            return false;
+        }
+        self.batch_cycles += cycles;
+        if self.batch_cycles < self.batch {
+            return false;
+        }
+        self.batch_cycles = 0;
+        self.poll_commands();
+        if self.halted {
+            return false;
+        }
+        let now = Instant::now();
+        if let Some(frequency) = self.frequency {
+            let emulated = self.cycles - self.start_cycles;
+            let target = Duration::from_nanos(
+                (emulated as u128 * 1_000_000_000 / frequency as u128) as u64);
+            let elapsed = now - self.start;
+            if target > elapsed {
+                thread::sleep(target - elapsed);
+            } else if elapsed - target > MAX_LAG {
+                self.resync();
+            }
+        }
+        let since_report = now - self.report_start;
+        if since_report >= REPORT_INTERVAL {
+            self.effective_frequency = (self.cycles - self.report_cycles) as f64 / since_report.as_secs_f64();
+            self.report_start = now;
+            self.report_cycles = self.cycles;
+            return true;
+        }
+        false
+    }
+
+    /// Prints the measured speed on a status line.
+    pub fn print_status(&self) {
+        if self.quiet {
+            return;
+        }
+        let target = match self.frequency {
+            Some(f) => format_frequency(f as f64),
+            None => String::from("unlimited"),
+        };
+        if self.halted {
+            println!("Clock: halted (speed {})", target);
+        } else if self.is_manual() {
+            println!("Clock: {} (speed {})", format_frequency(self.effective_frequency), target);
+        } else {
+            print!("Clock: {} (target {})          \r", format_frequency(self.effective_frequency), target);
+            io::stdout().flush().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use crate::clock::{Clock, ClockCommand, MAX_FREQUENCY, parse_frequency};
+
+    #[test]
+    fn test_parse_frequency() {
+        assert_eq!(parse_frequency("1000000"), Ok(Some(1_000_000)));
+        assert_eq!(parse_frequency("1MHz"), Ok(Some(1_000_000)));
+        assert_eq!(parse_frequency("2.5mhz"), Ok(Some(2_500_000)));
+        assert_eq!(parse_frequency("500 kHz"), Ok(Some(500_000)));
+        assert_eq!(parse_frequency("1hz"), Ok(Some(1)));
+        assert_eq!(parse_frequency("unlimited"), Ok(None));
+        assert!(parse_frequency("0").is_err());
+        assert!(parse_frequency("15MHz").is_err());
+        assert!(parse_frequency("fast").is_err());
+    }
+
+    #[test]
+    fn test_clock_batches() {
+        let mut clock = Clock::new(Some(1_000_000));
+        assert_eq!(clock.batch, 2000);
+        for _ in 0..4000 {
+            clock.tick(false);
+        }
+        assert_eq!(clock.cycles, 4000);
+        assert!(clock.start.elapsed().as_micros() >= 4000);
+    }
+
+    #[test]
+    fn test_clock_advance() {
+        let mut clock = Clock::new(Some(1_000_000));
+        clock.advance(500, false);
+        assert_eq!(clock.cycles_until_sync(), 1500);
+        clock.advance(1500, false);
+        assert_eq!(clock.cycles_until_sync(), 2000);
+        assert_eq!(clock.cycles, 2000);
+        assert!(clock.start.elapsed().as_micros() >= 2000);
+    }
+
+    #[test]
+    fn test_clock_speed_knob() {
+        let (transmitt_command, receive_command) = mpsc::channel();
+        let mut clock = Clock::new(Some(1_000_000));
+        clock.set_controls(receive_command, false);
+        transmitt_command.send(ClockCommand::Faster).unwrap();
+        transmitt_command.send(ClockCommand::Faster).unwrap();
+        for _ in 0..2000 {
+            clock.tick(false);
+        }
+        assert_eq!(clock.frequency, Some(5_000_000));
+        transmitt_command.send(ClockCommand::Faster).unwrap();
+        transmitt_command.send(ClockCommand::Faster).unwrap();
+        transmitt_command.send(ClockCommand::Faster).unwrap();
+        for _ in 0..10000 {
+            clock.tick(false);
+        }
+        assert_eq!(clock.frequency, None);
+        transmitt_command.send(ClockCommand::Slower).unwrap();
+        clock.advance(clock.cycles_until_sync(), false);
+        assert_eq!(clock.frequency, Some(MAX_FREQUENCY));
+    }
+
+    #[test]
+    fn test_clock_single_step() {
+        let (transmitt_command, receive_command) = mpsc::channel();
+        let mut clock = Clock::new(Some(1_000_000));
+        clock.set_controls(receive_command, true);
+        transmitt_command.send(ClockCommand::Step).unwrap();
+        transmitt_command.send(ClockCommand::Step).unwrap();
+        clock.tick(false);
+        clock.tick(false);
+        assert_eq!(clock.cycles, 2);
+        assert!(clock.commands.as_ref().unwrap().try_recv().is_err());
+        transmitt_command.send(ClockCommand::Toggle).unwrap();
+        clock.tick(false);
+        assert!(!clock.halted);
+    }
+
+    #[test]
+    fn test_clock_step_instruction() {
+        let (transmitt_command, receive_command) = mpsc::channel();
+        let mut clock = Clock::new(Some(1_000_000));
+        clock.set_controls(receive_command, true);
+        transmitt_command.send(ClockCommand::StepInstruction).unwrap();
+        clock.tick(true);
+        // Runs through the rest of the instruction without waiting
+        for _ in 0..5 {
+            clock.tick(false);
+        }
+        assert!(clock.to_next_instruction);
+        assert_eq!(clock.cycles, 6);
+        transmitt_command.send(ClockCommand::Step).unwrap();
+        clock.tick(true);
+        assert!(!clock.to_next_instruction);
+        assert_eq!(clock.cycles, 7);
+        assert!(clock.commands.as_ref().unwrap().try_recv().is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 
 mod lda;
 mod ldx;
@@ -56,24 +57,131 @@ mod tsx;
 mod txa;
 mod txs;
 mod tya;
+mod interrupt;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    Nmos6502,
+    /// The NMOS instruction set with the pin and flag behaviour of the WDC
+    /// 65C02: RDY halts write cycles too, BRK and interrupts clear D. The
+    /// 65C02's own op-codes and addressing modes aren't emulated.
+    WdcPins,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct CpuInputPins {
-    pub(crate) data: u8,
-    pub(crate) irq: bool,  // trigger interupt on low
-    pub(crate) nmi: bool,  // trigger interupt on low
-    pub(crate) phi2: bool,  // clock
-    pub(crate) rdy: bool,  // pauses cpu on low
-    pub(crate) res: bool,  // reset CPU, hold low 2 cycles, then 7 cycles before reset complete
-    pub(crate) vdd: bool,
+    pub data: u8,
+    pub irq: bool,  // trigger interupt on low
+    pub nmi: bool,  // trigger interupt on low
+    pub phi2: bool,  // clock
+    pub rdy: bool,  // pauses cpu on low, NMOS only in read cycles
+    pub so: bool,  // sets overflow on falling edge
+    pub res: bool,  // reset CPU, hold low 2 cycles, then 7 cycles before reset complete
+    pub vdd: bool,
 }
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct CpuOutputPins {
-    pub(crate) addr: u16,
-    pub(crate) data: u8,
-    pub(crate) rwb: bool, // read or write address
-    pub(crate) sync: bool, // High during op-code read
+    pub addr: u16,
+    pub data: u8,
+    pub rwb: bool, // read or write address
+    pub sync: bool, // High during op-code read
+}
+
+/// The flags in P. B and bit 5 are no flip-flops in the CPU, they only exist
+/// in the byte pushed on the stack: bit 5 is always set, B is set when BRK or
+/// PHP pushed it and clear when IRQ or NMI did.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct StatusRegister {
+    pub n: bool,
+    pub v: bool,
+    pub d: bool,
+    pub i: bool,
+    pub z: bool,
+    pub c: bool,
+}
+
+impl StatusRegister {
+    pub const UNUSED: u8 = 0b0010_0000;
+
+    fn to_byte(self, b: bool) -> u8 {
+        let mut p = StatusRegister::UNUSED;
+        for (flag, bit) in [(self.n, CPU::FLAG_N), (self.v, CPU::FLAG_V), (b, CPU::FLAG_B), (self.d, CPU::FLAG_D),
+                            (self.i, CPU::FLAG_I), (self.z, CPU::FLAG_Z), (self.c, CPU::FLAG_C)] {
+            if flag {
+                p |= bit;
+            }
+        }
+        p
+    }
+
+    /// The byte BRK and PHP push, with B set.
+    pub fn pushed_by_instruction(self) -> u8 {
+        self.to_byte(true)
+    }
+
+    /// The byte IRQ and NMI push, with B clear.
+    pub fn pushed_by_interrupt(self) -> u8 {
+        self.to_byte(false)
+    }
+
+    /// The flags as `NV-BDIZC`, upper case when set. B reads clear.
+    pub fn flags(&self) -> String {
+        let p = self.pushed_by_interrupt();
+        "NV-BDIZC".chars().enumerate().map(|(i, flag)| {
+            if p & (0x80 >> i) > 0 { flag } else { flag.to_ascii_lowercase() }
+        }).collect()
+    }
+}
+
+/// Pulled by PLP and RTI, B and bit 5 are ignored.
+impl From<u8> for StatusRegister {
+    fn from(p: u8) -> StatusRegister {
+        StatusRegister {
+            n: p & CPU::FLAG_N > 0,
+            v: p & CPU::FLAG_V > 0,
+            d: p & CPU::FLAG_D > 0,
+            i: p & CPU::FLAG_I > 0,
+            z: p & CPU::FLAG_Z > 0,
+            c: p & CPU::FLAG_C > 0,
+        }
+    }
+}
+
+/// As it reads on the stack after an interrupt, bit 5 set and B clear.
+impl From<StatusRegister> for u8 {
+    fn from(p: StatusRegister) -> u8 {
+        p.pushed_by_interrupt()
+    }
+}
+
+/// The programmer-visible registers, for tests and debuggers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Registers {
+    pub pc: u16,
+    pub sp: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: StatusRegister,
+}
+
+impl Registers {
+    /// The flags as `NV-BDIZC`, upper case when set.
+    pub fn flags(&self) -> String {
+        self.p.flags()
+    }
+}
+
+/// Where a debugger meets the running CPU: the registers as they were at the
+/// last op-code fetch, and registers to load before the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DebugPort {
+    pub registers: Registers,
+    pub load: Option<Registers>,
 }
 
 pub struct CPU {
+    pub(crate) variant: Variant,
     pub(crate) pc: u16,
     pub(crate) sp: u8,
     pub(crate) a: u8,
@@ -83,16 +191,18 @@ pub struct CPU {
     pub(crate) z: bool,
     pub(crate) i: bool,
     pub(crate) d: bool,
-    pub(crate) b: bool,
     pub(crate) v: bool,
     pub(crate) n: bool,
+    pub(crate) nmi_pending: bool, // falling edge seen on nmi
     pub(crate) inp: CpuInputPins,
     pub(crate) out: CpuOutputPins,
+    pub(crate) debug_port: Option<Arc<Mutex<DebugPort>>>,
 }
 
 impl CPU {
     pub fn new() -> CPU {
         CPU {
+            variant: Variant::Nmos6502,
             pc: 0xFFFC,
             sp: 255,
             a: 0,
@@ -102,15 +212,16 @@ impl CPU {
             z: false,
             i: false,
             d: false,
-            b: false,
             v: false,
             n: false,
+            nmi_pending: false,
             inp: CpuInputPins {
                 data: 0,
-                irq: false,
-                nmi: false,
+                irq: true,
+                nmi: true,
                 phi2: false,
                 rdy: true,
+                so: true,
                 res: false,
                 vdd: true,
             },
@@ -120,6 +231,36 @@ impl CPU {
                 rwb: false,
                 sync: false,
             },
+            debug_port: None,
+        }
+    }
+
+    pub fn status(&self) -> StatusRegister {
+        StatusRegister { n: self.n, v: self.v, d: self.d, i: self.i, z: self.z, c: self.c }
+    }
+
+    pub fn set_status(&mut self, p: StatusRegister) {
+        (self.n, self.v, self.d, self.i, self.z, self.c) = (p.n, p.v, p.d, p.i, p.z, p.c);
+    }
+
+    /// A snapshot of the registers.
+    pub fn registers(&self) -> Registers {
+        Registers { pc: self.pc, sp: self.sp, a: self.a, x: self.x, y: self.y, p: self.status() }
+    }
+
+    pub fn set_registers(&mut self, registers: Registers) {
+        (self.pc, self.sp, self.a, self.x, self.y) = (registers.pc, registers.sp, registers.a, registers.x, registers.y);
+        self.set_status(registers.p);
+    }
+
+    // Takes the registers a debugger loaded and shows it the current ones
+    fn sync_debug_port(&mut self) {
+        if let Some(port) = self.debug_port.clone() {
+            let mut port = port.lock().unwrap();
+            if let Some(registers) = port.load.take() {
+                self.set_registers(registers);
+            }
+            port.registers = self.registers();
         }
     }
 
@@ -279,15 +420,32 @@ impl CPU {
     }
     pub fn run(&mut self, input: Receiver<CpuInputPins>, output: Sender<CpuOutputPins>) {
         let wait_for_tick = |cpu: &mut CPU| {
-            let cpu_inp: CpuInputPins = input.recv().unwrap();
-            cpu.inp.data =  cpu_inp.data;
-            cpu.inp.irq = cpu_inp.irq;
-            cpu.inp.nmi = cpu_inp.nmi;
-            cpu.inp.phi2 = cpu_inp.phi2;
-            cpu.inp.rdy = cpu_inp.rdy;
-            cpu.inp.res = cpu_inp.res;
-            cpu.inp.vdd =  cpu_inp.vdd;
-            return ;
+            loop {
+                let cpu_inp: CpuInputPins = input.recv().unwrap();
+                if cpu.inp.nmi && !cpu_inp.nmi {
+                    cpu.nmi_pending = true;
+                }
+                if cpu.inp.so && !cpu_inp.so {
+                    cpu.v = true;
+                }
+                cpu.inp.data =  cpu_inp.data;
+                cpu.inp.irq = cpu_inp.irq;
+                cpu.inp.nmi = cpu_inp.nmi;
+                cpu.inp.phi2 = cpu_inp.phi2;
+                cpu.inp.rdy = cpu_inp.rdy;
+                cpu.inp.so = cpu_inp.so;
+                cpu.inp.res = cpu_inp.res;
+                cpu.inp.vdd =  cpu_inp.vdd;
+                // RDY low stretches the cycle: the same pins go out again
+                // until it's high. The NMOS CPU only stops in read cycles.
+                if cpu.inp.rdy || !cpu.inp.res || !cpu.inp.vdd {
+                    return ;
+                }
+                if !cpu.out.rwb && cpu.variant == Variant::Nmos6502 {
+                    return ;
+                }
+                output.send(cpu.out).unwrap();
+            }
         };
         let set_pins = |cpu: &mut CPU| {
             let out = CpuOutputPins{
@@ -321,16 +479,25 @@ impl CPU {
                 self.y = 0;
                 self.c = false;
                 self.z = false;
-                self.i = false;
+                // Interrupts are disabled until the reset handler clears I
+                self.i = true;
                 self.d = false;
-                self.b = false;
                 self.v = false;
                 self.n = false;
+                self.nmi_pending = false;
+            }
+            if self.run_interrupt(&wait_for_tick, &set_pins) {
+                continue;
             }
+            self.sync_debug_port();
             self.out.sync = true;
             let inst = self.read_next_byte(&wait_for_tick, &set_pins);
             // println!("{:#06x}: {:#04x}", self.pc, inst);
             self.out.sync = false;
+            if self.debug_port.as_ref().is_some_and(|port| port.lock().unwrap().load.is_some()) {
+                // Loaded while the fetch was on the bus, fetch again from the new PC
+                continue;
+            }
             if self.run_lda(&wait_for_tick, &set_pins, inst) {
                 continue;
             } else if self.run_ldx(&wait_for_tick, &set_pins, inst) {
@@ -448,4 +615,103 @@ impl CPU {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use crate::cpu::{CPU, CpuInputPins, CpuOutputPins, Registers, StatusRegister, Variant};
+
+    // Runs the program at $0200 for the cycles, with RDY and SO for each
+    // cycle from `pins`. Returns the CPU and the addresses it put out.
+    fn run(mut cpu: CPU, program: &[u8], cycles: usize, pins: impl Fn(usize) -> (bool, bool)) -> (CPU, Vec<u16>) {
+        let mut mem: [u8; 0x10000] = [0; 0x10000];
+        mem[0x0200..0x0200 + program.len()].copy_from_slice(program);
+        cpu.pc = 0x0200;
+        cpu.inp.res = true;
+        let (transmitt_to_cpu, receive_on_cpu) = mpsc::channel();
+        let (transmitt_from_cpu, receive_from_cpu) = mpsc::channel();
+        let handler = thread::spawn(move || {
+            cpu.run(receive_on_cpu, transmitt_from_cpu);
+            cpu
+        });
+        let mut addresses = Vec::new();
+        for i in 0..cycles {
+            let output_pins: CpuOutputPins = receive_from_cpu.recv().unwrap();
+            addresses.push(output_pins.addr);
+            let data = if output_pins.rwb {
+                mem[output_pins.addr as usize]
+            } else {
+                mem[output_pins.addr as usize] = output_pins.data;
+                output_pins.data
+            };
+            let (rdy, so) = pins(i);
+            transmitt_to_cpu.send(CpuInputPins {
+                data,
+                irq: true,
+                nmi: true,
+                phi2: true,
+                rdy,
+                so,
+                res: true,
+                vdd: i + 1 < cycles,
+            }).unwrap();
+        }
+        // A CPU that isn't done panics instead of waiting for more cycles
+        drop(transmitt_to_cpu);
+        (handler.join().unwrap(), addresses)
+    }
+
+    #[test]
+    fn test_status_register() {
+        let p = StatusRegister { n: true, i: true, c: true, ..StatusRegister::default() };
+        assert_eq!(p.pushed_by_instruction(), 0b1011_0101);
+        assert_eq!(p.pushed_by_interrupt(), 0b1010_0101);
+        assert_eq!(u8::from(p), 0b1010_0101);
+        // B and bit 5 don't come back from the stack
+        assert_eq!(StatusRegister::from(0b1011_0101), p);
+        assert_eq!(StatusRegister::from(0b0011_0000), StatusRegister::default());
+        assert_eq!(StatusRegister::from(0xFF).pushed_by_interrupt(), 0xEF);
+        assert_eq!(p.flags(), "Nv-bdIzC");
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut cpu = CPU::new();
+        let registers = Registers { pc: 0x1234, sp: 0xF0, a: 1, x: 2, y: 3, p: StatusRegister::from(0b1100_0011) };
+        cpu.set_registers(registers);
+        assert_eq!(cpu.registers(), registers);
+        assert!(cpu.n && cpu.v && cpu.z && cpu.c);
+        assert!(!cpu.d && !cpu.i);
+        assert_eq!(cpu.status().flags(), "NV-bdiZC");
+    }
+
+    #[test]
+    fn test_rdy() {
+        // STA $10, LDA #$42 with RDY low in the write cycle and the two after
+        let program = [CPU::STA_ZP, 0x10, CPU::LDA_IM, 0x42];
+        let pins = |i| (!(2..5).contains(&i), true);
+        let mut cpu = CPU::new();
+        cpu.a = 0x17;
+        let (cpu, addresses) = run(cpu, &program, 7, pins);
+        assert_eq!(addresses, vec![0x0200, 0x0201, 0x0010, 0x0202, 0x0202, 0x0202, 0x0203]);
+        assert_eq!(cpu.a, 0x42);
+        // With the WDC pins it also stops in the write
+        let mut cpu = CPU::new();
+        cpu.variant = Variant::WdcPins;
+        cpu.a = 0x17;
+        let (cpu, addresses) = run(cpu, &program, 8, pins);
+        assert_eq!(addresses, vec![0x0200, 0x0201, 0x0010, 0x0010, 0x0010, 0x0010, 0x0202, 0x0203]);
+        assert_eq!(cpu.a, 0x42);
+    }
+
+    #[test]
+    fn test_so() {
+        let (cpu, _) = run(CPU::new(), &[CPU::NOP, CPU::NOP], 4, |i| (true, i != 1));
+        assert!(cpu.v);
+        // Only the falling edge sets V
+        let (cpu, _) = run(CPU::new(), &[CPU::NOP, CPU::CLV, CPU::NOP], 6, |i| (true, i == 0));
+        assert!(!cpu.v);
+    }
+}
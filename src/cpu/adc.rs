@@ -124,6 +124,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -169,6 +170,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -214,6 +216,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -259,6 +262,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -300,6 +304,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -344,6 +349,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -388,6 +394,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -432,6 +439,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -476,6 +484,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -520,6 +529,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -564,6 +574,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -608,6 +619,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -651,6 +663,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -89,6 +89,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -128,6 +129,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -167,6 +169,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -91,6 +91,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -131,6 +132,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -171,6 +173,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -212,6 +215,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -254,6 +258,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -296,6 +301,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -337,6 +343,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
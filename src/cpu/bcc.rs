@@ -64,6 +64,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -101,6 +102,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -138,6 +140,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -176,6 +179,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -61,6 +61,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -101,6 +102,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -142,6 +144,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -1,4 +1,4 @@
-use crate::cpu::CPU;
+use crate::cpu::{CPU, Variant};
 
 impl CPU {
     pub const BRK: u8 = 0x00;
@@ -8,18 +8,13 @@ impl CPU {
             let tmp = self.pc + 1;
             let lsb = (tmp & 0x00ff) as u8;
             let msb = ((tmp & 0xff00) >> 8) as u8;
-            let mut res: u8 = 0;
-            if self.c { res += CPU::FLAG_C; }
-            if self.z { res += CPU::FLAG_Z; }
-            if self.i { res += CPU::FLAG_I; }
-            if self.d { res += CPU::FLAG_D; }
-            if self.v { res += CPU::FLAG_V; }
-            if self.n { res += CPU::FLAG_N; }
+            let res = self.status().pushed_by_instruction();
 
             self.i = true;
-            // res += CPU::FLAG_I;
-            res += CPU::FLAG_B;
-            res += 0b0010_0000;
+            if self.variant == Variant::WdcPins {
+                // The WDC 65C02 clears decimal mode when taking an interrupt
+                self.d = false;
+            }
             self.push_to_stack(wait_for_tick, set_pins, msb);
             self.push_to_stack(wait_for_tick, set_pins, lsb);
             self.push_to_stack(wait_for_tick, set_pins, res);
@@ -39,7 +34,7 @@ impl CPU {
 mod tests {
     use std::sync::mpsc;
     use std::thread;
-    use crate::cpu::{CPU, CpuInputPins, CpuOutputPins};
+    use crate::cpu::{CPU, CpuInputPins, CpuOutputPins, Variant};
 
     #[test]
     fn test_brk_1() {
@@ -72,6 +67,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -84,4 +80,49 @@ mod tests {
         assert_eq!(cpu.i, true);
         assert_eq!(cpu.sp, 0xfc);
     }
+
+    #[test]
+    fn test_brk_wdc_pins() {
+        let mut cpu = CPU::new();
+        cpu.variant = Variant::WdcPins;
+        cpu.d = true;
+        let mut mem: [u8; 0x10000] = [0; 0x10000];
+        mem[0xFFFC] = CPU::BRK;
+        mem[0xFFFE] = 0x34;
+        mem[0xFFFF] = 0x45;
+        let cycles = 7;
+        let (transmitt_to_cpu, receive_on_cpu) = mpsc::channel();
+        let (transmitt_from_cpu, receive_from_cpu) = mpsc::channel();
+        let mut data: u8;
+        // Straight into the program, without the reset sequence
+        cpu.inp.res = true;
+
+        let handler = thread::spawn(move || {
+            cpu.run(receive_on_cpu, transmitt_from_cpu);
+            return cpu;
+        });
+        for i in 0..cycles {
+            let output_pins: CpuOutputPins = receive_from_cpu.recv().unwrap();
+            if output_pins.rwb {
+                data = mem[usize::from(output_pins.addr)];
+            } else {
+                data = output_pins.data;
+                mem[usize::from(output_pins.addr)] = data;
+            }
+            transmitt_to_cpu.send(CpuInputPins {
+                data: data,
+                irq: true,
+                nmi: true,
+                phi2: true,
+                rdy: true,
+                so: true,
+                res: true,
+                vdd: i == 0,
+            }).unwrap();
+        }
+        cpu = handler.join().unwrap();
+        assert_eq!(mem[0x01FD], CPU::FLAG_B | CPU::FLAG_D | 0b0010_0000);
+        assert_eq!(cpu.pc, 0x4534);
+        assert_eq!(cpu.d, false);
+    }
 }
@@ -0,0 +1,137 @@
+use crate::cpu::{CPU, Variant};
+
+impl CPU {
+    pub const NMI_VECTOR: u16 = 0xFFFA;
+    pub const IRQ_VECTOR: u16 = 0xFFFE;
+
+    // Checked between instructions, NMI on a falling edge and IRQ while low and I is clear
+    pub fn run_interrupt(&mut self, wait_for_tick: &dyn Fn(&mut CPU), set_pins: &dyn Fn(&mut CPU)) -> bool {
+        let vector = if self.nmi_pending {
+            self.nmi_pending = false;
+            CPU::NMI_VECTOR
+        } else if !self.inp.irq && !self.i {
+            CPU::IRQ_VECTOR
+        } else {
+            return false;
+        };
+        // The op-code fetch is thrown away and PC is not incremented
+        self.out.sync = true;
+        self.read_byte(wait_for_tick, set_pins, self.pc);
+        self.out.sync = false;
+        self.read_byte(wait_for_tick, set_pins, self.pc);
+        let lsb = (self.pc & 0x00ff) as u8;
+        let msb = ((self.pc & 0xff00) >> 8) as u8;
+        let res = self.status().pushed_by_interrupt();
+        self.push_to_stack(wait_for_tick, set_pins, msb);
+        self.push_to_stack(wait_for_tick, set_pins, lsb);
+        self.push_to_stack(wait_for_tick, set_pins, res);
+        self.i = true;
+        if self.variant == Variant::WdcPins {
+            self.d = false;
+        }
+        let vector_lsb = self.read_byte(wait_for_tick, set_pins, vector);
+        let vector_msb = self.read_byte(wait_for_tick, set_pins, vector + 1);
+        self.pc = ((vector_msb as u16) << 8) + (vector_lsb as u16);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use crate::cpu::{CPU, CpuInputPins, CpuOutputPins};
+
+    fn run(mut cpu: CPU, mem: &mut [u8; 0x10000], cycles: u32, irq: bool, nmi: bool) -> CPU {
+        let (transmitt_to_cpu, receive_on_cpu) = mpsc::channel();
+        let (transmitt_from_cpu, receive_from_cpu) = mpsc::channel();
+        let mut data: u8;
+        // Straight into the program, without the reset sequence
+        cpu.inp.res = true;
+
+        let handler = thread::spawn(move || {
+            cpu.run(receive_on_cpu, transmitt_from_cpu);
+            cpu
+        });
+        for i in 0..cycles {
+            let output_pins: CpuOutputPins = receive_from_cpu.recv().unwrap();
+            if output_pins.rwb {
+                data = mem[usize::from(output_pins.addr)];
+            } else {
+                data = output_pins.data;
+                mem[usize::from(output_pins.addr)] = data;
+            }
+            transmitt_to_cpu.send(CpuInputPins {
+                data,
+                irq,
+                nmi,
+                phi2: true,
+                rdy: true,
+                so: true,
+                res: true,
+                vdd: i == 0,
+            }).unwrap();
+        }
+        handler.join().unwrap()
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut cpu = CPU::new();
+        cpu.pc = 0x1234;
+        cpu.c = true;
+        cpu.inp.irq = false;
+        let mut mem: [u8; 0x10000] = [0; 0x10000];
+        mem[0xFFFE] = 0x00;
+        mem[0xFFFF] = 0x80;
+        let cpu = run(cpu, &mut mem, 7, false, true);
+        assert_eq!(mem[0x01FF], 0x12);
+        assert_eq!(mem[0x01FE], 0x34);
+        assert_eq!(mem[0x01FD], CPU::FLAG_C | 0b0010_0000);
+        assert_eq!(cpu.pc, 0x8000);
+        assert!(cpu.i);
+        assert_eq!(cpu.sp, 0xfc);
+    }
+
+    #[test]
+    fn test_irq_masked() {
+        let mut cpu = CPU::new();
+        cpu.i = true;
+        cpu.inp.irq = false;
+        let mut mem: [u8; 0x10000] = [0; 0x10000];
+        mem[0xFFFC] = CPU::NOP;
+        let cpu = run(cpu, &mut mem, 2, false, true);
+        assert_eq!(cpu.pc, 0xFFFD);
+        assert_eq!(cpu.sp, 0xff);
+    }
+
+    #[test]
+    fn test_nmi() {
+        let mut cpu = CPU::new();
+        cpu.pc = 0x1234;
+        cpu.i = true;
+        cpu.nmi_pending = true;
+        cpu.inp.nmi = false;
+        let mut mem: [u8; 0x10000] = [0; 0x10000];
+        mem[0xFFFA] = 0x00;
+        mem[0xFFFB] = 0x90;
+        let cpu = run(cpu, &mut mem, 7, true, false);
+        assert_eq!(mem[0x01FD], CPU::FLAG_I | 0b0010_0000);
+        assert_eq!(cpu.pc, 0x9000);
+        assert!(!cpu.nmi_pending);
+    }
+
+    #[test]
+    fn test_nmi_edge() {
+        // NMI going low during an instruction is taken once the instruction is done
+        let mut cpu = CPU::new();
+        cpu.pc = 0x1234;
+        let mut mem: [u8; 0x10000] = [0; 0x10000];
+        mem[0x1234] = CPU::NOP;
+        mem[0xFFFA] = 0x00;
+        mem[0xFFFB] = 0x90;
+        let cpu = run(cpu, &mut mem, 2, true, false);
+        assert_eq!(cpu.pc, 0x1235);
+        assert!(cpu.nmi_pending);
+    }
+}
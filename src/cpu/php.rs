@@ -5,15 +5,7 @@ impl CPU {
 
     pub fn run_php(&mut self, wait_for_tick: &dyn Fn(&mut CPU), set_pins: &dyn Fn(&mut CPU), inst: u8) -> bool {
         if inst == CPU::PHP {
-            let mut res: u8 = 0;
-            if self.c { res += CPU::FLAG_C; }
-            if self.z { res += CPU::FLAG_Z; }
-            if self.i { res += CPU::FLAG_I; }
-            if self.d { res += CPU::FLAG_D; }
-            res += CPU::FLAG_B;
-            if self.v { res += CPU::FLAG_V; }
-            if self.n { res += CPU::FLAG_N; }
-            res += 0b0010_0000;
+            let res = self.status().pushed_by_instruction();
             self.push_to_stack(wait_for_tick, set_pins, res);
             set_pins(self);
         wait_for_tick(self);
@@ -62,6 +54,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -1,4 +1,4 @@
-use crate::cpu::CPU;
+use crate::cpu::{CPU, StatusRegister};
 
 impl CPU {
     pub const PLP: u8 = 0x28;
@@ -6,13 +6,7 @@ impl CPU {
     pub fn run_plp(&mut self, wait_for_tick: &dyn Fn(&mut CPU), set_pins: &dyn Fn(&mut CPU), inst: u8) -> bool {
         if inst == CPU::PLP {
             let res = self.pop_from_stack(wait_for_tick, set_pins);
-            self.c = res & CPU::FLAG_C > 0;
-            self.z = res & CPU::FLAG_Z > 0;
-            self.i = res & CPU::FLAG_I > 0;
-            self.d = res & CPU::FLAG_D > 0;
-            // self.b = res & CPU::FLAG_B > 0;
-            self.v = res & CPU::FLAG_V > 0;
-            self.n = res & CPU::FLAG_N > 0;
+            self.set_status(StatusRegister::from(res));
             set_pins(self);
         wait_for_tick(self);
             set_pins(self);
@@ -60,6 +54,7 @@ mod tests {
                 nmi: true,
                 phi2: true,
                 rdy: true,
+                so: true,
                 res: true,
                 vdd: i == 0,
             }).unwrap();
@@ -68,7 +63,6 @@ mod tests {
         assert_eq!(cpu.c, true, "c reg");
         assert_eq!(cpu.n, true, "n reg");
         assert_eq!(cpu.z, true, "z reg");
-        assert_eq!(cpu.b, false, "b reg");
         assert_eq!(cpu.d, false, "d reg");
         assert_eq!(cpu.i, false, "i reg");
         assert_eq!(cpu.v, false, "v reg");
@@ -0,0 +1,201 @@
+use crate::disassembler::Mode::*;
+
+/// How an instruction finds its operand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndexedIndirect,
+    IndirectIndexed,
+    Relative,
+}
+
+impl Mode {
+    /// Bytes of the instruction, op-code included.
+    pub fn length(&self) -> u16 {
+        match self {
+            Implied | Accumulator => 1,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndexedIndirect | IndirectIndexed | Relative => 2,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 3,
+        }
+    }
+}
+
+// The documented NMOS op-codes, the rest are shown as data
+const OPCODES: [Option<(&str, Mode)>; 256] = [
+    // $0x
+    Some(("BRK", Implied)), Some(("ORA", IndexedIndirect)), None, None,
+    None, Some(("ORA", ZeroPage)), Some(("ASL", ZeroPage)), None,
+    Some(("PHP", Implied)), Some(("ORA", Immediate)), Some(("ASL", Accumulator)), None,
+    None, Some(("ORA", Absolute)), Some(("ASL", Absolute)), None,
+    // $1x
+    Some(("BPL", Relative)), Some(("ORA", IndirectIndexed)), None, None,
+    None, Some(("ORA", ZeroPageX)), Some(("ASL", ZeroPageX)), None,
+    Some(("CLC", Implied)), Some(("ORA", AbsoluteY)), None, None,
+    None, Some(("ORA", AbsoluteX)), Some(("ASL", AbsoluteX)), None,
+    // $2x
+    Some(("JSR", Absolute)), Some(("AND", IndexedIndirect)), None, None,
+    Some(("BIT", ZeroPage)), Some(("AND", ZeroPage)), Some(("ROL", ZeroPage)), None,
+    Some(("PLP", Implied)), Some(("AND", Immediate)), Some(("ROL", Accumulator)), None,
+    Some(("BIT", Absolute)), Some(("AND", Absolute)), Some(("ROL", Absolute)), None,
+    // $3x
+    Some(("BMI", Relative)), Some(("AND", IndirectIndexed)), None, None,
+    None, Some(("AND", ZeroPageX)), Some(("ROL", ZeroPageX)), None,
+    Some(("SEC", Implied)), Some(("AND", AbsoluteY)), None, None,
+    None, Some(("AND", AbsoluteX)), Some(("ROL", AbsoluteX)), None,
+    // $4x
+    Some(("RTI", Implied)), Some(("EOR", IndexedIndirect)), None, None,
+    None, Some(("EOR", ZeroPage)), Some(("LSR", ZeroPage)), None,
+    Some(("PHA", Implied)), Some(("EOR", Immediate)), Some(("LSR", Accumulator)), None,
+    Some(("JMP", Absolute)), Some(("EOR", Absolute)), Some(("LSR", Absolute)), None,
+    // $5x
+    Some(("BVC", Relative)), Some(("EOR", IndirectIndexed)), None, None,
+    None, Some(("EOR", ZeroPageX)), Some(("LSR", ZeroPageX)), None,
+    Some(("CLI", Implied)), Some(("EOR", AbsoluteY)), None, None,
+    None, Some(("EOR", AbsoluteX)), Some(("LSR", AbsoluteX)), None,
+    // $6x
+    Some(("RTS", Implied)), Some(("ADC", IndexedIndirect)), None, None,
+    None, Some(("ADC", ZeroPage)), Some(("ROR", ZeroPage)), None,
+    Some(("PLA", Implied)), Some(("ADC", Immediate)), Some(("ROR", Accumulator)), None,
+    Some(("JMP", Indirect)), Some(("ADC", Absolute)), Some(("ROR", Absolute)), None,
+    // $7x
+    Some(("BVS", Relative)), Some(("ADC", IndirectIndexed)), None, None,
+    None, Some(("ADC", ZeroPageX)), Some(("ROR", ZeroPageX)), None,
+    Some(("SEI", Implied)), Some(("ADC", AbsoluteY)), None, None,
+    None, Some(("ADC", AbsoluteX)), Some(("ROR", AbsoluteX)), None,
+    // $8x
+    None, Some(("STA", IndexedIndirect)), None, None,
+    Some(("STY", ZeroPage)), Some(("STA", ZeroPage)), Some(("STX", ZeroPage)), None,
+    Some(("DEY", Implied)), None, Some(("TXA", Implied)), None,
+    Some(("STY", Absolute)), Some(("STA", Absolute)), Some(("STX", Absolute)), None,
+    // $9x
+    Some(("BCC", Relative)), Some(("STA", IndirectIndexed)), None, None,
+    Some(("STY", ZeroPageX)), Some(("STA", ZeroPageX)), Some(("STX", ZeroPageY)), None,
+    Some(("TYA", Implied)), Some(("STA", AbsoluteY)), Some(("TXS", Implied)), None,
+    None, Some(("STA", AbsoluteX)), None, None,
+    // $Ax
+    Some(("LDY", Immediate)), Some(("LDA", IndexedIndirect)), Some(("LDX", Immediate)), None,
+    Some(("LDY", ZeroPage)), Some(("LDA", ZeroPage)), Some(("LDX", ZeroPage)), None,
+    Some(("TAY", Implied)), Some(("LDA", Immediate)), Some(("TAX", Implied)), None,
+    Some(("LDY", Absolute)), Some(("LDA", Absolute)), Some(("LDX", Absolute)), None,
+    // $Bx
+    Some(("BCS", Relative)), Some(("LDA", IndirectIndexed)), None, None,
+    Some(("LDY", ZeroPageX)), Some(("LDA", ZeroPageX)), Some(("LDX", ZeroPageY)), None,
+    Some(("CLV", Implied)), Some(("LDA", AbsoluteY)), Some(("TSX", Implied)), None,
+    Some(("LDY", AbsoluteX)), Some(("LDA", AbsoluteX)), Some(("LDX", AbsoluteY)), None,
+    // $Cx
+    Some(("CPY", Immediate)), Some(("CMP", IndexedIndirect)), None, None,
+    Some(("CPY", ZeroPage)), Some(("CMP", ZeroPage)), Some(("DEC", ZeroPage)), None,
+    Some(("INY", Implied)), Some(("CMP", Immediate)), Some(("DEX", Implied)), None,
+    Some(("CPY", Absolute)), Some(("CMP", Absolute)), Some(("DEC", Absolute)), None,
+    // $Dx
+    Some(("BNE", Relative)), Some(("CMP", IndirectIndexed)), None, None,
+    None, Some(("CMP", ZeroPageX)), Some(("DEC", ZeroPageX)), None,
+    Some(("CLD", Implied)), Some(("CMP", AbsoluteY)), None, None,
+    None, Some(("CMP", AbsoluteX)), Some(("DEC", AbsoluteX)), None,
+    // $Ex
+    Some(("CPX", Immediate)), Some(("SBC", IndexedIndirect)), None, None,
+    Some(("CPX", ZeroPage)), Some(("SBC", ZeroPage)), Some(("INC", ZeroPage)), None,
+    Some(("INX", Implied)), Some(("SBC", Immediate)), Some(("NOP", Implied)), None,
+    Some(("CPX", Absolute)), Some(("SBC", Absolute)), Some(("INC", Absolute)), None,
+    // $Fx
+    Some(("BEQ", Relative)), Some(("SBC", IndirectIndexed)), None, None,
+    None, Some(("SBC", ZeroPageX)), Some(("INC", ZeroPageX)), None,
+    Some(("SED", Implied)), Some(("SBC", AbsoluteY)), None, None,
+    None, Some(("SBC", AbsoluteX)), Some(("INC", AbsoluteX)), None,
+];
+
+/// One disassembled instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub(crate) address: u16,
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) text: String,
+}
+
+impl Instruction {
+    /// Where the next instruction starts.
+    pub fn next(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len().max(1) as u16)
+    }
+}
+
+/// Disassembles the instruction at `address`. `read` gives the memory that
+/// can be looked at without side effects, an instruction running into
+/// anything else is shown as far as it could be read.
+pub fn disassemble(address: u16, read: impl Fn(u16) -> Option<u8>) -> Instruction {
+    let Some(opcode) = read(address) else {
+        return Instruction { address, bytes: Vec::new(), text: String::from("??") };
+    };
+    let Some((mnemonic, mode)) = OPCODES[opcode as usize] else {
+        return Instruction { address, bytes: vec![opcode], text: format!(".byte ${:02X}", opcode) };
+    };
+    let mut bytes = vec![opcode];
+    for offset in 1..mode.length() {
+        match read(address.wrapping_add(offset)) {
+            Some(byte) => bytes.push(byte),
+            None => return Instruction { address, bytes, text: format!("{} ??", mnemonic) },
+        }
+    }
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+    let operand = match mode {
+        Implied => String::new(),
+        Accumulator => String::from("A"),
+        Immediate => format!("#${:02X}", byte),
+        ZeroPage => format!("${:02X}", byte),
+        ZeroPageX => format!("${:02X},X", byte),
+        ZeroPageY => format!("${:02X},Y", byte),
+        Absolute => format!("${:04X}", word),
+        AbsoluteX => format!("${:04X},X", word),
+        AbsoluteY => format!("${:04X},Y", word),
+        Indirect => format!("(${:04X})", word),
+        IndexedIndirect => format!("(${:02X},X)", byte),
+        IndirectIndexed => format!("(${:02X}),Y", byte),
+        Relative => format!("${:04X}", address.wrapping_add(2).wrapping_add(byte as i8 as u16)),
+    };
+    let text = if operand.is_empty() { mnemonic.to_string() } else { format!("{} {}", mnemonic, operand) };
+    Instruction { address, bytes, text }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disassembler::disassemble;
+
+    fn memory(bytes: &[u8]) -> impl Fn(u16) -> Option<u8> + '_ {
+        |addr| bytes.get(addr.wrapping_sub(0x8000) as usize).copied()
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = [0xA9, 0xFF, 0x8D, 0x02, 0x60, 0x6A, 0xD0, 0xFB, 0xB1, 0x10, 0x6C, 0xFC, 0xFF, 0x02];
+        let read = memory(&program);
+        let mut address = 0x8000;
+        let mut listing = Vec::new();
+        while (address as usize) < 0x8000 + program.len() {
+            let instruction = disassemble(address, &read);
+            address = instruction.next();
+            listing.push(instruction.text);
+        }
+        assert_eq!(listing, ["LDA #$FF", "STA $6002", "ROR A", "BNE $8003", "LDA ($10),Y", "JMP ($FFFC)", ".byte $02"]);
+        assert_eq!(disassemble(0x8002, &read).bytes, vec![0x8D, 0x02, 0x60]);
+    }
+
+    #[test]
+    fn test_unreadable() {
+        let read = memory(&[0xEA, 0x4C, 0x00]);
+        assert_eq!(disassemble(0x7FFF, &read).text, "??");
+        assert_eq!(disassemble(0x7FFF, &read).next(), 0x8000);
+        let jmp = disassemble(0x8001, &read);
+        assert_eq!(jmp.text, "JMP ??");
+        assert_eq!(jmp.bytes, vec![0x4C, 0x00]);
+    }
+}
@@ -1,22 +1,35 @@
 use std::mem::discriminant;
+use std::sync::Mutex;
 use std::sync::mpsc::{Receiver, Sender};
 
-extern crate sdl2;
-
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use crate::cgrom::{CGROM};
+use crate::cgrom::CharacterRom;
 pub struct DisplayInputPins {
     pub(crate) data: Option<u8>,
     pub(crate) rs: Option<bool>,
     pub(crate) rwb: Option<bool>,
     pub(crate) e: Option<bool>,
+    pub(crate) cycle: Option<u64>, // CPU cycles since power on
 }
 
 pub struct DisplayOutputPins {
-    pub(crate) data: u8,
+    pub(crate) data: Option<u8>, // Driven on the data lines after a read strobe
+    pub(crate) next_event: Option<u64>, // When the controller changes by itself
+}
+
+/// What the liquid crystal is driven to show, one dot per entry, row by row.
+/// Characters are 5 dots wide and `char_height` dots high, cursor line included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub(crate) columns: u8,
+    pub(crate) rows: u8,
+    pub(crate) char_height: u8,
+    pub(crate) dots: Vec<bool>,
+}
+
+impl Frame {
+    pub fn width(&self) -> usize {
+        self.columns as usize * 5
+    }
 }
 
 pub struct Display {
@@ -40,10 +53,39 @@ pub struct Display {
     pub(crate) s_c: bool,
     pub(crate) r_l: bool,
     pub(crate) cg_dd: bool, // cg_ram (True) or ddram (False)
+    pub(crate) d_l: bool, // 8-bit (True) or 4-bit (False) interface
+    pub(crate) n: bool, // 2 lines (True) or 1 line (False)
+    pub(crate) f: bool, // 5x10 dots (True) or 5x8 dots (False)
+    pub(crate) nibble: Option<u8>, // First half of a 4-bit transfer
+    pub(crate) display_shift: u8, // DDRAM column shown in the leftmost position
+
+    pub(crate) cycle: u64,
+    pub frequency: u64, // CPU clock, used to turn execution times into cycles
+    pub(crate) busy_until: u64,
+    pub busy_warnings: bool, // Report writes made while busy
+
+    pub(crate) data_register: u8, // DR, holds the last byte read or written
+    pub(crate) stale: bool, // DR not loaded since the address was set
+
+    pub columns: u8, // Size of the module
+    pub rows: u8,
+    pub rom: CharacterRom, // Character generator ROM, A00 unless the module says otherwise
 }
 
 
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
 impl Display {
+    // Execution times with the typical 270 kHz oscillator
+    pub const EXECUTION_TIME_US: u64 = 37;
+    pub const CLEAR_TIME_US: u64 = 1520;
+    // The cursor character blinks at about 1.9 Hz
+    pub const BLINK_INTERVAL_US: u64 = 263_000;
+
     pub fn new() -> Display {
         Display {
             data: 0,
@@ -61,168 +103,674 @@ impl Display {
             s_c: false,
             r_l: false,
             cg_dd: false,
+            // Powers up in 8-bit mode
+            d_l: true,
+            n: false,
+            f: false,
+            nibble: None,
+            display_shift: 0,
+            cycle: 0,
+            frequency: 1_000_000,
+            busy_until: 0,
+            busy_warnings: false,
+            data_register: 0,
+            stale: false,
+            columns: 16,
+            rows: 2,
+            rom: CharacterRom::a00(),
         }
     }
-    pub fn run(&mut self, input: Receiver<DisplayInputPins>, output: Sender<DisplayOutputPins>) {
-        let wait_for_tick = |disp: &mut Display| {
-            let mut prev_e = disp.e;
-            while !(prev_e == false && disp.e == true) {
-                prev_e = disp.e;
-                let disp_inp: DisplayInputPins = input.recv().unwrap();
-                disp.data = disp_inp.data.unwrap_or(disp.data);
-                disp.rs = disp_inp.rs.unwrap_or(disp.rs);
-                disp.rwb = disp_inp.rwb.unwrap_or(disp.rwb);
-                disp.e = disp_inp.e.unwrap_or(disp.e);
+    /// Length of a DDRAM line, 40 characters in 2-line mode and 80 in 1-line mode.
+    fn line_length(&self) -> u8 {
+        if self.n { 40 } else { 80 }
+    }
+    /// DDRAM address shown at a position on the screen, taking the display shift into account.
+    /// Rows 2 and 3 of a 4-row module continue lines 1 and 2, so 20x4 starts its
+    /// rows at 0x00, 0x40, 0x14 and 0x54.
+    fn ddram_address(&self, row: u8, column: u8) -> u8 {
+        if !self.n {
+            return (row * self.columns + column + self.display_shift) % self.line_length();
+        }
+        let base_addr: u8 = if row % 2 == 1 { 0b0100_0000 } else { 0 };
+        let column = column + (row / 2) * self.columns;
+        base_addr + (column + self.display_shift) % self.line_length()
+    }
+    /// The 5x10 dots font only exists in 1-line mode, F is ignored with 2 lines.
+    fn tall_font(&self) -> bool {
+        !self.n && self.f
+    }
+    /// Rows of dots in a character, including the cursor line.
+    fn char_height(&self) -> u8 {
+        if self.tall_font() { 11 } else { 8 }
+    }
+    /// Rows of the module that are driven, only the first in 1-line mode.
+    fn visible_rows(&self) -> u8 {
+        if self.n { self.rows } else { 1 }
+    }
+    /// Dots of one row of a character, the 5 low bits with the leftmost dot in bit 4.
+    fn glyph_row(&self, code: u8, row: u8) -> u8 {
+        let row = row as usize;
+        if code <= 0x0F {
+            // CGRAM, 8 characters of 8 rows or 4 of 16 rows with the 5x10 font, mirrored at 0x08
+            if self.tall_font() {
+                self.cgram[((code as usize & 0b110) << 3) | row]
+            } else if row < 8 {
+                self.cgram[((code as usize & 0b111) << 3) | row]
+            } else {
+                0
             }
+        } else {
+            self.rom.row(code, row as u8)
+        }
+    }
+    /// Moves the address counter one step. DDRAM addresses wrap from the end of
+    /// one line to the start of the next, 0x27 to 0x40 and 0x67 to 0x00 in 2-line mode.
+    fn step_address(&mut self, increment: bool) {
+        if self.cg_dd {
+            let step = if increment { 1 } else { 0b0011_1111 };
+            self.address_counter = self.address_counter.wrapping_add(step) & 0b0011_1111;
             return;
-        };
-        let set_pins = |data: u8| {
-            let out = DisplayOutputPins {
-                data: data,
+        }
+        let line_length = self.line_length();
+        if !self.n {
+            self.address_counter = if increment {
+                (self.address_counter + 1) % line_length
+            } else {
+                (self.address_counter + line_length - 1) % line_length
             };
-            output.send(out).unwrap();
             return;
+        }
+        // Position counting through both lines, 0 to 79
+        let position = if self.address_counter >= 0b0100_0000 {
+            self.address_counter - 0b0100_0000 + line_length
+        } else {
+            self.address_counter.min(line_length - 1)
         };
-        let sdl_context = sdl2::init().expect("");
-        let video_subsystem = sdl_context.video().expect("");
-        let pixel_size = 4;
-        let side_border = 6;
-        let top_bottom_border = 2;
-        let char_space = 1;
-        let dot_space = 1;
-        let window_width = (16 * (5 + char_space) + 2 * side_border - char_space) * (pixel_size + dot_space);
-        let window_height = ((2 * (8 + char_space)) + (top_bottom_border * 2)) * (pixel_size + dot_space);
-        let window = video_subsystem
-            .window(
-                "Display",
-                window_width,
-                window_height,
-            )
-            .position_centered()
-            .opengl()
-            .build()
-            .map_err(|e| e.to_string()).expect("");
-
-        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string()).expect("");
-        canvas.set_draw_color(Color::RGB(0x2b, 0x4b, 0xe5));
-        canvas.clear();
-        canvas.present();
-        loop {
-            wait_for_tick(self);
-            // #2b4be5
-            canvas.set_draw_color(Color::RGB(0x2b, 0x4b, 0xe5));
-            canvas.clear();
-            canvas.set_draw_color(Color::RGB(0x21, 0x21, 0x23));
-
-            if !self.rs {
-                // Instructions
-                if self.rwb == false && self.data & 0b1111_1111 == 1 {
-                    // Clear display
-                    // Clears entire display and sets DDRAM address 0 in address counter.
-                    self.drram = [0b00100000; 128];
-                    self.address_counter = 0;
-                    self.cg_dd = false;
-                } else if self.rwb == false && self.data & 0b1111_1110 == 0b0000_0010 {
-                    // Return home
-                    // Sets DDRAM address 0 in address counter.
-                    // Also returns display from being shifted to original position.
-                    // DDRAM contents remain unchanged.
-                    self.address_counter = 0;
-                } else if self.rwb == false && self.data & 0b1111_1100 == 0b0000_0100 {
-                    // Entry mode set
-                    // Sets cursor move direction and specifies display shift.
-                    // These operations are performed during data write and read.
-                    // TODO: Not implemented
-                    self.i_d = self.data & 0b0000_0010 == 0b0000_0010;
-                    self.shift = self.data & 0b0000_0001 == 0b0000_0001;
-                } else if self.rwb == false && self.data & 0b1111_1000 == 0b0000_1000 {
-                    // Display on/off control
-                    // Sets entire display (D) on/off,
-                    // cursor on/off (C), and blinking of cursor position character (B).
-                    self.display = self.data & 0b0000_0100 == 0b0000_0100;
-                    self.cursor = self.data & 0b0000_0010 == 0b0000_0010;
-                    // TODO: Blink is not implemented
-                    self.blink = self.data & 0b0000_0001 == 0b0000_0001;
-                } else if self.rwb == false && self.data & 0b1111_0000 == 0b0001_0000 {
-                    // Cursor or display shift
-                    // Moves cursor and shifts display without changing DDRAM contents.
-                    // TODO: Not implemented
-                    self.s_c = self.data & 0b0000_1000 == 0b0000_1000;
-                    self.r_l = self.data & 0b0000_0100 == 0b0000_0100;
-                } else if self.rwb == false && self.data & 0b1110_0000 == 0b0010_0000 {
-                    // Function set
-                    // Sets interface data length (DL), number of display lines (N), and character font (F).
-                    let d_l = self.data & 0b0001_0000 == 0b0001_0000;
-                    let n = self.data & 0b0000_1000 == 0b0000_1000;
-                    let f = self.data & 0b0000_0100 == 0b0000_0100;
-                    if !(d_l && n && !f) {
-                        panic!("Not implemented")
-                    }
-                } else if self.rwb == false && self.data & 0b1100_0000 == 0b0100_0000 {
-                    // Set CGRAM address
-                    // CGRAM data is sent and received after this setting.
-                    self.address_counter = self.data & 0b0011_1111;
-                    self.cg_dd = true;
-                } else if self.rwb == false && self.data & 0b1000_0000 == 0b1000_0000 {
-                    // Set DDRAM address
-                    // DDRAM data is sent and received after this setting.
-                    self.address_counter = self.data & 0b0111_1111;
-                    self.cg_dd = false;
-                } else if self.rwb == true {
-                    // Read busy flag & address
-                    // Reads busy flag (BF) indicating internal operation
-                    // is being performed and reads address counter contents.
-                    // TODO: Not really correctly implemented.
-                    set_pins(self.address_counter & 0b0111_1111)
+        let position = if increment { (position + 1) % 80 } else { (position + 79) % 80 };
+        self.address_counter = if position >= line_length {
+            0b0100_0000 + position - line_length
+        } else {
+            position
+        };
+    }
+    /// Shifts the whole display one character, left moves the contents to the left.
+    fn shift_display(&mut self, left: bool) {
+        let line_length = self.line_length();
+        self.display_shift = if left {
+            (self.display_shift + 1) % line_length
+        } else {
+            (self.display_shift + line_length - 1) % line_length
+        };
+    }
+    fn cycles(&self, microseconds: u64) -> u64 {
+        microseconds * self.frequency / 1_000_000
+    }
+    /// True while the last instruction is still executing.
+    pub fn busy(&self) -> bool {
+        self.cycle < self.busy_until
+    }
+    /// The next cycle the controller changes without being strobed: the busy
+    /// flag clears or a blinking cursor character toggles. `None` when idle.
+    pub fn next_event(&self) -> Option<u64> {
+        let busy = self.busy().then_some(self.busy_until);
+        let interval = self.cycles(Display::BLINK_INTERVAL_US).max(1);
+        let blink = (self.display && self.blink).then(|| (self.cycle / interval + 1) * interval);
+        busy.into_iter().chain(blink).min()
+    }
+    /// Whether a blinking cursor character is currently shown as a solid block.
+    fn blink_on(&self) -> bool {
+        (self.cycle / self.cycles(Display::BLINK_INTERVAL_US).max(1)).is_multiple_of(2)
+    }
+    fn ram_at_address(&self) -> u8 {
+        if self.cg_dd {
+            self.cgram[self.address_counter as usize]
+        } else {
+            self.drram[self.address_counter as usize]
+        }
+    }
+    /// Busy flag and address counter, or data from DDRAM/CGRAM when RS is set.
+    fn read(&mut self) -> u8 {
+        if !self.rs {
+            // Read busy flag & address
+            // Reads busy flag (BF) indicating internal operation
+            // is being performed and reads address counter contents.
+            let busy_flag = if self.busy() { 0b1000_0000 } else { 0 };
+            busy_flag | (self.address_counter & 0b0111_1111)
+        } else {
+            // Reads data from DDRAM or CGRAM.
+            // The byte comes from the data register, which isn't loaded by setting
+            // an address, so the first read after that returns stale data.
+            self.busy_until = self.cycle + self.cycles(Display::EXECUTION_TIME_US);
+            if self.stale {
+                self.stale = false;
+                let value = self.data_register;
+                self.data_register = self.ram_at_address();
+                return value;
+            }
+            self.data_register = self.ram_at_address();
+            self.step_address(self.i_d);
+            self.data_register
+        }
+    }
+    /// Data put on the bus for a read strobe. In 4-bit mode a read takes two
+    /// strobes, the high nibble and then the low nibble, both on D4-D7.
+    fn read_strobe(&mut self) -> u8 {
+        if self.d_l {
+            return self.read();
+        }
+        match self.nibble.take() {
+            Some(value) => value << 4,
+            None => {
+                let value = self.read();
+                self.nibble = Some(value);
+                value & 0b1111_0000
+            }
+        }
+    }
+    /// The byte written by a write strobe. In 4-bit mode the high nibble is
+    /// held until the low nibble arrives on D4-D7 with the next strobe.
+    fn write_strobe(&mut self) -> Option<u8> {
+        if self.d_l {
+            return Some(self.data);
+        }
+        match self.nibble.take() {
+            Some(high) => Some(high | (self.data >> 4)),
+            None => {
+                self.nibble = Some(self.data & 0b1111_0000);
+                None
+            }
+        }
+    }
+    fn write(&mut self) {
+        if self.busy() && self.busy_warnings {
+            eprintln!("LCD: {} {:#04x} written while busy for {} more cycles",
+                      if self.rs { "data" } else { "instruction" }, self.data, self.busy_until - self.cycle);
+        }
+        // Clear display and return home take longer than everything else
+        let execution_time = if !self.rs && (1..=3).contains(&self.data) {
+            Display::CLEAR_TIME_US
+        } else {
+            Display::EXECUTION_TIME_US
+        };
+        self.busy_until = self.cycle + self.cycles(execution_time);
+        if !self.rs {
+            // Instructions
+            if self.data & 0b1111_1111 == 1 {
+                // Clear display
+                // Clears entire display and sets DDRAM address 0 in address counter.
+                // Also sets I/D to increment and returns the display from being shifted.
+                self.drram = [0b00100000; 128];
+                self.address_counter = 0;
+                self.cg_dd = false;
+                self.i_d = true;
+                self.display_shift = 0;
+            } else if self.data & 0b1111_1110 == 0b0000_0010 {
+                // Return home
+                // Sets DDRAM address 0 in address counter.
+                // Also returns display from being shifted to original position.
+                // DDRAM contents remain unchanged.
+                self.address_counter = 0;
+                self.cg_dd = false;
+                self.display_shift = 0;
+            } else if self.data & 0b1111_1100 == 0b0000_0100 {
+                // Entry mode set
+                // Sets cursor move direction and specifies display shift.
+                // These operations are performed during data write and read.
+                self.i_d = self.data & 0b0000_0010 == 0b0000_0010;
+                self.shift = self.data & 0b0000_0001 == 0b0000_0001;
+            } else if self.data & 0b1111_1000 == 0b0000_1000 {
+                // Display on/off control
+                // Sets entire display (D) on/off,
+                // cursor on/off (C), and blinking of cursor position character (B).
+                self.display = self.data & 0b0000_0100 == 0b0000_0100;
+                self.cursor = self.data & 0b0000_0010 == 0b0000_0010;
+                self.blink = self.data & 0b0000_0001 == 0b0000_0001;
+            } else if self.data & 0b1111_0000 == 0b0001_0000 {
+                // Cursor or display shift
+                // Moves cursor and shifts display without changing DDRAM contents.
+                self.s_c = self.data & 0b0000_1000 == 0b0000_1000;
+                self.r_l = self.data & 0b0000_0100 == 0b0000_0100;
+                if self.s_c {
+                    self.shift_display(!self.r_l);
+                } else {
+                    self.step_address(self.r_l);
                 }
+            } else if self.data & 0b1110_0000 == 0b0010_0000 {
+                // Function set
+                // Sets interface data length (DL), number of display lines (N), and character font (F).
+                // The 4-bit switch is usually sent as a single nibble while still
+                // in 8-bit mode, with N and F read from unconnected D0-D3.
+                self.d_l = self.data & 0b0001_0000 == 0b0001_0000;
+                self.n = self.data & 0b0000_1000 == 0b0000_1000;
+                self.f = self.data & 0b0000_0100 == 0b0000_0100;
+                self.nibble = None;
+            } else if self.data & 0b1100_0000 == 0b0100_0000 {
+                // Set CGRAM address
+                // CGRAM data is sent and received after this setting.
+                self.address_counter = self.data & 0b0011_1111;
+                self.cg_dd = true;
+                self.stale = true;
+            } else if self.data & 0b1000_0000 == 0b1000_0000 {
+                // Set DDRAM address
+                // DDRAM data is sent and received after this setting.
+                self.address_counter = self.data & 0b0111_1111;
+                self.cg_dd = false;
+                self.stale = true;
+            }
+        } else {
+            // Writes data into DDRAM or CGRAM, through the data register.
+            self.data_register = self.data;
+            if self.cg_dd {
+                // CGRAM
+                self.cgram[self.address_counter as usize] = self.data;
+                self.step_address(self.i_d);
             } else {
-                if self.rwb == false {
-                    // Writes data into DDRAM or CGRAM.
-                    if self.cg_dd {
-                        // CGRAM
-                        self.cgram[self.address_counter as usize] = self.data;
-                        self.address_counter += 1;
+                // DDRAM
+                self.drram[self.address_counter as usize] = self.data;
+                self.step_address(self.i_d);
+                if self.shift {
+                    // The display moves with the cursor, left when incrementing
+                    self.shift_display(self.i_d);
+                }
+            }
+        }
+    }
+    /// The character codes on the visible rows, empty while the display is off.
+    pub fn text(&self) -> Vec<Vec<u8>> {
+        if !self.display {
+            return Vec::new();
+        }
+        (0..self.visible_rows())
+            .map(|row| (0..self.columns).map(|char| self.drram[self.ddram_address(row, char) as usize]).collect())
+            .collect()
+    }
+    /// The dots of every visible character, with the cursor and blinking block.
+    pub fn frame(&self) -> Frame {
+        let char_height = self.char_height();
+        let rows = self.visible_rows();
+        let width = self.columns as usize * 5;
+        let mut dots = vec![false; width * rows as usize * char_height as usize];
+        if !self.display {
+            return Frame { columns: self.columns, rows, char_height, dots };
+        }
+        let blink_on = self.blink_on();
+        for row in 0..rows {
+            for char in 0..self.columns {
+                let ddram_addr = self.ddram_address(row, char);
+                let cg_addr = self.drram[ddram_addr as usize];
+                let at_cursor = self.address_counter == ddram_addr && !self.cg_dd;
+                for c_y in 0..char_height {
+                    let block = self.blink && blink_on;
+                    let underline = self.cursor && c_y == char_height - 1;
+                    let c_row = if at_cursor && (block || underline) {
+                        0b1_1111
                     } else {
-                        // DDRAM
-                        self.drram[self.address_counter as usize] = self.data;
-                        self.address_counter += 1;
+                        self.glyph_row(cg_addr, c_y)
+                    };
+                    let y = (row * char_height + c_y) as usize;
+                    for c_x in 0..5 {
+                        dots[y * width + char as usize * 5 + c_x] = c_row & (0b1_0000 >> c_x) > 0;
                     }
-                } else {
-                    // Reads data from DDRAM or CGRAM.
-                    // TODO: Not implemented, but cant get the physical display to read correct.
                 }
             }
-            // Update display
-            for row in 0..2 {
-                for char in 0..16 {
-                    let base_addr:u8 = if row == 1 { 0b01000000 } else { 0 };
-                    let cg_addr = self.drram[(base_addr | char) as usize];
-                    for c_y in 0..8 {
-                        let addr: usize = (((cg_addr as u16) * 8) + c_y) as usize;
-                        let c_row = if cg_addr <= 0x0F { self.cgram[addr] } else { CGROM[addr] };
-                        for c_x in 0..5 {
-                            // println!("{:#010b}", row);
-                            let x = (side_border + (char as u32 * (5 + char_space) ) + c_x) * (pixel_size + dot_space);
-                            let y = (top_bottom_border + (row * (8 + char_space)) + c_y as u32) * (pixel_size + dot_space);
-                            if self.display && ((self.cursor && c_y == 7 && self.address_counter == (base_addr | char) && !self.cg_dd) ||
-                               (c_x == 0 && 0b00010000 & c_row > 0) ||
-                               (c_x == 1 && 0b00001000 & c_row > 0) ||
-                               (c_x == 2 && 0b00000100 & c_row > 0) ||
-                               (c_x == 3 && 0b00000010 & c_row > 0) ||
-                               (c_x == 4 && 0b00000001 & c_row > 0)) {
-                                canvas.set_draw_color(Color::RGB(0x21, 0x21, 0x23));
-                                canvas.fill_rect(Rect::new(x as i32, y as i32, pixel_size, pixel_size)).expect("");
-                            } else {
-                                // #2640b9
-                                canvas.set_draw_color(Color::RGB(0x26, 0x46, 0xe0));
-                                // canvas.set_draw_color(Color::RGB(0x26, 0x40, 0xb9));
-                                canvas.fill_rect(Rect::new(x as i32, y as i32, pixel_size, pixel_size)).expect("");
-                            }
-                        }
-                    }
+        }
+        Frame { columns: self.columns, rows, char_height, dots }
+    }
+    /// The controller, follows the pins and answers every change with what it
+    /// drives and when it changes next. It only updates the shared state, the
+    /// renderer draws it at its own pace.
+    pub fn run(display: &Mutex<Display>, input: Receiver<DisplayInputPins>, output: Sender<DisplayOutputPins>) {
+        for disp_inp in input {
+            let mut disp = display.lock().unwrap();
+            let prev_e = disp.e;
+            disp.data = disp_inp.data.unwrap_or(disp.data);
+            disp.rs = disp_inp.rs.unwrap_or(disp.rs);
+            disp.rwb = disp_inp.rwb.unwrap_or(disp.rwb);
+            disp.e = disp_inp.e.unwrap_or(disp.e);
+            disp.cycle = disp_inp.cycle.unwrap_or(disp.cycle);
+            let mut data = None;
+            if !prev_e && disp.e {
+                if disp.rwb {
+                    data = Some(disp.read_strobe());
+                } else if let Some(data) = disp.write_strobe() {
+                    disp.data = data;
+                    disp.write();
                 }
             }
-            canvas.present();
+            let next_event = disp.next_event();
+            drop(disp);
+            output.send(DisplayOutputPins { data, next_event }).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::display::Display;
+
+    // One E strobe with the given data lines, returns what the LCD drives on a read
+    fn strobe(disp: &mut Display, rs: bool, rwb: bool, data: u8) -> Option<u8> {
+        disp.rs = rs;
+        disp.rwb = rwb;
+        disp.data = data;
+        if rwb {
+            Some(disp.read_strobe())
+        } else {
+            if let Some(data) = disp.write_strobe() {
+                disp.data = data;
+                disp.write();
+            }
+            None
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_four_bit_init() {
+        let mut disp = Display::new();
+        // Function set x3, then 4-bit as a single nibble in 8-bit mode
+        for _ in 0..3 {
+            strobe(&mut disp, false, false, 0x30);
+            assert!(disp.d_l);
+        }
+        strobe(&mut disp, false, false, 0x20);
+        assert!(!disp.d_l);
+        // 4-bit, 2 lines, 5x8 as two nibbles
+        strobe(&mut disp, false, false, 0x20);
+        assert!(!disp.n);
+        strobe(&mut disp, false, false, 0x80);
+        assert!(disp.n);
+        assert!(!disp.d_l);
+        // Write "A" to DDRAM
+        strobe(&mut disp, true, false, 0x40);
+        strobe(&mut disp, true, false, 0x10);
+        assert_eq!(disp.drram[0], 0x41);
+        assert_eq!(disp.address_counter, 1);
+    }
+
+    #[test]
+    fn test_four_bit_read() {
+        let mut disp = Display::new();
+        disp.d_l = false;
+        disp.address_counter = 0x4A;
+        assert_eq!(strobe(&mut disp, false, true, 0), Some(0x40));
+        assert_eq!(strobe(&mut disp, false, true, 0), Some(0xA0));
+        // And back to the high nibble
+        assert_eq!(strobe(&mut disp, false, true, 0), Some(0x40));
+    }
+
+    #[test]
+    fn test_back_to_eight_bit() {
+        let mut disp = Display::new();
+        disp.d_l = false;
+        strobe(&mut disp, false, false, 0x30);
+        strobe(&mut disp, false, false, 0x80);
+        assert!(disp.d_l);
+        assert!(disp.n);
+        strobe(&mut disp, false, false, 0x0E);
+        assert!(disp.display);
+        assert!(disp.cursor);
+    }
+
+    fn eight_bit_two_lines() -> Display {
+        let mut disp = Display::new();
+        strobe(&mut disp, false, false, 0x38);
+        disp
+    }
+
+    #[test]
+    fn test_address_wrap() {
+        let mut disp = eight_bit_two_lines();
+        strobe(&mut disp, false, false, 0x80 | 0x27);
+        strobe(&mut disp, true, false, b'a');
+        assert_eq!(disp.address_counter, 0x40);
+        strobe(&mut disp, false, false, 0x80 | 0x67);
+        strobe(&mut disp, true, false, b'b');
+        assert_eq!(disp.address_counter, 0x00);
+        // Decrementing wraps the other way
+        strobe(&mut disp, false, false, 0x04);
+        strobe(&mut disp, true, false, b'c');
+        assert_eq!(disp.address_counter, 0x67);
+        assert_eq!(disp.drram[0x27], b'a');
+        assert_eq!(disp.drram[0x67], b'b');
+        assert_eq!(disp.drram[0x00], b'c');
+        // 1-line mode counts through 0x00-0x4F
+        disp.n = false;
+        disp.address_counter = 0x4F;
+        strobe(&mut disp, false, false, 0x14);
+        assert_eq!(disp.address_counter, 0x00);
+    }
+
+    #[test]
+    fn test_cursor_shift() {
+        let mut disp = eight_bit_two_lines();
+        strobe(&mut disp, false, false, 0x14);
+        assert_eq!(disp.address_counter, 0x01);
+        strobe(&mut disp, false, false, 0x10);
+        strobe(&mut disp, false, false, 0x10);
+        assert_eq!(disp.address_counter, 0x67);
+        assert_eq!(disp.display_shift, 0);
+    }
+
+    #[test]
+    fn test_display_shift() {
+        let mut disp = eight_bit_two_lines();
+        // Shift left, the contents move left
+        strobe(&mut disp, false, false, 0x18);
+        assert_eq!(disp.display_shift, 1);
+        assert_eq!(disp.ddram_address(0, 0), 0x01);
+        assert_eq!(disp.ddram_address(1, 15), 0x50);
+        assert_eq!(disp.ddram_address(0, 39), 0x00);
+        // Shift right twice wraps around the 40 columns
+        strobe(&mut disp, false, false, 0x1C);
+        strobe(&mut disp, false, false, 0x1C);
+        assert_eq!(disp.display_shift, 39);
+        assert_eq!(disp.ddram_address(0, 0), 0x27);
+        assert_eq!(disp.ddram_address(1, 1), 0x40);
+        assert_eq!(disp.address_counter, 0);
+        // Return home
+        strobe(&mut disp, false, false, 0x02);
+        assert_eq!(disp.display_shift, 0);
+    }
+
+    #[test]
+    fn test_entry_mode_shift() {
+        let mut disp = eight_bit_two_lines();
+        // Increment and shift, the cursor stays put on screen
+        strobe(&mut disp, false, false, 0x07);
+        strobe(&mut disp, true, false, b'a');
+        strobe(&mut disp, true, false, b'b');
+        assert_eq!(disp.address_counter, 2);
+        assert_eq!(disp.display_shift, 2);
+        // CGRAM writes don't shift
+        strobe(&mut disp, false, false, 0x40);
+        strobe(&mut disp, true, false, 0x1F);
+        assert_eq!(disp.display_shift, 2);
+        assert_eq!(disp.address_counter, 1);
+        // Decrement and shift moves the display right
+        strobe(&mut disp, false, false, 0x80);
+        strobe(&mut disp, false, false, 0x05);
+        strobe(&mut disp, true, false, b'c');
+        assert_eq!(disp.address_counter, 0x67);
+        assert_eq!(disp.display_shift, 1);
+        // Clear display resets the shift and I/D
+        strobe(&mut disp, false, false, 0x01);
+        assert_eq!(disp.display_shift, 0);
+        assert!(disp.i_d);
+    }
+
+    #[test]
+    fn test_busy_flag() {
+        let mut disp = eight_bit_two_lines();
+        disp.cycle = 100;
+        strobe(&mut disp, true, false, b'a');
+        disp.cycle = 136;
+        assert_eq!(strobe(&mut disp, false, true, 0), Some(0b1000_0001));
+        disp.cycle = 137;
+        assert_eq!(strobe(&mut disp, false, true, 0), Some(0b0000_0001));
+        // Clear display takes 1.52 ms
+        strobe(&mut disp, false, false, 0x01);
+        disp.cycle = 137 + 1519;
+        assert!(disp.busy());
+        disp.cycle = 137 + 1520;
+        assert!(!disp.busy());
+        // Counted in cycles of the CPU clock
+        disp.frequency = 2_000_000;
+        strobe(&mut disp, false, false, 0x0F);
+        disp.cycle += 73;
+        assert!(disp.busy());
+        disp.cycle += 1;
+        assert!(!disp.busy());
+    }
+
+    #[test]
+    fn test_blink() {
+        let mut disp = Display::new();
+        disp.cycle = 0;
+        assert!(disp.blink_on());
+        disp.cycle = 262_999;
+        assert!(disp.blink_on());
+        disp.cycle = 263_000;
+        assert!(!disp.blink_on());
+        disp.cycle = 526_000;
+        assert!(disp.blink_on());
+    }
+
+    #[test]
+    fn test_next_event() {
+        let mut disp = eight_bit_two_lines();
+        disp.cycle = 100;
+        assert_eq!(disp.next_event(), None);
+        strobe(&mut disp, true, false, b'a');
+        assert_eq!(disp.next_event(), Some(137));
+        // Display on with a blinking cursor
+        disp.cycle = 137;
+        strobe(&mut disp, false, false, 0x0F);
+        assert_eq!(disp.next_event(), Some(174));
+        disp.cycle = 174;
+        assert_eq!(disp.next_event(), Some(263_000));
+        disp.cycle = 263_000;
+        assert_eq!(disp.next_event(), Some(526_000));
+    }
+
+    #[test]
+    fn test_ddram_read() {
+        let mut disp = eight_bit_two_lines();
+        for c in b"hello" {
+            strobe(&mut disp, true, false, *c);
+        }
+        strobe(&mut disp, false, false, 0x80 | 0x01);
+        // The first read after setting the address is the last byte written
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(b'o'));
+        assert_eq!(disp.address_counter, 0x01);
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(b'e'));
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(b'l'));
+        assert_eq!(disp.address_counter, 0x03);
+        // Decrementing, and reads don't shift the display
+        strobe(&mut disp, false, false, 0x05);
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(b'l'));
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(b'l'));
+        assert_eq!(disp.address_counter, 0x01);
+        assert_eq!(disp.display_shift, 0);
+    }
+
+    #[test]
+    fn test_cgram_read() {
+        let mut disp = eight_bit_two_lines();
+        strobe(&mut disp, false, false, 0x40 | 0x3F);
+        strobe(&mut disp, true, false, 0x15);
+        strobe(&mut disp, true, false, 0x0A);
+        strobe(&mut disp, false, false, 0x40 | 0x3F);
+        strobe(&mut disp, true, true, 0);
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(0x15));
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(0x0A));
+        assert_eq!(disp.address_counter, 0x01);
+    }
+
+    #[test]
+    fn test_four_bit_data_read() {
+        let mut disp = Display::new();
+        disp.d_l = false;
+        disp.drram[0] = 0x5A;
+        disp.drram[1] = 0xC3;
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(0x50));
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(0xA0));
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(0xC0));
+        assert_eq!(strobe(&mut disp, true, true, 0), Some(0x30));
+        assert_eq!(disp.address_counter, 2);
+    }
+
+    #[test]
+    fn test_twenty_by_four() {
+        let mut disp = eight_bit_two_lines();
+        disp.columns = 20;
+        disp.rows = 4;
+        assert_eq!(disp.ddram_address(0, 0), 0x00);
+        assert_eq!(disp.ddram_address(1, 0), 0x40);
+        assert_eq!(disp.ddram_address(2, 0), 0x14);
+        assert_eq!(disp.ddram_address(3, 19), 0x67);
+        assert_eq!(disp.visible_rows(), 4);
+        // Shifting moves rows 3 and 4 along with lines 1 and 2
+        strobe(&mut disp, false, false, 0x18);
+        assert_eq!(disp.ddram_address(0, 19), 0x14);
+        assert_eq!(disp.ddram_address(2, 19), 0x00);
+    }
+
+    #[test]
+    fn test_one_line() {
+        let mut disp = Display::new();
+        disp.columns = 16;
+        disp.rows = 1;
+        strobe(&mut disp, false, false, 0x30);
+        assert_eq!(disp.visible_rows(), 1);
+        assert_eq!(disp.ddram_address(0, 15), 0x0F);
+        strobe(&mut disp, false, false, 0x1C);
+        assert_eq!(disp.ddram_address(0, 0), 0x4F);
+    }
+
+    #[test]
+    fn test_five_by_ten_font() {
+        let mut disp = Display::new();
+        strobe(&mut disp, false, false, 0x34);
+        assert!(disp.tall_font());
+        assert_eq!(disp.char_height(), 11);
+        // 4 CGRAM characters of 16 rows, character 1 is the same as 0
+        strobe(&mut disp, false, false, 0x40 | 0x10 | 9);
+        strobe(&mut disp, true, false, 0x1F);
+        assert_eq!(disp.glyph_row(0x02, 9), 0x1F);
+        assert_eq!(disp.glyph_row(0x03, 9), 0x1F);
+        assert_eq!(disp.glyph_row(0x0A, 9), 0x1F);
+        assert_eq!(disp.glyph_row(0x00, 9), 0);
+        // F is ignored in 2-line mode
+        strobe(&mut disp, false, false, 0x3C);
+        assert!(!disp.tall_font());
+        assert_eq!(disp.char_height(), 8);
+    }
+
+    #[test]
+    fn test_cgram_mirror() {
+        let mut disp = eight_bit_two_lines();
+        strobe(&mut disp, false, false, 0x40 | 0x08 | 3);
+        strobe(&mut disp, true, false, 0x11);
+        assert_eq!(disp.glyph_row(0x01, 3), 0x11);
+        assert_eq!(disp.glyph_row(0x09, 3), 0x11);
+    }
+
+    #[test]
+    fn test_frame() {
+        let mut disp = eight_bit_two_lines();
+        strobe(&mut disp, true, false, b'T');
+        assert!(disp.frame().dots.iter().all(|dot| !dot));
+        // Display on with the underline cursor, which is now on the second character
+        strobe(&mut disp, false, false, 0x0E);
+        let frame = disp.frame();
+        assert_eq!((frame.width(), frame.dots.len()), (80, 80 * 16));
+        let dot = |x: usize, y: usize| frame.dots[y * 80 + x];
+        assert_eq!((0..5).map(|x| dot(x, 0)).collect::<Vec<_>>(), vec![true; 5]);
+        assert!(dot(2, 6));
+        assert!(!dot(0, 6));
+        assert!((5..10).all(|x| dot(x, 7)));
+        assert!(!dot(10, 7));
+        assert_eq!(&disp.text()[0][..2], b"T ");
+        assert_eq!(disp.text().len(), 2);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::lcd_wiring::PortPin;
+
+/// What a host key is wired to on the board.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyTarget {
+    /// A push button between a port pin of the first VIA and ground.
+    Button(PortPin),
+    /// The reset button, holds RES low while pressed.
+    Reset,
+    /// A button on the NMI line.
+    Nmi,
+}
+
+/// Input from the host to the machine, sent from the window to the bus.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MachineInput {
+    Button(PortPin, bool),
+    Reset(bool),
+    Nmi(bool),
+    /// A key that is not bound to anything else went down or up, by its lower case name.
+    Key(String, bool),
+    /// An ASCII character was typed.
+    Char(u8),
+}
+
+impl MachineInput {
+    pub fn from_target(target: KeyTarget, pressed: bool) -> MachineInput {
+        match target {
+            KeyTarget::Button(pin) => MachineInput::Button(pin, pressed),
+            KeyTarget::Reset => MachineInput::Reset(pressed),
+            KeyTarget::Nmi => MachineInput::Nmi(pressed),
+        }
+    }
+}
+
+/// Host keys bound to buttons on the board, by their lower case SDL names like
+/// `f5`, `left` or `keypad 1`. Keys that are not bound go to the keyboard.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyMap {
+    bindings: Vec<(String, KeyTarget)>,
+}
+
+impl KeyMap {
+    /// Adds bindings like `f5=reset`, `f6=nmi` or `up=pa0,down=pa1`. A later
+    /// binding of the same key replaces the earlier one.
+    pub fn bind(&mut self, spec: &str) -> Result<(), String> {
+        for binding in spec.split(',') {
+            let (key, target) = binding.split_once('=')
+                .ok_or(format!("Expected key=input, got '{}'", binding))?;
+            let key = key.trim().to_lowercase();
+            if key.is_empty() {
+                return Err(format!("Expected key=input, got '{}'", binding));
+            }
+            let target = match target.trim().to_lowercase().as_str() {
+                "reset" | "res" => KeyTarget::Reset,
+                "nmi" => KeyTarget::Nmi,
+                pin => KeyTarget::Button(PortPin::parse(pin)?),
+            };
+            self.bindings.retain(|(bound, _)| *bound != key);
+            self.bindings.push((key, target));
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<KeyTarget> {
+        let key = key.to_lowercase();
+        self.bindings.iter().find(|(bound, _)| *bound == key).map(|(_, target)| *target)
+    }
+}
+
+// Shifted characters and the key they are on, US layout
+const SHIFTED: [(u8, u8); 21] = [
+    (b'~', b'`'), (b'!', b'1'), (b'@', b'2'), (b'#', b'3'), (b'$', b'4'), (b'%', b'5'),
+    (b'^', b'6'), (b'&', b'7'), (b'*', b'8'), (b'(', b'9'), (b')', b'0'), (b'_', b'-'),
+    (b'+', b'='), (b'{', b'['), (b'}', b']'), (b'|', b'\\'), (b':', b';'), (b'"', b'\''),
+    (b'<', b','), (b'>', b'.'), (b'?', b'/'),
+];
+
+/// The keys pressed and released to type an ASCII character on a US keyboard,
+/// followed by the character itself. For input from the terminal, which only
+/// sees characters.
+pub fn typed(c: u8) -> Vec<MachineInput> {
+    let (key, modifier, c) = match c {
+        b'\n' | b'\r' => (String::from("return"), None, b'\r'),
+        0x7F | 0x08 => (String::from("backspace"), None, 0x08),
+        b'\t' => (String::from("tab"), None, c),
+        0x1B => (String::from("escape"), None, c),
+        b' ' => (String::from("space"), None, c),
+        b'A'..=b'Z' => ((c.to_ascii_lowercase() as char).to_string(), Some("left shift"), c),
+        0x01..=0x1A => (((c + b'a' - 1) as char).to_string(), Some("left ctrl"), c),
+        _ => match SHIFTED.iter().find(|(shifted, _)| *shifted == c) {
+            Some((_, key)) => ((*key as char).to_string(), Some("left shift"), c),
+            None if c.is_ascii_graphic() => ((c as char).to_string(), None, c),
+            None => return vec![MachineInput::Char(c)],
+        },
+    };
+    let mut inputs = Vec::new();
+    if let Some(modifier) = modifier {
+        inputs.push(MachineInput::Key(String::from(modifier), true));
+    }
+    inputs.push(MachineInput::Key(key.clone(), true));
+    inputs.push(MachineInput::Key(key, false));
+    if let Some(modifier) = modifier {
+        inputs.push(MachineInput::Key(String::from(modifier), false));
+    }
+    inputs.push(MachineInput::Char(c));
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::{KeyMap, KeyTarget, MachineInput, typed};
+    use crate::lcd_wiring::{Port, PortPin};
+
+    #[test]
+    fn test_bind() {
+        let mut key_map = KeyMap::default();
+        key_map.bind("F5=reset, f6=nmi").unwrap();
+        key_map.bind("up=pa0,keypad 1=PB7").unwrap();
+        assert_eq!(key_map.get("f5"), Some(KeyTarget::Reset));
+        assert_eq!(key_map.get("F6"), Some(KeyTarget::Nmi));
+        assert_eq!(key_map.get("Up"), Some(KeyTarget::Button(PortPin { port: Port::A, bit: 0 })));
+        assert_eq!(key_map.get("keypad 1"), Some(KeyTarget::Button(PortPin { port: Port::B, bit: 7 })));
+        assert_eq!(key_map.get("down"), None);
+        key_map.bind("f5=nmi").unwrap();
+        assert_eq!(key_map.get("f5"), Some(KeyTarget::Nmi));
+        assert_eq!(MachineInput::from_target(KeyTarget::Reset, true), MachineInput::Reset(true));
+    }
+
+    #[test]
+    fn test_bind_errors() {
+        let mut key_map = KeyMap::default();
+        assert!(key_map.bind("f5").is_err());
+        assert!(key_map.bind("=reset").is_err());
+        assert!(key_map.bind("f5=pc0").is_err());
+        assert!(key_map.bind("f5=irq").is_err());
+    }
+
+    #[test]
+    fn test_typed() {
+        let key = |name: &str, pressed| MachineInput::Key(String::from(name), pressed);
+        assert_eq!(typed(b'a'), vec![key("a", true), key("a", false), MachineInput::Char(b'a')]);
+        assert_eq!(typed(b'\n'), vec![key("return", true), key("return", false), MachineInput::Char(b'\r')]);
+        assert_eq!(typed(b'?'), vec![key("left shift", true), key("/", true), key("/", false),
+                                     key("left shift", false), MachineInput::Char(b'?')]);
+        assert_eq!(typed(b'Q')[1], key("q", true));
+        assert_eq!(typed(0x03)[..2], [key("left ctrl", true), key("c", true)]);
+        assert_eq!(typed(0x80), vec![MachineInput::Char(0x80)]);
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+use crate::bus::BusDevice;
+
+/// A parallel ASCII keyboard, the kind with an encoder that presents one
+/// character at a time. Characters typed on the host queue up until the
+/// 6502 reads them.
+pub struct Keyboard {
+    pub(crate) queue: VecDeque<u8>,
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
+}
+
+impl Keyboard {
+    // Registers, mirrored through the rest of the region
+    pub const DATA: u8 = 0;
+    pub const STATUS: u8 = 1;
+
+    // Status bits
+    pub const READY: u8 = 0b1000_0000;
+
+    pub fn new() -> Keyboard {
+        Keyboard {
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.queue.clear();
+    }
+
+    pub fn type_char(&mut self, c: u8) {
+        self.queue.push_back(c & 0x7F);
+    }
+
+    /// The IRQ output is active while a character is waiting.
+    pub fn irq(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Reading DATA takes the next character, 0 when there is none.
+    /// STATUS has READY set while a character is waiting.
+    pub fn read(&mut self, reg: u8) -> u8 {
+        match reg & 1 {
+            Keyboard::DATA => self.queue.pop_front().unwrap_or(0),
+            Keyboard::STATUS => if self.queue.is_empty() { 0 } else { Keyboard::READY },
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl BusDevice for Keyboard {
+    fn read(&mut self, addr: u16) -> u8 {
+        Keyboard::read(self, addr as u8)
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {}
+
+    fn next_event(&self, _cycles: u64) -> Option<u64> {
+        None
+    }
+
+    fn irq(&self) -> bool {
+        Keyboard::irq(self)
+    }
+
+    fn reset(&mut self) {
+        Keyboard::reset(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyboard::Keyboard;
+
+    #[test]
+    fn test_typing() {
+        let mut keyboard = Keyboard::new();
+        assert_eq!(keyboard.read(Keyboard::STATUS), 0);
+        assert!(!keyboard.irq());
+        keyboard.type_char(b'h');
+        keyboard.type_char(b'i' | 0x80);
+        assert_eq!(keyboard.read(Keyboard::STATUS), Keyboard::READY);
+        assert!(keyboard.irq());
+        assert_eq!(keyboard.read(Keyboard::DATA), b'h');
+        // Mirrored
+        assert_eq!(keyboard.read(0x0F), Keyboard::READY);
+        assert_eq!(keyboard.read(0x0E), b'i');
+        assert_eq!(keyboard.read(Keyboard::STATUS), 0);
+        assert_eq!(keyboard.read(Keyboard::DATA), 0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut keyboard = Keyboard::new();
+        keyboard.type_char(b'x');
+        keyboard.reset();
+        assert!(!keyboard.irq());
+    }
+}
@@ -0,0 +1,424 @@
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+extern crate sdl2;
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use crate::clock::ClockCommand;
+use crate::display::{Display, Frame};
+use crate::input::{KeyMap, MachineInput};
+use crate::lcd_wiring::Port;
+use crate::leds::{Led, PortLeds};
+use crate::terminal;
+
+// Layout, in dots of PIXEL_SIZE pixels with DOT_SPACE pixels between them
+const PIXEL_SIZE: u32 = 4;
+const DOT_SPACE: u32 = 1;
+const SIDE_BORDER: u32 = 6;
+const TOP_BOTTOM_BORDER: u32 = 2;
+const CHAR_SPACE: u32 = 1;
+
+const BACKGROUND: [u8; 3] = [0x2b, 0x4b, 0xe5];
+const DOT_OFF: [u8; 3] = [0x26, 0x46, 0xe0];
+const DOT_ON: [u8; 3] = [0x21, 0x21, 0x23];
+
+// The LEDs go on a strip under the LCD, laid out in the same dots
+const LED_SIZE: u32 = 3;
+const LED_SPACE: u32 = 1;
+const PORT_GAP: u32 = 3;
+const DIGIT_WIDTH: u32 = 5; // Four dots of segments and the decimal point
+const DIGIT_HEIGHT: u32 = 7;
+
+const BOARD: [u8; 3] = [0x1e, 0x2a, 0x1e];
+const LED_ON: [u8; 3] = [0xff, 0x30, 0x20];
+const LED_OFF: [u8; 3] = [0x50, 0x10, 0x10];
+const LED_INPUT: [u8; 3] = [0x34, 0x3c, 0x34];
+
+// Dots of each segment in a digit, a to g and the decimal point
+const SEGMENTS: [[(u32, u32); 2]; 8] = [
+    [(1, 0), (2, 0)], [(3, 1), (3, 2)], [(3, 4), (3, 5)], [(1, 6), (2, 6)],
+    [(0, 4), (0, 5)], [(0, 1), (0, 2)], [(1, 3), (2, 3)], [(4, 6), (4, 6)],
+];
+
+/// Draws the LCD at a fixed refresh rate. The liquid crystal takes a while to
+/// turn dark or clear again, so changes fade in and out rather than snap.
+pub struct Renderer {
+    pub(crate) refresh_rate: u32, // Frames per second
+    pub(crate) on_time: Duration, // Time constant of a dot turning dark
+    pub(crate) off_time: Duration, // and of it clearing again
+    pub key_map: KeyMap,
+    pub clock_keys: bool, // Keys control the clock instead of going to the keyboard
+    levels: Vec<f32>, // How dark each dot is, 0.0 to 1.0
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer::new()
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Renderer {
+        Renderer {
+            refresh_rate: 60,
+            on_time: Duration::from_millis(40),
+            off_time: Duration::from_millis(70),
+            key_map: KeyMap::default(),
+            clock_keys: false,
+            levels: Vec::new(),
+        }
+    }
+
+    fn clock_command(keycode: Keycode) -> Option<ClockCommand> {
+        match keycode {
+            Keycode::Space | Keycode::Return | Keycode::S => Some(ClockCommand::Step),
+            Keycode::N => Some(ClockCommand::StepInstruction),
+            Keycode::H | Keycode::R => Some(ClockCommand::Toggle),
+            Keycode::Plus | Keycode::Equals | Keycode::KpPlus => Some(ClockCommand::Faster),
+            Keycode::Minus | Keycode::KpMinus => Some(ClockCommand::Slower),
+            _ => None,
+        }
+    }
+
+    /// Characters typed with keys that don't produce text input.
+    fn control_char(keycode: Keycode) -> Option<u8> {
+        match keycode {
+            Keycode::Return | Keycode::KpEnter => Some(b'\r'),
+            Keycode::Backspace => Some(0x08),
+            Keycode::Tab => Some(b'\t'),
+            Keycode::Escape => Some(0x1B),
+            _ => None,
+        }
+    }
+
+    /// Where the picture goes in a window of `output` size, scaled to fit with
+    /// its aspect ratio kept.
+    fn fit(natural: (u32, u32), output: (u32, u32)) -> Rect {
+        let scale = (output.0 as f32 / natural.0 as f32).min(output.1 as f32 / natural.1 as f32);
+        let width = ((natural.0 as f32 * scale) as u32).max(1);
+        let height = ((natural.1 as f32 * scale) as u32).max(1);
+        Rect::new(((output.0 - width.min(output.0)) / 2) as i32, ((output.1 - height.min(output.1)) / 2) as i32, width, height)
+    }
+
+    /// Moves every dot towards what it is driven to show. After a change in
+    /// size or font the dots start out settled.
+    pub fn fade(&mut self, frame: &Frame, elapsed: Duration) {
+        if self.levels.len() != frame.dots.len() {
+            self.levels = frame.dots.iter().map(|&on| if on { 1.0 } else { 0.0 }).collect();
+            return;
+        }
+        let on = 1.0 - (-elapsed.as_secs_f32() / self.on_time.as_secs_f32()).exp();
+        let off = 1.0 - (-elapsed.as_secs_f32() / self.off_time.as_secs_f32()).exp();
+        for (level, &dot) in self.levels.iter_mut().zip(&frame.dots) {
+            if dot {
+                *level += (1.0 - *level) * on;
+            } else {
+                *level -= *level * off;
+            }
+        }
+    }
+
+    fn window_size(frame: &Frame) -> (u32, u32) {
+        let columns = frame.columns as u32;
+        let rows = frame.rows as u32;
+        let char_height = frame.char_height as u32;
+        let window_width = (columns * (5 + CHAR_SPACE) + 2 * SIDE_BORDER - CHAR_SPACE) * (PIXEL_SIZE + DOT_SPACE);
+        let window_height = ((rows * (char_height + CHAR_SPACE)) + (TOP_BOTTOM_BORDER * 2)) * (PIXEL_SIZE + DOT_SPACE);
+        (window_width, window_height)
+    }
+
+    fn led_strip_size(leds: &PortLeds) -> (u32, u32) {
+        let digits = leds.digits.len() as u32;
+        let width = 2 * SIDE_BORDER + 16 * (LED_SIZE + LED_SPACE) - LED_SPACE + PORT_GAP
+            + digits * (PORT_GAP + DIGIT_WIDTH);
+        let height = 2 * TOP_BOTTOM_BORDER + if digits > 0 { DIGIT_HEIGHT } else { LED_SIZE };
+        (width * (PIXEL_SIZE + DOT_SPACE), height * (PIXEL_SIZE + DOT_SPACE))
+    }
+
+    /// The LCD with the LED strip under it, either may be missing.
+    fn natural_size(frame: Option<&Frame>, leds: Option<&PortLeds>) -> (u32, u32) {
+        let lcd = frame.map_or((0, 0), Renderer::window_size);
+        let strip = leds.map_or((0, 0), Renderer::led_strip_size);
+        (lcd.0.max(strip.0).max(1), (lcd.1 + strip.1).max(1))
+    }
+
+    /// Fills a square of `size` dots whose top left dot is at `x`, `y`.
+    fn fill_dots(buffer: &mut [u8], pitch: usize, (x, y): (u32, u32), size: u32, color: [u8; 3]) {
+        let unit = (PIXEL_SIZE + DOT_SPACE) as usize;
+        let (left, top) = (x as usize * unit, y as usize * unit);
+        let pixels = size as usize * unit - DOT_SPACE as usize;
+        for line in top..top + pixels {
+            let start = line * pitch + left * 3;
+            for pixel in buffer[start..start + pixels * 3].chunks_exact_mut(3) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+    }
+
+    /// Paints the LED strip from `top` pixels down to the end of the buffer:
+    /// port A then port B with bit 7 on the left, then the digits.
+    fn draw_leds(leds: &PortLeds, buffer: &mut [u8], pitch: usize, top: u32) {
+        for pixel in buffer[top as usize * pitch..].chunks_exact_mut(3) {
+            pixel.copy_from_slice(&BOARD);
+        }
+        let top = top / (PIXEL_SIZE + DOT_SPACE) + TOP_BOTTOM_BORDER;
+        let mut left = SIDE_BORDER;
+        for port in [Port::A, Port::B] {
+            for bit in (0..8).rev() {
+                let color = match leds.led(port, bit) {
+                    Led::On => LED_ON,
+                    Led::Off => LED_OFF,
+                    Led::Input => LED_INPUT,
+                };
+                Renderer::fill_dots(buffer, pitch, (left, top), LED_SIZE, color);
+                left += LED_SIZE + LED_SPACE;
+            }
+            left += PORT_GAP;
+        }
+        left -= LED_SPACE + PORT_GAP;
+        for digit in &leds.digits {
+            left += PORT_GAP;
+            let segments = digit.segments(leds);
+            for (segment, dots) in SEGMENTS.iter().enumerate() {
+                let color = if segments & (1 << segment) > 0 { LED_ON } else { LED_OFF };
+                for &(x, y) in dots {
+                    Renderer::fill_dots(buffer, pitch, (left + x, top + y), 1, color);
+                }
+            }
+            left += DIGIT_WIDTH;
+        }
+    }
+
+    /// Paints the dots into an RGB24 buffer the size of the window.
+    fn draw(&self, frame: &Frame, buffer: &mut [u8], pitch: usize) {
+        for pixel in buffer.chunks_exact_mut(3) {
+            pixel.copy_from_slice(&BACKGROUND);
+        }
+        let height = frame.rows as usize * frame.char_height as usize;
+        for y in 0..height {
+            let (row, c_y) = (y / frame.char_height as usize, y % frame.char_height as usize);
+            let top = (TOP_BOTTOM_BORDER as usize + row * (frame.char_height as usize + CHAR_SPACE as usize) + c_y)
+                * (PIXEL_SIZE + DOT_SPACE) as usize;
+            for x in 0..frame.width() {
+                let (char, c_x) = (x / 5, x % 5);
+                let left = (SIDE_BORDER as usize + char * (5 + CHAR_SPACE as usize) + c_x)
+                    * (PIXEL_SIZE + DOT_SPACE) as usize;
+                let level = self.levels[y * frame.width() + x];
+                let mut color = [0; 3];
+                for (channel, (off, on)) in color.iter_mut().zip(DOT_OFF.iter().zip(DOT_ON)) {
+                    *channel = (*off as f32 + (on as f32 - *off as f32) * level).round() as u8;
+                }
+                for line in top..top + PIXEL_SIZE as usize {
+                    let start = line * pitch + left * 3;
+                    for pixel in buffer[start..start + PIXEL_SIZE as usize * 3].chunks_exact_mut(3) {
+                        pixel.copy_from_slice(&color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Routes a key to the button it is bound to, the clock or the keyboard.
+    /// Returns false when the key is taken and the text it types should be dropped.
+    fn key(&self, keycode: Keycode, pressed: bool, clock_control: &Sender<ClockCommand>, input: &Sender<MachineInput>) -> bool {
+        let name = keycode.name().to_lowercase();
+        if let Some(target) = self.key_map.get(&name) {
+            input.send(MachineInput::from_target(target, pressed)).unwrap();
+            return false;
+        }
+        if self.clock_keys {
+            if let Some(command) = Renderer::clock_command(keycode) {
+                if pressed {
+                    clock_control.send(command).unwrap();
+                }
+                return false;
+            }
+        }
+        input.send(MachineInput::Key(name, pressed)).unwrap();
+        if pressed {
+            if let Some(c) = Renderer::control_char(keycode) {
+                input.send(MachineInput::Char(c)).unwrap();
+            }
+        }
+        true
+    }
+
+    /// Shows the LCD, the port LEDs or both until the window is closed.
+    pub fn run(&mut self, display: Option<&Mutex<Display>>, leds: Option<&Mutex<PortLeds>>,
+               clock_control: Sender<ClockCommand>, input: Sender<MachineInput>) {
+        let sdl_context = sdl2::init().expect("");
+        let mut event_pump = sdl_context.event_pump().expect("");
+        let video_subsystem = sdl_context.video().expect("");
+        let snapshot = || {
+            (display.map(|display| display.lock().unwrap().frame()), leds.map(|leds| leds.lock().unwrap().clone()))
+        };
+        let (frame, port_leds) = snapshot();
+        let mut natural_size = Renderer::natural_size(frame.as_ref(), port_leds.as_ref());
+        let window = video_subsystem
+            .window(
+                "Display",
+                natural_size.0,
+                natural_size.1,
+            )
+            .position_centered()
+            .resizable()
+            .opengl()
+            .build()
+            .map_err(|e| e.to_string()).expect("");
+        video_subsystem.text_input().start();
+
+        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string()).expect("");
+        let texture_creator = canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, natural_size.0, natural_size.1)
+            .map_err(|e| e.to_string()).expect("");
+        let frame_time = Duration::from_secs(1) / self.refresh_rate;
+        let mut last_frame = Instant::now();
+        let mut text_wanted = true;
+        loop {
+            for event in event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. } => terminal::exit(0),
+                    Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                        text_wanted = self.key(keycode, true, &clock_control, &input);
+                    }
+                    Event::KeyUp { keycode: Some(keycode), .. } => {
+                        self.key(keycode, false, &clock_control, &input);
+                    }
+                    // Follows the key down it was typed with
+                    Event::TextInput { text, .. } if text_wanted => {
+                        for c in text.bytes().filter(u8::is_ascii) {
+                            input.send(MachineInput::Char(c)).unwrap();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // Hold the locks only long enough to take a snapshot
+            let (frame, port_leds) = snapshot();
+            let now = Instant::now();
+            if let Some(frame) = &frame {
+                self.fade(frame, now - last_frame);
+            }
+            last_frame = now;
+            // Function set can change the font and number of lines, the window
+            // keeps its scale
+            if Renderer::natural_size(frame.as_ref(), port_leds.as_ref()) != natural_size {
+                let (width, height) = canvas.window().size();
+                let scale = (width as f32 / natural_size.0 as f32).min(height as f32 / natural_size.1 as f32);
+                natural_size = Renderer::natural_size(frame.as_ref(), port_leds.as_ref());
+                let scaled = ((natural_size.0 as f32 * scale) as u32, (natural_size.1 as f32 * scale) as u32);
+                canvas.window_mut().set_size(scaled.0.max(1), scaled.1.max(1)).expect("");
+                texture = texture_creator
+                    .create_texture_streaming(PixelFormatEnum::RGB24, natural_size.0, natural_size.1)
+                    .map_err(|e| e.to_string()).expect("");
+            }
+            texture.with_lock(None, |buffer, pitch| {
+                if let Some(frame) = &frame {
+                    self.draw(frame, buffer, pitch);
+                }
+                if let Some(port_leds) = &port_leds {
+                    let top = frame.as_ref().map_or(0, |frame| Renderer::window_size(frame).1);
+                    Renderer::draw_leds(port_leds, buffer, pitch, top);
+                }
+            }).expect("");
+            canvas.set_draw_color(Color::RGB(BACKGROUND[0], BACKGROUND[1], BACKGROUND[2]));
+            canvas.clear();
+            let output_size = canvas.output_size().expect("");
+            canvas.copy(&texture, None, Renderer::fit(natural_size, output_size)).expect("");
+            canvas.present();
+            thread::sleep(frame_time.saturating_sub(now.elapsed()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::display::Frame;
+    use sdl2::rect::Rect;
+    use crate::lcd_renderer::Renderer;
+    use crate::leds::{PortLeds, SevenSegment};
+
+    fn frame(dots: Vec<bool>) -> Frame {
+        Frame { columns: 1, rows: 1, char_height: 1, dots }
+    }
+
+    #[test]
+    fn test_fade() {
+        let mut renderer = Renderer::new();
+        renderer.fade(&frame(vec![false, true, false, false, false]), Duration::ZERO);
+        assert_eq!(renderer.levels, vec![0.0, 1.0, 0.0, 0.0, 0.0]);
+        let changed = frame(vec![true, false, false, false, false]);
+        renderer.fade(&changed, Duration::from_millis(40));
+        // One time constant in, turning dark is faster than clearing
+        assert!((renderer.levels[0] - 0.632).abs() < 0.01);
+        assert!(renderer.levels[1] > 1.0 - renderer.levels[0]);
+        renderer.fade(&changed, Duration::from_secs(1));
+        assert!(renderer.levels[0] > 0.99);
+        assert!(renderer.levels[1] < 0.01);
+    }
+
+    #[test]
+    fn test_fade_resize() {
+        let mut renderer = Renderer::new();
+        renderer.fade(&frame(vec![false; 5]), Duration::ZERO);
+        let tall = Frame { columns: 1, rows: 1, char_height: 2, dots: vec![true; 10] };
+        renderer.fade(&tall, Duration::from_millis(1));
+        assert_eq!(renderer.levels, vec![1.0; 10]);
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut renderer = Renderer::new();
+        let frame = frame(vec![true, false, false, false, false]);
+        renderer.fade(&frame, Duration::ZERO);
+        let (width, height) = Renderer::window_size(&frame);
+        let pitch = width as usize * 3;
+        let mut buffer = vec![0; pitch * height as usize];
+        renderer.draw(&frame, &mut buffer, pitch);
+        let pixel = |x: usize, y: usize| &buffer[y * pitch + x * 3..y * pitch + x * 3 + 3];
+        assert_eq!(pixel(0, 0), [0x2b, 0x4b, 0xe5]);
+        // The first dot is 6 dots in from the side and 2 from the top
+        assert_eq!(pixel(30, 10), [0x21, 0x21, 0x23]);
+        assert_eq!(pixel(34, 10), [0x2b, 0x4b, 0xe5]);
+        assert_eq!(pixel(35, 10), [0x26, 0x46, 0xe0]);
+    }
+
+    #[test]
+    fn test_draw_leds() {
+        let mut leds = PortLeds::new(vec![SevenSegment::parse("pb").unwrap()]);
+        (leds.a, leds.ddr_a, leds.b, leds.ddr_b) = (0x80, 0xF0, 0x01, 0xFF);
+        let lcd = Frame { columns: 16, rows: 2, char_height: 8, dots: vec![false; 16 * 2 * 40] };
+        let (width, height) = Renderer::natural_size(Some(&lcd), Some(&leds));
+        assert_eq!(width, Renderer::window_size(&lcd).0);
+        assert_eq!(height, Renderer::window_size(&lcd).1 + 11 * 5);
+        let pitch = width as usize * 3;
+        let mut buffer = vec![0; pitch * height as usize];
+        let top = Renderer::window_size(&lcd).1;
+        Renderer::draw_leds(&leds, &mut buffer, pitch, top);
+        let pixel = |x: usize, y: usize| &buffer[(top as usize + y) * pitch + x * 3..(top as usize + y) * pitch + x * 3 + 3];
+        assert_eq!(pixel(0, 0), [0x1e, 0x2a, 0x1e]);
+        // PA7 is on, PA6 off and PA3 an input
+        assert_eq!(pixel(30, 10), [0xff, 0x30, 0x20]);
+        assert_eq!(pixel(50, 10), [0x50, 0x10, 0x10]);
+        assert_eq!(pixel(110, 10), [0x34, 0x3c, 0x34]);
+        // Segment a of the digit is lit, b is not
+        let digit = (6 + 16 * 4 - 1 + 3 + 3) as usize * 5;
+        assert_eq!(pixel(digit + 5, 10), [0xff, 0x30, 0x20]);
+        assert_eq!(pixel(digit + 15, 15), [0x50, 0x10, 0x10]);
+        assert_eq!(Renderer::natural_size(None, None), (1, 1));
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(Renderer::fit((100, 50), (100, 50)), Rect::new(0, 0, 100, 50));
+        assert_eq!(Renderer::fit((100, 50), (300, 100)), Rect::new(50, 0, 200, 100));
+        assert_eq!(Renderer::fit((100, 50), (200, 400)), Rect::new(0, 150, 200, 100));
+    }
+}
@@ -0,0 +1,182 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Port {
+    A,
+    B,
+}
+
+/// A single VIA port bit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PortPin {
+    pub(crate) port: Port,
+    pub(crate) bit: u8,
+}
+
+impl PortPin {
+    pub fn parse(value: &str) -> Result<PortPin, String> {
+        let value = value.to_lowercase();
+        let port = match value.get(..2) {
+            Some("pa") => Port::A,
+            Some("pb") => Port::B,
+            _ => return Err(format!("Expected a port pin like pa5 or pb0, got '{}'", value)),
+        };
+        match value[2..].parse::<u8>() {
+            Ok(bit) if bit < 8 => Ok(PortPin { port, bit }),
+            _ => Err(format!("Expected a port pin like pa5 or pb0, got '{}'", value)),
+        }
+    }
+
+    pub fn level(&self, port_a: u8, port_b: u8) -> bool {
+        let port = match self.port {
+            Port::A => port_a,
+            Port::B => port_b,
+        };
+        port & (1 << self.bit) > 0
+    }
+}
+
+/// Which VIA port bits the LCD pins are wired to. D0-D3 are left unconnected
+/// when the LCD is wired for 4-bit mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LcdWiring {
+    pub(crate) data: [Option<PortPin>; 8],
+    pub(crate) rs: PortPin,
+    pub(crate) rw: PortPin,
+    pub(crate) e: PortPin,
+}
+
+impl LcdWiring {
+    /// D0-D7 on PB0-PB7, RS, RW and E on PA5-PA7.
+    pub fn eight_bit() -> LcdWiring {
+        let mut data = [None; 8];
+        for (bit, pin) in data.iter_mut().enumerate() {
+            *pin = Some(PortPin { port: Port::B, bit: bit as u8 });
+        }
+        LcdWiring {
+            data,
+            rs: PortPin { port: Port::A, bit: 5 },
+            rw: PortPin { port: Port::A, bit: 6 },
+            e: PortPin { port: Port::A, bit: 7 },
+        }
+    }
+
+    /// D4-D7 on PB0-PB3, RS, RW and E on PB4-PB6, leaving port A free.
+    pub fn four_bit() -> LcdWiring {
+        let mut data = [None; 8];
+        for (bit, pin) in data.iter_mut().enumerate().skip(4) {
+            *pin = Some(PortPin { port: Port::B, bit: bit as u8 - 4 });
+        }
+        LcdWiring {
+            data,
+            rs: PortPin { port: Port::B, bit: 4 },
+            rw: PortPin { port: Port::B, bit: 5 },
+            e: PortPin { port: Port::B, bit: 6 },
+        }
+    }
+
+    /// `8bit`, `4bit` or a list like `d4=pa0,d5=pa1,d6=pa2,d7=pa3,rs=pb5,rw=pb6,e=pb7`.
+    pub fn parse(spec: &str) -> Result<LcdWiring, String> {
+        match spec.to_lowercase().as_str() {
+            "8bit" | "8-bit" => return Ok(LcdWiring::eight_bit()),
+            "4bit" | "4-bit" => return Ok(LcdWiring::four_bit()),
+            _ => {}
+        }
+        let mut data = [None; 8];
+        let (mut rs, mut rw, mut e) = (None, None, None);
+        for assignment in spec.split(',') {
+            let (name, pin) = assignment.trim().split_once('=')
+                .ok_or(format!("Expected pin=port bit, got '{}'", assignment))?;
+            let pin = PortPin::parse(pin.trim())?;
+            match name.trim().to_lowercase().as_str() {
+                "rs" => rs = Some(pin),
+                "rw" => rw = Some(pin),
+                "e" => e = Some(pin),
+                name => match name.strip_prefix('d').and_then(|bit| bit.parse::<usize>().ok()) {
+                    Some(bit) if bit < 8 => data[bit] = Some(pin),
+                    _ => return Err(format!("Unknown LCD pin: {}", name)),
+                },
+            }
+        }
+        if data[4..].iter().any(|pin| pin.is_none()) {
+            return Err(String::from("D4-D7 must be wired"));
+        }
+        if data[..4].iter().any(|pin| pin.is_some()) && data[..4].iter().any(|pin| pin.is_none()) {
+            return Err(String::from("D0-D3 must all be wired for 8-bit mode, or none for 4-bit mode"));
+        }
+        Ok(LcdWiring {
+            data,
+            rs: rs.ok_or("RS must be wired")?,
+            rw: rw.ok_or("RW must be wired")?,
+            e: e.ok_or("E must be wired")?,
+        })
+    }
+
+    /// The data, RS, RW and E levels the LCD sees, unconnected data lines read low.
+    pub fn pins(&self, port_a: u8, port_b: u8) -> (u8, bool, bool, bool) {
+        let mut data = 0;
+        for (bit, pin) in self.data.iter().enumerate() {
+            if let Some(pin) = pin {
+                if pin.level(port_a, port_b) {
+                    data |= 1 << bit;
+                }
+            }
+        }
+        (data, self.rs.level(port_a, port_b), self.rw.level(port_a, port_b), self.e.level(port_a, port_b))
+    }
+
+    /// The levels on port A and B when the LCD drives `data`, other pins read high.
+    pub fn drive(&self, data: u8) -> (u8, u8) {
+        let (mut port_a, mut port_b) = (0xFF, 0xFF);
+        for (bit, pin) in self.data.iter().enumerate() {
+            if let Some(pin) = pin {
+                let port = match pin.port {
+                    Port::A => &mut port_a,
+                    Port::B => &mut port_b,
+                };
+                if data & (1 << bit) == 0 {
+                    *port &= !(1 << pin.bit);
+                }
+            }
+        }
+        (port_a, port_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lcd_wiring::{LcdWiring, Port, PortPin};
+
+    #[test]
+    fn test_eight_bit() {
+        let wiring = LcdWiring::eight_bit();
+        assert_eq!(wiring.pins(0b1010_0000, 0x38), (0x38, true, false, true));
+        assert_eq!(wiring.drive(0x80), (0xFF, 0x80));
+    }
+
+    #[test]
+    fn test_four_bit() {
+        let wiring = LcdWiring::four_bit();
+        // E | RS and the nibble $2 on PB0-PB3
+        assert_eq!(wiring.pins(0, 0b0101_0010), (0x20, true, false, true));
+        assert_eq!(wiring.drive(0x80), (0xFF, 0b1111_1000));
+    }
+
+    #[test]
+    fn test_parse() {
+        let wiring = LcdWiring::parse("d4=pa0, d5=pa1, d6=pa2, d7=pa3, rs=PB5, rw=pb6, e=pb7").unwrap();
+        assert_eq!(wiring.data[7], Some(PortPin { port: Port::A, bit: 3 }));
+        assert_eq!(wiring.data[0], None);
+        assert_eq!(wiring.e, PortPin { port: Port::B, bit: 7 });
+        assert_eq!(wiring.pins(0b0000_1001, 0b1000_0000), (0x90, false, false, true));
+        assert_eq!(LcdWiring::parse("4bit"), Ok(LcdWiring::four_bit()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(LcdWiring::parse("d4=pa0,d5=pa1,d6=pa2,rs=pb5,rw=pb6,e=pb7").is_err());
+        assert!(LcdWiring::parse("d0=pa4,d4=pa0,d5=pa1,d6=pa2,d7=pa3,rs=pb5,rw=pb6,e=pb7").is_err());
+        assert!(LcdWiring::parse("d4=pa0,d5=pa1,d6=pa2,d7=pa3,rs=pb5,rw=pb6").is_err());
+        assert!(LcdWiring::parse("d4=pc0").is_err());
+        assert!(LcdWiring::parse("d4=pa8").is_err());
+        assert!(LcdWiring::parse("d9=pa0").is_err());
+    }
+}
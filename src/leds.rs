@@ -0,0 +1,162 @@
+use crate::lcd_wiring::Port;
+use crate::via::Via;
+
+/// How an LED on a port pin looks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Led {
+    On,
+    Off,
+    /// The pin is an input, the LED shows whatever drives it only faintly.
+    Input,
+}
+
+/// A seven-segment display on a whole port, segment a on bit 0 through g on
+/// bit 6 and the decimal point on bit 7.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SevenSegment {
+    pub(crate) port: Port,
+    pub(crate) common_anode: bool, // Segments light up when their pin is low
+}
+
+// Segment patterns of the characters that can be told apart, gfedcba
+const DIGITS: [(u8, char); 22] = [
+    (0x3F, '0'), (0x06, '1'), (0x5B, '2'), (0x4F, '3'), (0x66, '4'), (0x6D, '5'), (0x7D, '6'),
+    (0x07, '7'), (0x7F, '8'), (0x6F, '9'), (0x77, 'A'), (0x7C, 'b'), (0x39, 'C'), (0x5E, 'd'),
+    (0x79, 'E'), (0x71, 'F'), (0x76, 'H'), (0x38, 'L'), (0x73, 'P'), (0x3E, 'U'), (0x40, '-'),
+    (0x00, ' '),
+];
+
+impl SevenSegment {
+    /// `pa` or `pb`, with `:ca` for a common anode display or `:cc` for a
+    /// common cathode one (the default).
+    pub fn parse(value: &str) -> Result<SevenSegment, String> {
+        let value = value.trim().to_lowercase();
+        let (port, kind) = value.split_once(':').unwrap_or((&value, "cc"));
+        let port = match port {
+            "pa" => Port::A,
+            "pb" => Port::B,
+            _ => return Err(format!("Expected a port like pb or pb:ca, got '{}'", value)),
+        };
+        let common_anode = match kind {
+            "ca" => true,
+            "cc" => false,
+            _ => return Err(format!("Expected cc or ca for the display, got '{}'", kind)),
+        };
+        Ok(SevenSegment { port, common_anode })
+    }
+
+    /// The segments that are lit. Pins set as inputs light nothing.
+    pub fn segments(&self, ports: &PortLeds) -> u8 {
+        let (value, ddr) = match self.port {
+            Port::A => (ports.a, ports.ddr_a),
+            Port::B => (ports.b, ports.ddr_b),
+        };
+        if self.common_anode { !value & ddr } else { value & ddr }
+    }
+
+    /// What the segments read as, `?` when they aren't a character.
+    pub fn character(segments: u8) -> String {
+        let c = DIGITS.iter().find(|(pattern, _)| *pattern == segments & 0x7F).map_or('?', |(_, c)| *c);
+        if segments & 0x80 > 0 { format!("{}.", c) } else { c.to_string() }
+    }
+}
+
+/// The two ports of the first VIA with a LED on every pin, and the
+/// seven-segment displays wired to them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PortLeds {
+    pub(crate) a: u8,
+    pub(crate) ddr_a: u8,
+    pub(crate) b: u8,
+    pub(crate) ddr_b: u8,
+    pub(crate) digits: Vec<SevenSegment>,
+}
+
+impl PortLeds {
+    pub fn new(digits: Vec<SevenSegment>) -> PortLeds {
+        PortLeds { digits, ..PortLeds::default() }
+    }
+
+    /// Takes the pin levels and directions from the VIA. Returns true when
+    /// anything changed.
+    pub fn update(&mut self, via: &Via) -> bool {
+        // Timer 1 can drive PB7 whatever DDRB says
+        let ddr_b = if via.acr & 0x80 > 0 { via.ddrb | 0x80 } else { via.ddrb };
+        let pins = (via.port_a(), via.ddra, via.port_b(), ddr_b);
+        let changed = pins != (self.a, self.ddr_a, self.b, self.ddr_b);
+        (self.a, self.ddr_a, self.b, self.ddr_b) = pins;
+        changed
+    }
+
+    pub fn led(&self, port: Port, bit: u8) -> Led {
+        let (value, ddr) = match port {
+            Port::A => (self.a, self.ddr_a),
+            Port::B => (self.b, self.ddr_b),
+        };
+        match (ddr & (1 << bit) > 0, value & (1 << bit) > 0) {
+            (false, _) => Led::Input,
+            (true, true) => Led::On,
+            (true, false) => Led::Off,
+        }
+    }
+
+    /// One line for the terminal, bit 7 first like on the board: `●` for a
+    /// high output, `○` for a low one and `·` for an input.
+    pub fn line(&self) -> String {
+        let mut line = String::new();
+        for (name, port) in [("PA", Port::A), ("PB", Port::B)] {
+            line.push_str(name);
+            line.push(' ');
+            for bit in (0..8).rev() {
+                line.push(match self.led(port, bit) {
+                    Led::On => '●',
+                    Led::Off => '○',
+                    Led::Input => '·',
+                });
+            }
+            line.push_str("  ");
+        }
+        for digit in &self.digits {
+            line.push_str(&format!("[{:2}]", SevenSegment::character(digit.segments(self))));
+        }
+        line.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lcd_wiring::Port;
+    use crate::leds::{Led, PortLeds, SevenSegment};
+    use crate::via::Via;
+
+    #[test]
+    fn test_update() {
+        let mut via = Via::new();
+        let mut leds = PortLeds::new(Vec::new());
+        via.write(Via::DDRB, 0xFF);
+        via.write(Via::ORB, 0x50);
+        assert!(leds.update(&via));
+        assert!(!leds.update(&via));
+        assert_eq!(leds.led(Port::B, 6), Led::On);
+        assert_eq!(leds.led(Port::B, 5), Led::Off);
+        assert_eq!(leds.led(Port::A, 0), Led::Input);
+        assert_eq!(leds.line(), "PA ········  PB ○●○●○○○○");
+    }
+
+    #[test]
+    fn test_seven_segment() {
+        let digit = SevenSegment::parse("pb").unwrap();
+        assert_eq!(digit, SevenSegment { port: Port::B, common_anode: false });
+        let mut leds = PortLeds::new(vec![digit, SevenSegment::parse("PA:ca").unwrap()]);
+        (leds.b, leds.ddr_b) = (0x4F | 0x80, 0xFF);
+        (leds.a, leds.ddr_a) = (!0x06, 0xFF);
+        assert_eq!(SevenSegment::character(digit.segments(&leds)), "3.");
+        assert!(leds.line().ends_with("[3.][1 ]"));
+        assert_eq!(SevenSegment::character(0x49), "?");
+        // Inputs don't light anything
+        leds.ddr_b = 0x0F;
+        assert_eq!(digit.segments(&leds), 0x0F);
+        assert!(SevenSegment::parse("pc").is_err());
+        assert!(SevenSegment::parse("pb:xx").is_err());
+    }
+}
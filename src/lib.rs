@@ -0,0 +1,23 @@
+pub mod cpu;
+pub mod bus;
+pub mod scheduler;
+pub mod machine;
+pub mod display;
+pub mod cgrom;
+pub mod clock;
+pub mod terminal;
+pub mod bus_monitor;
+pub mod memory_map;
+pub mod cli;
+pub mod loader;
+pub mod via;
+pub mod lcd_wiring;
+pub mod lcd_renderer;
+pub mod leds;
+pub mod input;
+pub mod keyboard;
+pub mod ps2;
+pub mod acia;
+pub mod serial;
+pub mod disassembler;
+pub mod tui;
@@ -0,0 +1,375 @@
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    /// Plain binary, placed at a load address.
+    Raw,
+    IntelHex,
+    SRecord,
+    O65,
+    /// C64 style binary prefixed with its load address.
+    Prg,
+}
+
+impl ImageFormat {
+    pub fn parse(value: &str) -> Result<ImageFormat, String> {
+        match value.to_lowercase().as_str() {
+            "raw" | "bin" => Ok(ImageFormat::Raw),
+            "ihex" | "hex" => Ok(ImageFormat::IntelHex),
+            "srec" | "s19" => Ok(ImageFormat::SRecord),
+            "o65" => Ok(ImageFormat::O65),
+            "prg" => Ok(ImageFormat::Prg),
+            _ => Err(format!("Unknown image format: {}", value)),
+        }
+    }
+
+    /// Guesses the format from the file extension, falling back to the contents.
+    pub fn detect(path: &str, contents: &[u8]) -> ImageFormat {
+        let extension = Path::new(path).extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "hex" | "ihx" | "ihex" => ImageFormat::IntelHex,
+            "srec" | "s19" | "s28" | "s37" | "mot" => ImageFormat::SRecord,
+            "o65" => ImageFormat::O65,
+            "prg" => ImageFormat::Prg,
+            _ if contents.starts_with(&O65_MAGIC) => ImageFormat::O65,
+            _ if contents.starts_with(b":") => ImageFormat::IntelHex,
+            _ if contents.starts_with(b"S0") || contents.starts_with(b"S1") => ImageFormat::SRecord,
+            _ => ImageFormat::Raw,
+        }
+    }
+}
+
+/// Data to be placed at an address.
+#[derive(Debug, PartialEq)]
+pub struct Segment {
+    pub address: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+    /// Where execution should start, if the file says so.
+    pub entry: Option<u16>,
+}
+
+const O65_MAGIC: [u8; 5] = [0x01, 0x00, b'o', b'6', b'5'];
+
+/// Reads an image in one of the formats that carry their own addresses.
+pub fn parse(contents: &[u8], format: ImageFormat) -> Result<Image, String> {
+    let mut image = match format {
+        ImageFormat::Raw => return Err(String::from("Raw images need a load address")),
+        ImageFormat::IntelHex => parse_intel_hex(&text(contents)?)?,
+        ImageFormat::SRecord => parse_s_record(&text(contents)?)?,
+        ImageFormat::O65 => parse_o65(contents)?,
+        ImageFormat::Prg => parse_prg(contents)?,
+    };
+    image.segments.retain(|segment| !segment.data.is_empty());
+    check_overlaps(&image)?;
+    Ok(image)
+}
+
+fn text(contents: &[u8]) -> Result<String, String> {
+    String::from_utf8(contents.to_vec()).map_err(|_| String::from("Not a text file"))
+}
+
+fn hex_bytes(record: &str) -> Result<Vec<u8>, String> {
+    if !record.is_ascii() || !record.len().is_multiple_of(2) {
+        return Err(String::from("Records must be an even number of hex digits"));
+    }
+    (0..record.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&record[i..i + 2], 16).map_err(|_| format!("Invalid hex digits '{}'", &record[i..i + 2])))
+        .collect()
+}
+
+fn address_16(address: u32) -> Result<u16, String> {
+    u16::try_from(address).map_err(|_| format!("Address ${:x} is outside the 64K address space", address))
+}
+
+/// Adds `data` at `address`, making sure it doesn't run past $FFFF.
+fn push_segment(segments: &mut Vec<Segment>, address: u32, data: &[u8]) -> Result<(), String> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let last = address.checked_add(data.len() as u32 - 1)
+        .ok_or_else(|| format!("Address ${:x} is outside the 64K address space", address))?;
+    address_16(last)?;
+    let address = address_16(address)?;
+    // Records following each other are merged into one segment
+    if let Some(last) = segments.last_mut() {
+        if last.address as u32 + last.data.len() as u32 == address as u32 {
+            last.data.extend_from_slice(data);
+            return Ok(());
+        }
+    }
+    segments.push(Segment { address, data: data.to_vec() });
+    Ok(())
+}
+
+fn parse_intel_hex(text: &str) -> Result<Image, String> {
+    let mut image = Image { segments: Vec::new(), entry: None };
+    let mut base: u32 = 0;
+    let mut end = false;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        if end {
+            return Err(error(String::from("Data after the end of file record")));
+        }
+        let record = line.strip_prefix(':').ok_or_else(|| error(String::from("Records must start with ':'")))?;
+        let bytes = hex_bytes(record).map_err(error)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(error(String::from("Record length doesn't match its byte count")));
+        }
+        let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        if sum != 0 {
+            let checksum = bytes[bytes.len() - 1];
+            return Err(error(format!("Checksum is ${:02x}, expected ${:02x}", checksum, checksum.wrapping_sub(sum))));
+        }
+        let address = ((bytes[1] as u32) << 8) | bytes[2] as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        let value = data.iter().fold(0u32, |value, byte| (value << 8) | *byte as u32);
+        match bytes[3] {
+            0x00 => {
+                let address = base.checked_add(address)
+                    .ok_or_else(|| error(format!("Address ${:x}+${:04x} is outside the 64K address space", base, address)))?;
+                push_segment(&mut image.segments, address, data).map_err(error)?
+            }
+            0x01 => end = true,
+            // Extended segment address
+            0x02 => base = value << 4,
+            // Start segment address, CS:IP
+            0x03 => image.entry = Some(address_16(((value >> 16) << 4) + (value & 0xFFFF)).map_err(error)?),
+            // Extended linear address
+            0x04 => base = value << 16,
+            // Start linear address
+            0x05 => image.entry = Some(address_16(value).map_err(error)?),
+            record_type => return Err(error(format!("Unknown record type {:02x}", record_type))),
+        }
+    }
+    if !end {
+        return Err(String::from("Missing end of file record"));
+    }
+    Ok(image)
+}
+
+fn parse_s_record(text: &str) -> Result<Image, String> {
+    let mut image = Image { segments: Vec::new(), entry: None };
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        if !line.is_ascii() || line.len() < 2 || !line.starts_with('S') {
+            return Err(error(String::from("Records must start with 'S'")));
+        }
+        let record_type = &line[1..2];
+        let bytes = hex_bytes(&line[2..]).map_err(error)?;
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(error(String::from("Record length doesn't match its byte count")));
+        }
+        let sum = bytes[..bytes.len() - 1].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        let checksum = bytes[bytes.len() - 1];
+        if checksum != !sum {
+            return Err(error(format!("Checksum is ${:02x}, expected ${:02x}", checksum, !sum)));
+        }
+        let address_length = match record_type {
+            "0" | "1" | "5" | "9" => 2,
+            "2" | "6" | "8" => 3,
+            "3" | "7" => 4,
+            _ => return Err(error(format!("Unknown record type S{}", record_type))),
+        };
+        if bytes.len() < address_length + 2 {
+            return Err(error(String::from("Record is too short")));
+        }
+        let address = bytes[1..=address_length].iter().fold(0u32, |value, byte| (value << 8) | *byte as u32);
+        let data = &bytes[address_length + 1..bytes.len() - 1];
+        match record_type {
+            "1" | "2" | "3" => push_segment(&mut image.segments, address, data).map_err(error)?,
+            "7" | "8" | "9" => image.entry = Some(address_16(address).map_err(error)?),
+            // Header and record counts
+            _ => {}
+        }
+    }
+    Ok(image)
+}
+
+/// Loads the text and data segments of an o65 file at the addresses they
+/// were assembled for, execution starts at the text segment.
+fn parse_o65(contents: &[u8]) -> Result<Image, String> {
+    if !contents.starts_with(&O65_MAGIC) {
+        return Err(String::from("Not an o65 file"));
+    }
+    let truncated = || String::from("o65 file is truncated");
+    let byte = |offset: usize| contents.get(offset).copied().ok_or_else(truncated);
+    // Version at 5, then the mode word
+    let mode = byte(6)? as u16 | (byte(7)? as u16) << 8;
+    if mode & 0x8000 > 0 {
+        return Err(String::from("65816 o65 files are not supported"));
+    }
+    if mode & 0x1000 > 0 {
+        return Err(String::from("o65 object files need linking first"));
+    }
+    let word_size = if mode & 0x2000 > 0 { 4 } else { 2 };
+    let mut offset = 8;
+    let mut word = || {
+        let mut value: u32 = 0;
+        for i in 0..word_size {
+            value |= (byte(offset + i)? as u32) << (8 * i);
+        }
+        offset += word_size;
+        Ok::<u32, String>(value)
+    };
+    let text_base = word()?;
+    let text_length = word()?;
+    let data_base = word()?;
+    let data_length = word()?;
+    // bss, zero page and stack sizes
+    for _ in 0..5 {
+        word()?;
+    }
+    // Header options, each prefixed with its length, ending with a zero length
+    loop {
+        let length = byte(offset)? as usize;
+        if length == 0 {
+            offset += 1;
+            break;
+        }
+        offset += length;
+    }
+    let text_end = offset + text_length as usize;
+    let data_end = text_end + data_length as usize;
+    if data_end > contents.len() {
+        return Err(truncated());
+    }
+    let mut image = Image { segments: Vec::new(), entry: None };
+    push_segment(&mut image.segments, text_base, &contents[offset..text_end])?;
+    push_segment(&mut image.segments, data_base, &contents[text_end..data_end])?;
+    if text_length > 0 {
+        image.entry = Some(text_base as u16);
+    }
+    Ok(image)
+}
+
+fn parse_prg(contents: &[u8]) -> Result<Image, String> {
+    if contents.len() < 2 {
+        return Err(String::from("PRG file is missing its load address"));
+    }
+    let address = contents[0] as u32 | (contents[1] as u32) << 8;
+    let mut image = Image { segments: Vec::new(), entry: None };
+    push_segment(&mut image.segments, address, &contents[2..])?;
+    Ok(image)
+}
+
+fn check_overlaps(image: &Image) -> Result<(), String> {
+    for (i, a) in image.segments.iter().enumerate() {
+        for b in &image.segments[i + 1..] {
+            let a_end = a.address as u32 + a.data.len() as u32;
+            let b_end = b.address as u32 + b.data.len() as u32;
+            if (a.address as u32) < b_end && (b.address as u32) < a_end {
+                return Err(format!("Data at ${:04x}-${:04x} overlaps data at ${:04x}-${:04x}",
+                                   a.address, a_end - 1, b.address, b_end - 1));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loader::{Image, ImageFormat, Segment, parse};
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(ImageFormat::detect("rom.hex", b""), ImageFormat::IntelHex);
+        assert_eq!(ImageFormat::detect("rom.s19", b""), ImageFormat::SRecord);
+        assert_eq!(ImageFormat::detect("a.out", b":00000001FF"), ImageFormat::IntelHex);
+        assert_eq!(ImageFormat::detect("a.out", b"S1130000"), ImageFormat::SRecord);
+        assert_eq!(ImageFormat::detect("a.out", &[1, 0, b'o', b'6', b'5', 0]), ImageFormat::O65);
+        assert_eq!(ImageFormat::detect("GAME.PRG", b""), ImageFormat::Prg);
+        assert_eq!(ImageFormat::detect("display.out", &[0xa9, 0xff]), ImageFormat::Raw);
+    }
+
+    #[test]
+    fn test_intel_hex() {
+        let image = parse(b"\
+            :03800000A9FF8D48\n\
+            :02800300026019\n\
+            :02FFFC00008083\n\
+            :040000050000800077\n\
+            :00000001FF\n", ImageFormat::IntelHex).unwrap();
+        assert_eq!(image, Image {
+            segments: vec![
+                Segment { address: 0x8000, data: vec![0xa9, 0xff, 0x8d, 0x02, 0x60] },
+                Segment { address: 0xfffc, data: vec![0x00, 0x80] },
+            ],
+            entry: Some(0x8000),
+        });
+    }
+
+    #[test]
+    fn test_intel_hex_errors() {
+        let error = parse(b":03800000A9FF8D49\n:00000001FF\n", ImageFormat::IntelHex).unwrap_err();
+        assert_eq!(error, "line 1: Checksum is $49, expected $48");
+        assert!(parse(b":03800000A9FF8D48\n", ImageFormat::IntelHex).is_err());
+        assert!(parse(b":020000040001F9\n:01000000EA15\n:00000001FF\n", ImageFormat::IntelHex)
+            .unwrap_err().contains("outside the 64K address space"));
+        assert!(parse(b":02FFFF00000000\n:00000001FF\n", ImageFormat::IntelHex).is_err());
+        assert!(parse(b":02000004FFFFFC\n:02FFFF00000000\n:00000001FF\n", ImageFormat::IntelHex)
+            .unwrap_err().contains("outside the 64K address space"));
+        assert!(parse(b":04000002FFFFFFFC01\n:01FFFF00EA17\n:00000001FF\n", ImageFormat::IntelHex)
+            .unwrap_err().contains("outside the 64K address space"));
+        assert!(parse(b":01800000007F\n:01800000EA95\n:00000001FF\n", ImageFormat::IntelHex)
+            .unwrap_err().contains("overlaps"));
+    }
+
+    #[test]
+    fn test_s_record() {
+        let image = parse(b"\
+            S00600004844521B\n\
+            S1068000A9FF8D44\n\
+            S1058003026015\n\
+            S5030002FA\n\
+            S90380007C\n", ImageFormat::SRecord).unwrap();
+        assert_eq!(image.segments, vec![Segment { address: 0x8000, data: vec![0xa9, 0xff, 0x8d, 0x02, 0x60] }]);
+        assert_eq!(image.entry, Some(0x8000));
+        let error = parse(b"S1068000A9FF8D45\n", ImageFormat::SRecord).unwrap_err();
+        assert_eq!(error, "line 1: Checksum is $45, expected $44");
+        assert!(parse(b"S2060100000000F8\n", ImageFormat::SRecord).is_err());
+    }
+
+    #[test]
+    fn test_o65() {
+        let mut o65 = vec![0x01, 0x00, b'o', b'6', b'5', 0x00, 0x00, 0x00];
+        // tbase, tlen, dbase, dlen, bbase, blen, zbase, zlen, stack
+        for word in [0x8000u16, 3, 0x0200, 2, 0x0300, 0x10, 0x00, 0x00, 0x00] {
+            o65.extend_from_slice(&word.to_le_bytes());
+        }
+        // One header option, then the end of the options
+        o65.extend_from_slice(&[4, 0, b'h', 0, 0]);
+        o65.extend_from_slice(&[0xa9, 0x01, 0x60, 0x11, 0x22]);
+        let image = parse(&o65, ImageFormat::O65).unwrap();
+        assert_eq!(image.segments, vec![
+            Segment { address: 0x8000, data: vec![0xa9, 0x01, 0x60] },
+            Segment { address: 0x0200, data: vec![0x11, 0x22] },
+        ]);
+        assert_eq!(image.entry, Some(0x8000));
+        o65.pop();
+        assert_eq!(parse(&o65, ImageFormat::O65).unwrap_err(), "o65 file is truncated");
+    }
+
+    #[test]
+    fn test_prg() {
+        let image = parse(&[0x01, 0x08, 0x0b, 0x08], ImageFormat::Prg).unwrap();
+        assert_eq!(image.segments, vec![Segment { address: 0x0801, data: vec![0x0b, 0x08] }]);
+        assert_eq!(image.entry, None);
+        assert!(parse(&[0x01], ImageFormat::Prg).is_err());
+        assert!(parse(&[0xff, 0xff, 0x00, 0x00], ImageFormat::Prg).is_err());
+    }
+}
@@ -0,0 +1,673 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::acia::Acia;
+use crate::bus::{Bus, BusDevice};
+use crate::cpu::{CPU, CpuInputPins, CpuOutputPins, DebugPort, Registers, Variant};
+use crate::display::{Display, DisplayInputPins, DisplayOutputPins};
+use crate::input::MachineInput;
+use crate::keyboard::Keyboard;
+use crate::lcd_wiring::{LcdWiring, Port};
+use crate::leds::{PortLeds, SevenSegment};
+use crate::memory_map::MemoryMap;
+use crate::ps2::Ps2Keyboard;
+use crate::serial::SerialLink;
+use crate::via::{InterruptLine, Via};
+
+/// Looks at every cycle on the bus, after the devices answered and before
+/// the CPU sees the input pins.
+pub trait BusObserver: Send {
+    fn cycle(&mut self, cycle: u64, output: &CpuOutputPins, input: &CpuInputPins);
+}
+
+impl<F: FnMut(u64, &CpuOutputPins, &CpuInputPins) + Send> BusObserver for F {
+    fn cycle(&mut self, cycle: u64, output: &CpuOutputPins, input: &CpuInputPins) {
+        self(cycle, output, input)
+    }
+}
+
+// The LCD on the first VIA, its controller runs on a thread of its own
+struct LcdLink {
+    wiring: LcdWiring,
+    to_display: Sender<DisplayInputPins>,
+    from_display: Receiver<DisplayOutputPins>,
+    pins: Option<(u8, bool, bool, bool)>, // What the VIA drives, as last sent
+    drive: (u8, u8), // What the LCD drives onto the ports
+    next_event: Option<u64>, // When the controller changes by itself
+}
+
+impl LcdLink {
+    // The controller answers every message
+    fn exchange(&mut self, input_pins: DisplayInputPins) -> DisplayOutputPins {
+        self.to_display.send(input_pins).unwrap();
+        let output_pins: DisplayOutputPins = self.from_display.recv().unwrap();
+        self.next_event = output_pins.next_event;
+        output_pins
+    }
+}
+
+/// A 6502 with the devices on its bus, run one bus cycle at a time. The CPU
+/// runs on a thread of its own and waits for the bus between cycles, so the
+/// machine can be stopped and looked at after any of them.
+///
+/// The bus is built from the memory map, more devices can be attached to
+/// ranges the map leaves free. The LCD, the PS/2 keyboard, the buttons and
+/// the LEDs hang off the ports of the first VIA, the serial link off the
+/// first ACIA and typed characters go to the first keyboard.
+pub struct Machine {
+    to_cpu: Sender<CpuInputPins>,
+    from_cpu: Receiver<CpuOutputPins>,
+    output: CpuOutputPins, // What the CPU drives for the next cycle
+    debug_port: Arc<Mutex<DebugPort>>,
+    bus: Arc<Mutex<Bus>>,
+    frequency: u64, // Nominal clock, used to turn device timings into cycles
+    /// Read from 0xFFFC/0xFFFD instead of the memory in the reset sequence,
+    /// to start at an image's entry point.
+    pub reset_vector: Option<u16>,
+    vector_fetch: bool, // From a reset until the CPU has read the vector
+    lcd: Option<LcdLink>,
+    ps2: Option<Ps2Keyboard>,
+    serial: Option<SerialLink>,
+    leds: Option<(PortLeds, Arc<Mutex<PortLeds>>)>,
+    observers: Vec<Box<dyn BusObserver>>,
+    transmitt_input: Sender<MachineInput>,
+    receive_input: Receiver<MachineInput>,
+    ps2_drive: (u8, u8),
+    buttons: (u8, u8), // Port pins held low by buttons
+    reset_button: bool,
+    reset_until: u64, // RES is held low until this cycle, at power on and after a press
+    nmi_button: bool,
+}
+
+impl Machine {
+    /// Powers on a CPU with the memory map, RES is held low for the first
+    /// cycles like by a reset circuit. `frequency` is the nominal clock in Hz.
+    pub fn new(variant: Variant, memory_map: MemoryMap, frequency: u64) -> Result<Machine, String> {
+        let bus = Bus::from_memory_map(memory_map, frequency)?;
+        let (to_cpu, receive_on_cpu) = mpsc::channel();
+        let (transmitt_from_cpu, from_cpu) = mpsc::channel();
+        let debug_port = Arc::new(Mutex::new(DebugPort::default()));
+        let mut cpu = CPU::new();
+        cpu.variant = variant;
+        cpu.debug_port = Some(debug_port.clone());
+        // Powered on in reset, the bus releases RES after a few cycles
+        cpu.inp.res = false;
+        thread::spawn(move || {
+            cpu.run(receive_on_cpu, transmitt_from_cpu);
+        });
+        let output = from_cpu.recv().map_err(|_| String::from("The CPU did not start"))?;
+        let (transmitt_input, receive_input) = mpsc::channel();
+        Ok(Machine {
+            to_cpu,
+            from_cpu,
+            output,
+            debug_port,
+            bus: Arc::new(Mutex::new(bus)),
+            frequency,
+            reset_vector: None,
+            vector_fetch: true,
+            lcd: None,
+            ps2: None,
+            serial: None,
+            leds: None,
+            observers: Vec::new(),
+            transmitt_input,
+            receive_input,
+            ps2_drive: (0xFF, 0xFF),
+            buttons: (0, 0),
+            reset_button: false,
+            reset_until: 4,
+            nmi_button: false,
+        })
+    }
+
+    /// Wires an HD44780 to the first VIA. Its controller keeps answering the
+    /// bus on a thread of its own, the display is shared for drawing it.
+    pub fn attach_lcd(&mut self, mut display: Display, wiring: LcdWiring) -> Arc<Mutex<Display>> {
+        let (to_display, receive_on_display) = mpsc::channel();
+        let (transmitt_from_display, from_display) = mpsc::channel();
+        // Execution times are counted in cycles of the nominal clock
+        display.frequency = self.frequency;
+        let display = Arc::new(Mutex::new(display));
+        let controller = display.clone();
+        thread::spawn(move || {
+            Display::run(&controller, receive_on_display, transmitt_from_display);
+        });
+        self.lcd = Some(LcdLink { wiring, to_display, from_display, pins: None, drive: (0xFF, 0xFF), next_event: None });
+        display
+    }
+
+    /// Wires a PS/2 keyboard to the first VIA, it takes `MachineInput::Key`.
+    pub fn attach_ps2(&mut self, mut keyboard: Ps2Keyboard) {
+        keyboard.frequency = self.frequency;
+        self.ps2 = Some(keyboard);
+    }
+
+    /// Connects the first ACIA to the host.
+    pub fn attach_serial(&mut self, link: SerialLink) {
+        self.serial = Some(link);
+    }
+
+    /// Puts a LED on every port pin of the first VIA, and the seven-segment
+    /// displays. They are shared for drawing and updated when a pin changes.
+    pub fn attach_leds(&mut self, digits: Vec<SevenSegment>) -> Arc<Mutex<PortLeds>> {
+        let port_leds = PortLeds::new(digits);
+        let shared = Arc::new(Mutex::new(port_leds.clone()));
+        self.leds = Some((port_leds, shared.clone()));
+        shared
+    }
+
+    /// Attaches a device of your own to `size` bytes from `base`, its IRQ
+    /// output wired to `line`. Ranges in the memory map come first.
+    pub fn attach_device(&mut self, base: u16, size: u32, line: InterruptLine, device: impl BusDevice) {
+        self.bus.lock().unwrap().attach(base, size, line, device);
+    }
+
+    pub fn has_keyboard(&self) -> bool {
+        self.ps2.is_some() || self.bus.lock().unwrap().device::<Keyboard>().is_some()
+    }
+
+    pub fn has_serial_port(&self) -> bool {
+        self.bus.lock().unwrap().device::<Acia>().is_some()
+    }
+
+    /// Calls the observer on every cycle from now on.
+    pub fn observe(&mut self, observer: impl BusObserver + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// For frontends on other threads, the inputs are taken at the next cycle.
+    pub fn input_sender(&self) -> Sender<MachineInput> {
+        self.transmitt_input.clone()
+    }
+
+    /// Presses or releases a button, or types a key.
+    pub fn input(&mut self, input: MachineInput) {
+        let bus = self.bus.clone();
+        self.apply_input(&mut bus.lock().unwrap(), input);
+    }
+
+    fn apply_input(&mut self, bus: &mut Bus, input: MachineInput) {
+        match input {
+            MachineInput::Button(pin, pressed) => {
+                let held = match pin.port {
+                    Port::A => &mut self.buttons.0,
+                    Port::B => &mut self.buttons.1,
+                };
+                if pressed {
+                    *held |= 1 << pin.bit;
+                } else {
+                    *held &= !(1 << pin.bit);
+                }
+            }
+            MachineInput::Reset(pressed) => {
+                // However short the press, RES is low long enough to reset
+                if pressed {
+                    self.reset_until = bus.cycles() + 4;
+                }
+                self.reset_button = pressed;
+            }
+            MachineInput::Nmi(pressed) => self.nmi_button = pressed,
+            MachineInput::Char(c) => {
+                if let Some(keyboard) = bus.device_mut::<Keyboard>() {
+                    keyboard.type_char(c);
+                }
+            }
+            MachineInput::Key(key, pressed) => {
+                if let Some(ps2) = self.ps2.as_mut() {
+                    ps2.key(&key, pressed);
+                }
+            }
+        }
+    }
+
+    /// Cycles since power on, as the bus counts them.
+    pub fn cycles(&self) -> u64 {
+        self.bus.lock().unwrap().cycles()
+    }
+
+    /// True when the next cycle fetches an op-code, the machine is between instructions.
+    pub fn sync(&self) -> bool {
+        self.output.sync
+    }
+
+    /// What the CPU drives for the next cycle.
+    pub fn output_pins(&self) -> CpuOutputPins {
+        self.output
+    }
+
+    /// Runs one bus cycle.
+    pub fn cycle(&mut self) {
+        self.run_batch(1);
+    }
+
+    /// Runs cycles up to and including the next one a device is ticked in,
+    /// or `max` cycles. Frontends on other threads can't get at the bus
+    /// during a batch. Returns the cycles run.
+    pub fn run_batch(&mut self, max: u64) -> u64 {
+        let bus = self.bus.clone();
+        let mut bus = bus.lock().unwrap();
+        for ran in 1..=max {
+            let due = self.next_event(&mut bus).is_some_and(|cycle| cycle <= bus.cycles());
+            self.cycle_on(&mut bus);
+            if due {
+                return ran;
+            }
+        }
+        max
+    }
+
+    // The first cycle a device on the bus, the keyboard or the LCD needs
+    fn next_event(&mut self, bus: &mut Bus) -> Option<u64> {
+        let ps2 = match (self.ps2.as_ref(), bus.device::<Via>()) {
+            (Some(ps2), Some(via)) => ps2.next_event(ps2.inhibited(via)),
+            _ => None,
+        };
+        let lcd = self.lcd.as_ref().and_then(|lcd| lcd.next_event);
+        [bus.next_event(), ps2, lcd].into_iter().flatten().min()
+    }
+
+    fn cycle_on(&mut self, bus: &mut Bus) {
+        let output_pins = self.output;
+        let cycles = bus.cycles();
+        if output_pins.sync {
+            bus.sync(output_pins.addr);
+        }
+        let data = if output_pins.rwb {
+            let data = bus.read(output_pins.addr);
+            let vector = self.reset_vector.filter(|_| self.vector_fetch);
+            match (vector, output_pins.addr) {
+                (Some(vector), 0xFFFC) => (vector & 0x00FF) as u8,
+                (Some(vector), 0xFFFD) => {
+                    self.vector_fetch = false;
+                    (vector >> 8) as u8
+                }
+                _ => data,
+            }
+        } else {
+            bus.write(output_pins.addr, output_pins.data);
+            output_pins.data
+        };
+        while let Ok(input) = self.receive_input.try_recv() {
+            self.apply_input(bus, input);
+        }
+        // The reset button pulls RES low for the whole board
+        let reset = cycles <= self.reset_until || self.reset_button;
+        if reset {
+            bus.reset();
+            self.vector_fetch = true;
+        }
+        self.connect_devices(bus, cycles);
+        let (irq, nmi) = bus.tick();
+        self.update_devices(bus, cycles);
+        let input_pins = CpuInputPins {
+            data,
+            irq: !irq,
+            nmi: !(nmi || self.nmi_button),
+            phi2: true,
+            rdy: !bus.rdy(),
+            so: !bus.so(),
+            res: !reset,
+            vdd: true,
+        };
+        for observer in self.observers.iter_mut() {
+            observer.cycle(cycles, &output_pins, &input_pins);
+        }
+        self.to_cpu.send(input_pins).unwrap();
+        self.output = self.from_cpu.recv().unwrap();
+    }
+
+    /// Runs until the CPU is about to fetch the next op-code. Doesn't return
+    /// while the reset button is held.
+    pub fn step_instruction(&mut self) {
+        self.cycle();
+        while !self.sync() {
+            self.cycle();
+        }
+    }
+
+    pub fn run_for(&mut self, mut cycles: u64) {
+        while cycles > 0 {
+            cycles -= self.run_batch(cycles);
+        }
+    }
+
+    /// Pulls RES low, like a short press of the reset button.
+    pub fn reset(&mut self) {
+        self.reset_until = self.cycles() + 4;
+    }
+
+    /// The registers as of the last op-code fetch.
+    pub fn registers(&self) -> Registers {
+        let port = self.debug_port.lock().unwrap();
+        port.load.unwrap_or(port.registers)
+    }
+
+    /// Loads the registers before the next instruction. Between instructions
+    /// the op-code is fetched again from the new PC.
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.debug_port.lock().unwrap().load = Some(registers);
+    }
+
+    /// For frontends that show the registers from another thread.
+    pub fn debug_port(&self) -> Arc<Mutex<DebugPort>> {
+        self.debug_port.clone()
+    }
+
+    /// What RAM or ROM holds at the address, `None` for devices.
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        self.bus.lock().unwrap().peek(addr)
+    }
+
+    /// Stores a byte in RAM or ROM, devices are left alone.
+    pub fn poke(&mut self, addr: u16, value: u8) -> Result<(), String> {
+        self.bus.lock().unwrap().poke(addr, value)
+    }
+
+    /// The bus, shared for frontends on other threads.
+    pub fn bus(&self) -> Arc<Mutex<Bus>> {
+        self.bus.clone()
+    }
+
+    // Drives the inputs of the first VIA and ACIA from what is wired to them.
+    // The devices are only changed when a level does
+    fn connect_devices(&mut self, bus: &mut Bus, cycles: u64) {
+        if let (Some(serial), Some(acia)) = (self.serial.as_mut(), bus.device::<Acia>()) {
+            // The host sends while RTS is asserted, and holds off the ACIA through CTS
+            serial.flush();
+            let byte = if acia.rts() && acia.input.is_empty() { serial.receive() } else { None };
+            let (cts, connected) = (serial.clear_to_send(), serial.connected());
+            if byte.is_some() || (acia.cts, acia.dcd, acia.dsr) != (cts, connected, connected) {
+                let acia = bus.device_mut::<Acia>().unwrap();
+                acia.input.extend(byte);
+                acia.cts = cts;
+                acia.dcd = connected;
+                acia.dsr = connected;
+            }
+        }
+        if let (Some(ps2), Some(via)) = (self.ps2.as_mut(), bus.device::<Via>()) {
+            // Between its events the keyboard only counts
+            ps2.catch_up(cycles);
+            if ps2.next_event(ps2.inhibited(via)).is_some_and(|cycle| cycle <= cycles) {
+                self.ps2_drive = ps2.connect(bus.device_mut::<Via>().unwrap());
+            }
+        }
+        self.drive_ports(bus);
+    }
+
+    // Puts what the LCD, the buttons and the keyboard drive on the VIA's ports
+    fn drive_ports(&self, bus: &mut Bus) {
+        let lcd_drive = self.lcd.as_ref().map_or((0xFF, 0xFF), |lcd| lcd.drive);
+        let inputs = (lcd_drive.0 & !self.buttons.0 & self.ps2_drive.0, lcd_drive.1 & !self.buttons.1 & self.ps2_drive.1);
+        if bus.device::<Via>().is_some_and(|via| (via.port_a_input, via.port_b_input) != inputs) {
+            let via = bus.device_mut::<Via>().unwrap();
+            via.set_port_a_input(inputs.0);
+            via.set_port_b_input(inputs.1);
+        }
+    }
+
+    // Takes what the first ACIA sent and the VIA's new pin levels
+    fn update_devices(&mut self, bus: &mut Bus, cycles: u64) {
+        if bus.device::<Acia>().is_some_and(|acia| !acia.output.is_empty()) {
+            // Lost when nothing is connected
+            for byte in bus.device_mut::<Acia>().unwrap().output.drain(..) {
+                if let Some(serial) = self.serial.as_mut() {
+                    serial.send(byte);
+                }
+            }
+        }
+        if let (Some((port_leds, shared)), Some(via)) = (self.leds.as_mut(), bus.device::<Via>()) {
+            if port_leds.update(via) {
+                *shared.lock().unwrap() = port_leds.clone();
+            }
+        }
+        self.update_lcd(bus, cycles);
+    }
+
+    // Passes the VIA pins on to the LCD when they change, and what it drives back
+    fn update_lcd(&mut self, bus: &mut Bus, cycles: u64) {
+        let (Some(lcd), Some(via)) = (self.lcd.as_mut(), bus.device::<Via>()) else {
+            return;
+        };
+        let pins = lcd.wiring.pins(via.port_a(), via.port_b());
+        if Some(pins) != lcd.pins {
+            let (lcd_data, rs, rwb, e) = pins;
+            let output_pins = lcd.exchange(DisplayInputPins {
+                data: Some(lcd_data),
+                rs: Some(rs),
+                rwb: Some(rwb),
+                e: Some(e),
+                cycle: Some(cycles),
+            });
+            match output_pins.data {
+                // The LCD drives the data lines while E is high
+                Some(data) => lcd.drive = lcd.wiring.drive(data),
+                None if !(e && rwb) => lcd.drive = (0xFF, 0xFF),
+                None => {}
+            }
+            self.drive_ports(bus);
+            if let (Some(lcd), Some(via)) = (self.lcd.as_mut(), bus.device::<Via>()) {
+                lcd.pins = Some(lcd.wiring.pins(via.port_a(), via.port_b()));
+            }
+        } else if lcd.next_event.is_some_and(|cycle| cycle <= cycles) {
+            // The busy flag clears or the cursor blinks, the LCD is told the time
+            lcd.exchange(DisplayInputPins {
+                data: None,
+                rs: None,
+                rwb: None,
+                e: None,
+                cycle: Some(cycles),
+            });
+        }
+    }
+}
+
+impl Drop for Machine {
+    // Powers off: the CPU finishes its instruction and its thread ends
+    fn drop(&mut self) {
+        loop {
+            let input_pins = CpuInputPins {
+                data: 0xEA,
+                irq: true,
+                nmi: true,
+                phi2: true,
+                rdy: true,
+                so: true,
+                res: true,
+                vdd: false,
+            };
+            if self.to_cpu.send(input_pins).is_err() || self.from_cpu.recv().is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::bus::BusDevice;
+    use crate::cpu::{CpuInputPins, CpuOutputPins, Registers, Variant};
+    use crate::input::MachineInput;
+    use crate::lcd_wiring::{Port, PortPin};
+    use crate::machine::Machine;
+    use crate::memory_map::MemoryMap;
+    use crate::via::{InterruptLine, Via};
+
+    // Holds RDY low for two cycles on every read, and notes the op-code fetches
+    struct Slow {
+        waits: Option<u8>,
+        fetches: Vec<u16>,
+    }
+
+    impl BusDevice for Slow {
+        fn read(&mut self, _addr: u16) -> u8 {
+            // A stretched read is repeated, only the first one starts the wait
+            self.waits.get_or_insert(3);
+            0x99
+        }
+
+        fn write(&mut self, _addr: u16, _value: u8) {}
+
+        fn tick(&mut self, _cycles: u64) {
+            self.waits = self.waits.map(|waits| waits - 1).filter(|&waits| waits > 0);
+        }
+
+        fn rdy(&self) -> bool {
+            self.waits.is_some()
+        }
+
+        fn sync(&mut self, addr: u16) {
+            self.fetches.push(addr);
+        }
+    }
+
+    // LDA #$42, STA $0200, INX, JMP $8003 at the reset vector
+    fn machine() -> Machine {
+        let mut memory_map = MemoryMap::default_board();
+        memory_map.load_segment(0x8000, &[0xA9, 0x42, 0x8D, 0x00, 0x02, 0xE8, 0x4C, 0x05, 0x80]).unwrap();
+        memory_map.load_segment(0xFFFC, &[0x00, 0x80]).unwrap();
+        Machine::new(Variant::Nmos6502, memory_map, 1_000_000).unwrap()
+    }
+
+    #[test]
+    fn test_run() {
+        let mut machine = machine();
+        machine.run_for(100);
+        assert_eq!(machine.cycles(), 100);
+        assert_eq!(machine.peek(0x0200), Some(0x42));
+        assert_eq!(machine.registers().a, 0x42);
+        assert!(machine.registers().x > 0);
+        assert_eq!(machine.peek(0x6000), None);
+    }
+
+    #[test]
+    fn test_step_instruction() {
+        let mut machine = machine();
+        machine.step_instruction();
+        while machine.registers().pc != 0x8000 {
+            machine.step_instruction();
+        }
+        assert!(machine.sync());
+        let start = machine.cycles();
+        machine.step_instruction();
+        assert_eq!(machine.cycles() - start, 2);
+        assert_eq!(machine.registers().pc, 0x8002);
+        assert_eq!(machine.registers().a, 0x42);
+        machine.step_instruction();
+        assert_eq!(machine.cycles() - start, 6);
+        assert_eq!(machine.peek(0x0200), Some(0x42));
+    }
+
+    #[test]
+    fn test_set_registers() {
+        let mut machine = machine();
+        machine.run_for(50);
+        while !machine.sync() {
+            machine.cycle();
+        }
+        // Back to the start with X cleared, the STA goes elsewhere
+        machine.poke(0x8004, 0x10).unwrap();
+        let registers = Registers { pc: 0x8000, x: 0, ..machine.registers() };
+        machine.set_registers(registers);
+        assert_eq!(machine.registers(), registers);
+        machine.step_instruction();
+        assert_eq!(machine.registers().pc, 0x8000);
+        assert_eq!(machine.registers().x, 0);
+        machine.step_instruction();
+        machine.step_instruction();
+        assert_eq!(machine.peek(0x1000), Some(0x42));
+        assert_eq!(machine.registers().x, 0);
+        machine.step_instruction();
+        assert_eq!(machine.registers().x, 1);
+    }
+
+    #[test]
+    fn test_observe() {
+        let mut machine = machine();
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let seen = writes.clone();
+        machine.observe(move |_, output: &CpuOutputPins, _: &CpuInputPins| {
+            if !output.rwb {
+                seen.lock().unwrap().push((output.addr, output.data));
+            }
+        });
+        machine.run_for(30);
+        assert!(writes.lock().unwrap().contains(&(0x0200, 0x42)));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut machine = machine();
+        machine.run_for(50);
+        machine.input_sender().send(MachineInput::Button(PortPin { port: Port::A, bit: 0 }, true)).unwrap();
+        machine.reset();
+        machine.step_instruction();
+        assert_eq!(machine.registers().pc, 0x8000);
+        assert_eq!(machine.registers().x, 0);
+        assert_eq!(machine.buttons, (1, 0));
+        machine.input(MachineInput::Button(PortPin { port: Port::A, bit: 0 }, false));
+        assert_eq!(machine.buttons, (0, 0));
+    }
+
+    #[test]
+    fn test_batches() {
+        // Timer 1 free running from $1000, then JMP to itself
+        let mut memory_map = MemoryMap::default_board();
+        memory_map.load_segment(0x8000, &[
+            0xA9, 0x40, 0x8D, 0x0B, 0x60, 0xA9, 0x00, 0x8D, 0x04, 0x60,
+            0xA9, 0x10, 0x8D, 0x05, 0x60, 0x4C, 0x0F, 0x80,
+        ]).unwrap();
+        memory_map.load_segment(0xFFFC, &[0x00, 0x80]).unwrap();
+        let mut machine = Machine::new(Variant::Nmos6502, memory_map, 1_000_000).unwrap();
+        machine.run_for(100);
+        machine.run_batch(0x10000);
+        // Only the timer's timeouts end a batch
+        for _ in 0..3 {
+            assert_eq!(machine.run_batch(0x10000), 0x1002);
+            let bus = machine.bus();
+            let mut bus = bus.lock().unwrap();
+            assert_eq!(bus.device_mut::<Via>().unwrap().read(Via::IFR) & Via::IRQ_T1, Via::IRQ_T1);
+            bus.device_mut::<Via>().unwrap().read(Via::T1C_L);
+        }
+        assert_eq!(machine.run_batch(100), 100);
+    }
+
+    #[test]
+    fn test_reset_vector() {
+        // LDA $FFFD, STA $0200, JMP $8006, the vector in memory points elsewhere
+        let mut memory_map = MemoryMap::default_board();
+        memory_map.load_segment(0x8000, &[0xAD, 0xFD, 0xFF, 0x8D, 0x00, 0x02, 0x4C, 0x06, 0x80]).unwrap();
+        memory_map.load_segment(0xFFFC, &[0x00, 0x90]).unwrap();
+        let mut machine = Machine::new(Variant::Nmos6502, memory_map, 1_000_000).unwrap();
+        machine.reset_vector = Some(0x8000);
+        machine.run_for(50);
+        // Only the reset sequence sees the override
+        assert_eq!(machine.peek(0x0200), Some(0x90));
+        machine.reset();
+        machine.step_instruction();
+        assert_eq!(machine.registers().pc, 0x8000);
+    }
+
+    #[test]
+    fn test_wait_states() {
+        // LDA $5000, JMP $8003
+        let mut memory_map = MemoryMap::parse("ram $0000 $4000\nrom $8000 $8000").unwrap();
+        memory_map.load_segment(0x8000, &[0xAD, 0x00, 0x50, 0x4C, 0x03, 0x80]).unwrap();
+        memory_map.load_segment(0xFFFC, &[0x00, 0x80]).unwrap();
+        let mut machine = Machine::new(Variant::Nmos6502, memory_map, 1_000_000).unwrap();
+        machine.attach_device(0x5000, 1, InterruptLine::Disconnected, Slow { waits: None, fetches: Vec::new() });
+        while machine.registers().pc != 0x8000 {
+            machine.step_instruction();
+        }
+        let start = machine.cycles();
+        machine.step_instruction();
+        assert_eq!(machine.cycles() - start, 6);
+        assert_eq!(machine.registers().a, 0x99);
+        machine.step_instruction();
+        let bus = machine.bus();
+        let bus = bus.lock().unwrap();
+        assert_eq!(bus.device::<Slow>().unwrap().fetches.last(), Some(&0x8003));
+        assert!(!bus.rdy());
+    }
+}
@@ -1,121 +1,181 @@
-use std::{fs, io};
-use std::io::Write;
-use crate::cpu::{CPU, CpuInputPins, CpuOutputPins};
-use crate::display::{Display, DisplayInputPins, DisplayOutputPins};
+use std::{env, fs, process};
+use cpu6502::bus_monitor;
+use cpu6502::cli::{self, Options, Peripheral};
+use cpu6502::clock::{Clock, ClockCommand};
+use cpu6502::cpu::{CpuInputPins, CpuOutputPins};
+use cpu6502::display::Display;
+use cpu6502::input;
+use cpu6502::lcd_renderer::Renderer;
+use cpu6502::loader::{self, ImageFormat};
+use cpu6502::machine::Machine;
+use cpu6502::memory_map::MemoryMap;
+use cpu6502::ps2::Ps2Keyboard;
+use cpu6502::serial::{SerialLink, SerialTarget};
+use cpu6502::terminal;
+use cpu6502::tui::Tui;
 use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
+use std::io::Write;
 
-pub mod cpu;
-mod display;
-mod cgrom;
-
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
 
 fn main() {
-    let mut cpu = CPU::new();
-    let mut display = Display::new();
-    // let mut mem: [u8; 0x10000] = [0; 0x10000];
-    let file_path = "inputs/display.out";
-    let contents: Vec<u8> = fs::read(file_path)
-        .expect("Should have been able to read the file");
-    let boxed_slice: Box<[u8]> = contents.into_boxed_slice();
-    let rom_box: Box<[u8; 0x8000]> = boxed_slice.try_into().unwrap();
-    let rom: [u8; 0x8000] = *rom_box;
-    // println!("{:?}", rom);
-    let mut ram: [u8; 0x8000] = [0; 0x8000];
-    let (transmitt_to_cpu, receive_on_cpu) = mpsc::channel();
-    let (transmitt_from_cpu, receive_from_cpu) = mpsc::channel();
-    let (transmitt_to_disp, receive_on_disp) = mpsc::channel();
-    let (transmitt_from_disp, receive_from_disp) = mpsc::channel();
-    thread::spawn(move || {
-        display.run(receive_on_disp, transmitt_from_disp);
-    });
-    let mut port_a_direction = 0;
-    let mut port_b_direction = 0;
-    thread::spawn(move || {
-        let mut cycles: u64 = 0;
-        let mut data: u8;
-        loop {
-            let output_pins: CpuOutputPins = receive_from_cpu.recv().unwrap();
-            if output_pins.rwb {
-                // Read
-                if output_pins.addr & 0b1000_0000_0000_0000 > 0 {
-                    data = rom[usize::from(output_pins.addr - 0x8000)];
-                } else if output_pins.addr & 0b0100_0000_0000_0000 > 0 &&
-                    output_pins.addr & 0b0010_0000_0000_0000 > 0 &&
-                    output_pins.addr & 0b1000_0000_0000_0000 == 0 {
-                    // bus
-                    data = 0;
-                } else {
-                    data = ram[usize::from(output_pins.addr)];
-                }
-            } else {
-                // Write
-                data = output_pins.data;
-                if output_pins.addr & 0b1000_0000_0000_0000 > 0 {
-                    // ROM
-                } else if output_pins.addr & 0b0100_0000_0000_0000 > 0 &&
-                    output_pins.addr & 0b0010_0000_0000_0000 > 0 &&
-                    output_pins.addr & 0b1000_0000_0000_0000 == 0 {
-                    // VIA
-                    // PORTB = $6000
-                    // PORTA = $6001
-                    // DDRB = $6002
-                    // DDRA = $6003
-                    if output_pins.addr & 0x000F == 0 {
-                        // PORTB
-                        if port_b_direction == 0b1111_1111 {
-                            transmitt_to_disp.send(DisplayInputPins {
-                                data: Option::from(data),
-                                rs: None,
-                                rwb: None,
-                                e: None,
-                            }).unwrap();
-                        } else {
-                            let output_pins: DisplayOutputPins = receive_from_disp.recv().unwrap();
-                            data = output_pins.data;
-                        }
-                    } else if output_pins.addr & 0x000F == 1 {
-                        // PORTA
-                        // E  = %10000000
-                        // RW = %01000000
-                        // RS = %00100000
-                        transmitt_to_disp.send(DisplayInputPins {
-                            data: None,
-                            rs: Option::from(data & 0b0010_0000 > 0),
-                            rwb: Option::from(data & 0b0100_0000 > 0),
-                            e: Option::from(data & 0b1000_0000 > 0),
-                        }).unwrap();
-                    } else if output_pins.addr & 0x000F == 2 {
-                        // DDRB
-                        // Set data direction port B
-                        port_b_direction = data
-                    } else if output_pins.addr & 0x000F == 3 {
-                        // DDRA
-                        // Set data direction port A
-                        port_a_direction = data
+    let options = Options::parse(env::args().skip(1))
+        .unwrap_or_else(|e| exit_with_error(&format!("{}\n\n{}", e, cli::USAGE)));
+    if options.help {
+        print!("{}", cli::USAGE);
+        return;
+    }
+    // Stepping in the terminal shows every cycle, unless the frontend is there
+    let bus_monitor = options.bus_monitor || (options.step && !options.tui);
+    let trace = options.trace;
+    let mut reset_vector = options.reset_vector;
+    let mut clock = Clock::new(options.frequency);
+    let (transmitt_clock_control, receive_clock_control) = mpsc::channel();
+    if options.tui {
+        // The frontend has the keys and shows whether the clock runs
+        clock.set_controls(receive_clock_control, options.step);
+        clock.quiet = true;
+    } else if options.step {
+        clock.set_controls(receive_clock_control, true);
+        clock.print_status();
+        if terminal::is_terminal() && terminal::enable_raw_mode().is_ok() {
+            let keys = terminal::spawn_key_reader();
+            let transmitt_clock_control = transmitt_clock_control.clone();
+            thread::spawn(move || {
+                for key in keys {
+                    if let Some(command) = ClockCommand::from_key(key) {
+                        transmitt_clock_control.send(command).unwrap();
                     }
-                    // blink
-                    //print!("{:#010b}\r", data);
-                    //io::stdout().flush().unwrap();
-                } else {
-                    ram[usize::from(output_pins.addr)] = data;
                 }
+            });
+        }
+    }
+    let mut memory_map = match &options.memory_map {
+        Some(path) => MemoryMap::load(path).unwrap_or_else(|e| exit_with_error(&e)),
+        None => MemoryMap::default_board(),
+    };
+    let rom_path = match (&options.rom, &options.memory_map) {
+        (Some(path), _) => Some(path.as_str()),
+        (None, None) => Some("inputs/display.out"),
+        (None, Some(_)) => None,
+    };
+    if let Some(path) = rom_path {
+        let contents = fs::read(path)
+            .unwrap_or_else(|e| exit_with_error(&format!("Could not read {}: {}", path, e)));
+        let format = options.format.unwrap_or_else(|| ImageFormat::detect(path, &contents));
+        if format == ImageFormat::Raw {
+            let load_address = options.load_address.or(memory_map.rom_base())
+                .unwrap_or_else(|| exit_with_error("The memory map has no ROM, use --load-addr"));
+            memory_map.load_image(load_address, &contents, options.fit)
+                .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", path, e)));
+        } else {
+            let image = loader::parse(&contents, format)
+                .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", path, e)));
+            for segment in &image.segments {
+                memory_map.load_segment(segment.address, &segment.data)
+                    .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", path, e)));
             }
-            transmitt_to_cpu.send(CpuInputPins {
-                data: data,
-                irq: true,
-                nmi: true,
-                phi2: true,
-                rdy: true,
-                res: cycles > 4,
-                vdd: true,
-            }).unwrap();
-            cycles += 1;
-
-            // thread::sleep(Duration::from_millis(10));
+            reset_vector = reset_vector.or(image.entry);
         }
+    }
+    let mut machine = Machine::new(options.cpu, memory_map, options.frequency.unwrap_or(1_000_000))
+        .unwrap_or_else(|e| exit_with_error(&e));
+    machine.reset_vector = reset_vector;
+    let display = options.attached(Peripheral::Lcd).then(|| {
+        let mut display = Display::new();
+        display.busy_warnings = options.lcd_busy_warnings;
+        (display.columns, display.rows) = options.lcd_size;
+        display.rom = options.lcd_font.clone();
+        // The controller keeps answering the bus when there is no window
+        machine.attach_lcd(display, options.lcd_wiring.clone())
     });
-
-    cpu.run(receive_on_cpu, transmitt_from_cpu);
+    let leds = (options.shows_leds() || options.tui).then(|| machine.attach_leds(options.seven_segments.clone()));
+    if options.attached(Peripheral::Ps2) {
+        machine.attach_ps2(Ps2Keyboard::new(options.ps2_wiring));
+    }
+    if options.tui {
+        // Shown by the frontend
+    } else if !options.headless && (display.is_some() || leds.is_some()) {
+        let display = display.clone();
+        let leds = leds.clone();
+        let transmitt_clock_control = transmitt_clock_control.clone();
+        let mut renderer = Renderer::new();
+        renderer.key_map = options.key_map.clone();
+        // Halted, the keys step the clock like in the terminal
+        renderer.clock_keys = options.step;
+        let transmitt_input = machine.input_sender();
+        thread::spawn(move || {
+            renderer.run(display.as_deref(), leds.as_deref(), transmitt_clock_control, transmitt_input);
+        });
+    } else if let Some(leds) = leds.clone() {
+        // Without a window the LEDs are a line in the terminal, redrawn when they change
+        thread::spawn(move || {
+            let mut shown = String::new();
+            loop {
+                let line = leds.lock().unwrap().line();
+                if line != shown {
+                    print!("{}\r", line);
+                    std::io::stdout().flush().unwrap();
+                    shown = line;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+    }
+    // The first ACIA is the serial port. On stdio it takes stdin as it is,
+    // piped or typed in the terminal
+    if options.tui && machine.has_serial_port() && options.serial == SerialTarget::Stdio {
+        exit_with_error("The serial port can't use stdio with --tui, use --serial pty or tcp:<port>");
+    }
+    let serial_on_stdin = machine.has_serial_port() && options.serial == SerialTarget::Stdio && !options.step;
+    if machine.has_serial_port() {
+        machine.attach_serial(SerialLink::open(options.serial, serial_on_stdin).unwrap_or_else(|e| exit_with_error(&e)));
+    }
+    // Typing in the terminal reaches the keyboards, unless the keys step the clock
+    if !serial_on_stdin && machine.has_keyboard() && !options.step && !options.tui
+        && terminal::is_terminal() && terminal::enable_raw_mode().is_ok() {
+        let keys = terminal::spawn_key_reader();
+        let transmitt_input = machine.input_sender();
+        thread::spawn(move || {
+            for key in keys {
+                for input in input::typed(key) {
+                    transmitt_input.send(input).unwrap();
+                }
+            }
+        });
+    }
+    if let (true, Some(leds)) = (options.tui, leds) {
+        // The frontend looks at the registers and memory between cycles
+        let mut tui = Tui::new(display, leds, machine.debug_port(), machine.bus());
+        tui.paused = options.step;
+        let transmitt_input = machine.input_sender();
+        thread::spawn(move || {
+            tui.run(transmitt_clock_control, transmitt_input);
+        });
+    }
+    if trace {
+        machine.observe(|_, output_pins: &CpuOutputPins, input_pins: &CpuInputPins| {
+            if output_pins.sync && output_pins.rwb {
+                println!("{:#06x}: {:#04x}", output_pins.addr, input_pins.data);
+            }
+        });
+    }
+    if bus_monitor {
+        machine.observe(|_, output_pins: &CpuOutputPins, input_pins: &CpuInputPins| {
+            println!("{}", bus_monitor::format_cycle(output_pins, input_pins));
+        });
+    }
+    loop {
+        // The machine runs in batches up to the next look at the wall clock
+        let cycles = clock.cycles_until_sync();
+        if clock.advance(cycles, machine.sync()) && !bus_monitor {
+            clock.print_status();
+        }
+        machine.run_for(cycles);
+    }
 }
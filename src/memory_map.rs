@@ -0,0 +1,365 @@
+use std::fs;
+
+// Devices that can be placed in the memory map.
+const DEVICES: [&str; 3] = ["via", "keyboard", "acia"];
+
+/// A device placed in the memory map, with the options given in the config file.
+pub struct DeviceConfig {
+    pub(crate) name: String,
+    pub(crate) options: Vec<(String, String)>,
+}
+
+impl DeviceConfig {
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.options.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+}
+
+/// How an image smaller than the memory it is loaded into is handled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFit {
+    /// The image has to fill the memory from the load address to its end.
+    Exact,
+    /// The rest of the memory keeps its contents, $FF for ROM.
+    Pad,
+    /// The image repeats through the memory, like a small EEPROM with
+    /// unconnected address lines.
+    Mirror,
+}
+
+pub enum RegionKind {
+    Ram,
+    Rom,
+    Device(DeviceConfig),
+}
+
+/// An address range decoded to RAM, ROM or a device.
+///
+/// `mirror` is the size of the block that is actually decoded, the block
+/// repeats through the region like it does with partial address decoding.
+pub struct Region {
+    pub(crate) kind: RegionKind,
+    pub(crate) base: u16,
+    pub(crate) size: u32,
+    pub(crate) mirror: u32,
+    pub(crate) data: Vec<u8>,
+}
+
+impl Region {
+    pub fn contains(&self, addr: u16) -> bool {
+        addr >= self.base && ((addr - self.base) as u32) < self.size
+    }
+
+    pub fn offset(&self, addr: u16) -> usize {
+        ((addr - self.base) as u32 % self.mirror) as usize
+    }
+}
+
+/// The address decoding of a board, read from a config file with one region per line:
+///
+/// ```text
+/// # kind  base   size   options
+/// ram     $0000  16K
+/// via     $6000  $2000  mirror=$10
+/// rom     $8000  32K    file=inputs/display.out
+/// open_bus $ff
+/// ```
+///
+/// Regions are decoded in the order they are listed, the first match wins.
+/// Reads from unmapped addresses return `open_bus`, or the last value seen on
+/// the data bus when it is not set (or set to `last`). A `via` takes `irq=nmi`
+/// or `irq=none` to wire its IRQ output to NMI or to nothing instead of IRQ.
+/// A `keyboard` is an ASCII keyboard with its data at offset 0 and its status
+/// at offset 1, its IRQ output is unconnected unless it is given `irq=irq`.
+/// An `acia` is a 6551 on the serial port from `--serial`, it takes `irq=` like a
+/// `via` and `wdc_bug=yes` for the transmitter of the W65C51N.
+pub struct MemoryMap {
+    pub(crate) regions: Vec<Region>,
+    pub(crate) open_bus: Option<u8>,
+}
+
+/// Parses `$8000`, `0x8000`, `32768` or `32K`.
+pub fn parse_number(value: &str) -> Result<u32, String> {
+    let result = if let Some(hex) = value.strip_prefix('$') {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(kib) = value.strip_suffix('K').or_else(|| value.strip_suffix('k')) {
+        kib.parse::<u32>().ok().and_then(|k| k.checked_mul(1024))
+    } else {
+        value.parse::<u32>().ok()
+    };
+    result.ok_or_else(|| format!("Invalid number: {}", value))
+}
+
+impl MemoryMap {
+    /// The memory map of the Ben Eater 6502 kit as originally decoded in `main.rs`.
+    pub fn default_board() -> MemoryMap {
+        MemoryMap::parse("\
+            ram $0000 $6000\n\
+            via $6000 $2000 mirror=$10\n\
+            rom $8000 $8000\n").unwrap()
+    }
+
+    pub fn load(path: &str) -> Result<MemoryMap, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read memory map {}: {}", path, e))?;
+        MemoryMap::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<MemoryMap, String> {
+        let mut memory_map = MemoryMap {
+            regions: Vec::new(),
+            open_bus: None,
+        };
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            memory_map.parse_line(line).map_err(|e| format!("Memory map line {}: {}", number + 1, e))?;
+        }
+        Ok(memory_map)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields[0] == "open_bus" {
+            self.open_bus = match fields.get(1) {
+                Some(&"last") => None,
+                Some(value) => Some(u8::try_from(parse_number(value)?).map_err(|_| "open_bus must be a byte")?),
+                None => return Err(String::from("open_bus needs a value")),
+            };
+            return Ok(());
+        }
+        if fields.len() < 3 {
+            return Err(format!("Expected <kind> <base> <size>, got '{}'", line));
+        }
+        let base = parse_number(fields[1])?;
+        let size = parse_number(fields[2])?;
+        if size == 0 || base.checked_add(size).is_none_or(|end| end > 0x10000) {
+            return Err(format!("Region {} {} does not fit in the address space", fields[1], fields[2]));
+        }
+        let mut options = Vec::new();
+        for option in &fields[3..] {
+            match option.split_once('=') {
+                Some((key, value)) => options.push((key.to_string(), value.to_string())),
+                None => return Err(format!("Expected key=value, got '{}'", option)),
+            }
+        }
+        let mirror = match options.iter().find(|(key, _)| key == "mirror") {
+            Some((_, value)) => parse_number(value)?,
+            None => size,
+        };
+        if mirror == 0 || mirror > size {
+            return Err(format!("Mirror size must be between 1 and the region size, got {}", mirror));
+        }
+        let file = options.iter().find(|(key, _)| key == "file").map(|(_, value)| value.clone());
+        let kind = match fields[0] {
+            "ram" => RegionKind::Ram,
+            "rom" => RegionKind::Rom,
+            name if DEVICES.contains(&name) => RegionKind::Device(DeviceConfig {
+                name: name.to_string(),
+                options,
+            }),
+            name => return Err(format!("Unknown region kind: {}", name)),
+        };
+        let fill = if let RegionKind::Rom = kind { 0xFF } else { 0x00 };
+        let mut region = Region {
+            kind,
+            base: base as u16,
+            size,
+            mirror,
+            data: Vec::new(),
+        };
+        if !matches!(region.kind, RegionKind::Device(_)) {
+            region.data = vec![fill; mirror as usize];
+            if let Some(path) = file {
+                let contents = fs::read(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+                if contents.len() != region.data.len() {
+                    return Err(format!("{} is {} bytes, expected {}", path, contents.len(), region.data.len()));
+                }
+                region.data = contents;
+            }
+        }
+        self.regions.push(region);
+        Ok(())
+    }
+
+    /// The address of the first ROM region, where images are loaded by default.
+    pub fn rom_base(&self) -> Option<u16> {
+        self.regions.iter().find(|region| matches!(region.kind, RegionKind::Rom)).map(|region| region.base)
+    }
+
+    /// Copies `image` into the RAM or ROM region at `addr`.
+    pub fn load_image(&mut self, addr: u16, image: &[u8], fit: ImageFit) -> Result<(), String> {
+        let (index, offset) = self.decode(addr).ok_or(format!("Nothing is mapped at ${:04x}", addr))?;
+        let region = &mut self.regions[index];
+        if let RegionKind::Device(device) = &region.kind {
+            return Err(format!("Can't load an image into the {} at ${:04x}", device.name, addr));
+        }
+        let space = region.data.len() - offset;
+        if image.is_empty() || image.len() > space {
+            return Err(format!("Image is {} bytes, but only {} bytes fit at ${:04x}", image.len(), space, addr));
+        }
+        match fit {
+            ImageFit::Exact if image.len() != space => {
+                return Err(format!("Image is {} bytes, but the memory at ${:04x} is {} bytes \
+                    (use --pad or --mirror for smaller images)", image.len(), addr, space));
+            }
+            ImageFit::Mirror => {
+                for (i, byte) in region.data[offset..].iter_mut().enumerate() {
+                    *byte = image[i % image.len()];
+                }
+            }
+            _ => region.data[offset..offset + image.len()].copy_from_slice(image),
+        }
+        Ok(())
+    }
+
+    /// Copies `data` to `addr` onwards, which may span several RAM or ROM regions.
+    pub fn load_segment(&mut self, addr: u16, data: &[u8]) -> Result<(), String> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let end = addr as u32 + data.len() as u32 - 1;
+        if end > 0xFFFF {
+            return Err(format!("Data at ${:04x}-${:x} runs past $ffff", addr, end));
+        }
+        for (i, byte) in data.iter().enumerate() {
+            let target = addr + i as u16;
+            let (index, offset) = self.decode(target)
+                .ok_or(format!("Data at ${:04x}-${:04x} is not mapped at ${:04x}", addr, end, target))?;
+            let region = &mut self.regions[index];
+            if let RegionKind::Device(device) = &region.kind {
+                return Err(format!("Data at ${:04x}-${:04x} overlaps the {} at ${:04x}", addr, end, device.name, target));
+            }
+            region.data[offset] = *byte;
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the region decoding `addr`, and the offset into it.
+    pub fn decode(&self, addr: u16) -> Option<(usize, usize)> {
+        self.regions.iter()
+            .position(|region| region.contains(addr))
+            .map(|index| (index, self.regions[index].offset(addr)))
+    }
+
+    /// The RAM or ROM byte at `addr`, for debuggers. Devices are not read,
+    /// that could change their state.
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        let (index, offset) = self.decode(addr)?;
+        match self.regions[index].kind {
+            RegionKind::Ram | RegionKind::Rom => Some(self.regions[index].data[offset]),
+            RegionKind::Device(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory_map::{ImageFit, MemoryMap, RegionKind, parse_number};
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("$8000"), Ok(0x8000));
+        assert_eq!(parse_number("0x10"), Ok(0x10));
+        assert_eq!(parse_number("16K"), Ok(0x4000));
+        assert_eq!(parse_number("100"), Ok(100));
+        assert!(parse_number("$zz").is_err());
+        assert_eq!(parse_number("4194303K"), Ok(0xFFFF_FC00));
+        assert!(parse_number("4194304K").is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        let memory_map = MemoryMap::parse("\
+            # 16K RAM / 32K ROM kit\n\
+            ram $0000 16K\n\
+            via $6000 $2000 mirror=$10 # VIA\n\
+            rom $8000 32K\n\
+            open_bus $ea\n").unwrap();
+        assert_eq!(memory_map.regions.len(), 3);
+        assert_eq!(memory_map.open_bus, Some(0xea));
+        assert_eq!(memory_map.decode(0x3fff), Some((0, 0x3fff)));
+        assert_eq!(memory_map.decode(0x4000), None);
+        assert_eq!(memory_map.decode(0x6000), Some((1, 0)));
+        assert_eq!(memory_map.decode(0x7ff3), Some((1, 3)));
+        assert_eq!(memory_map.decode(0xfffc), Some((2, 0x7ffc)));
+        assert_eq!(memory_map.regions[2].data[0], 0xff);
+        assert!(matches!(memory_map.regions[1].kind, RegionKind::Device(ref device) if device.option("mirror") == Some("$10")));
+        assert_eq!(memory_map.peek(0xfffc), Some(0xff));
+        assert_eq!(memory_map.peek(0x6000), None);
+        assert_eq!(memory_map.peek(0x5000), None);
+    }
+
+    #[test]
+    fn test_first_region_wins() {
+        let memory_map = MemoryMap::parse("\
+            via $6000 $10\n\
+            ram $0000 $8000\n").unwrap();
+        assert_eq!(memory_map.decode(0x6001), Some((0, 1)));
+        assert_eq!(memory_map.decode(0x6010), Some((1, 0x6010)));
+        assert_eq!(memory_map.open_bus, None);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(MemoryMap::parse("ram $0000").is_err());
+        assert!(MemoryMap::parse("ram $8000 $8001").is_err());
+        assert!(MemoryMap::parse("ram 0xFFFFFFFF 2").is_err());
+        assert!(MemoryMap::parse("ram $0000 4194304K").is_err());
+        assert!(MemoryMap::parse("via $6000 $2000 mirror=4194304K").is_err());
+        assert!(MemoryMap::parse("ram $0000 $100 mirror=$200").is_err());
+        assert!(MemoryMap::parse("ram $0000 $100 mirror").is_err());
+        assert!(MemoryMap::parse("tape $0000 $100").is_err());
+        assert!(MemoryMap::parse("open_bus $100").is_err());
+        assert!(MemoryMap::parse("rom $8000 $8000 file=does/not/exist").is_err());
+    }
+
+    #[test]
+    fn test_load_image() {
+        let mut memory_map = MemoryMap::default_board();
+        assert_eq!(memory_map.rom_base(), Some(0x8000));
+        assert!(memory_map.load_image(0x8000, &[0xea; 0x2000], ImageFit::Exact).is_err());
+        assert!(memory_map.load_image(0x6000, &[0xea; 0x10], ImageFit::Pad).is_err());
+        assert!(memory_map.load_image(0xe000, &[0xea; 0x2001], ImageFit::Pad).is_err());
+
+        memory_map.load_image(0xe000, &[0xea; 0x2000], ImageFit::Exact).unwrap();
+        assert_eq!(memory_map.regions[2].data[0x5fff], 0xff);
+        assert_eq!(memory_map.regions[2].data[0x6000], 0xea);
+
+        let mut image = vec![0; 0x2000];
+        image[0x1ffc] = 0x34;
+        memory_map.load_image(0x8000, &image, ImageFit::Mirror).unwrap();
+        assert_eq!(memory_map.regions[2].data[0x1ffc], 0x34);
+        assert_eq!(memory_map.regions[2].data[0x7ffc], 0x34);
+        assert_eq!(memory_map.regions[2].data[0x7ffb], 0x00);
+
+        memory_map.load_image(0x0200, &[1, 2, 3], ImageFit::Pad).unwrap();
+        assert_eq!(memory_map.regions[0].data[0x0202], 3);
+        assert_eq!(memory_map.regions[0].data[0x0203], 0);
+    }
+
+    #[test]
+    fn test_load_segment() {
+        let mut memory_map = MemoryMap::parse("\
+            ram $0000 $4000\n\
+            via $6000 $10\n\
+            rom $8000 $8000\n").unwrap();
+        memory_map.load_segment(0x3fff, &[1]).unwrap();
+        assert_eq!(memory_map.regions[0].data[0x3fff], 1);
+        assert_eq!(memory_map.load_segment(0x3fff, &[1, 2]).unwrap_err(),
+                   "Data at $3fff-$4000 is not mapped at $4000");
+        assert_eq!(memory_map.load_segment(0x5fff, &[1, 2]).unwrap_err(),
+                   "Data at $5fff-$6000 is not mapped at $5fff");
+        assert_eq!(memory_map.load_segment(0x6000, &[1]).unwrap_err(),
+                   "Data at $6000-$6000 overlaps the via at $6000");
+        memory_map.load_segment(0xfffc, &[0x00, 0x80]).unwrap();
+        assert_eq!(memory_map.regions[2].data[0x7ffd], 0x80);
+        memory_map.load_segment(0x0000, &[]).unwrap();
+        assert_eq!(memory_map.load_segment(0xffff, &[1, 2]).unwrap_err(),
+                   "Data at $ffff-$10000 runs past $ffff");
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::VecDeque;
+use crate::lcd_wiring::{Port, PortPin};
+use crate::via::Via;
+
+/// A VIA pin a peripheral can be wired to, a port bit or one of the control lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViaPin {
+    Port(PortPin),
+    Ca1,
+    Ca2,
+    Cb1,
+    Cb2,
+}
+
+impl ViaPin {
+    pub fn parse(value: &str) -> Result<ViaPin, String> {
+        match value.trim().to_lowercase().as_str() {
+            "ca1" => Ok(ViaPin::Ca1),
+            "ca2" => Ok(ViaPin::Ca2),
+            "cb1" => Ok(ViaPin::Cb1),
+            "cb2" => Ok(ViaPin::Cb2),
+            pin => PortPin::parse(pin).map(ViaPin::Port),
+        }
+    }
+
+    /// Drives the pin from outside the VIA. Port pins are pulled low in `port`,
+    /// the levels the peripheral leaves on port A and B.
+    fn drive(&self, via: &mut Via, level: bool, port: &mut (u8, u8)) {
+        match self {
+            ViaPin::Ca1 => via.set_ca1(level),
+            ViaPin::Ca2 => via.set_ca2(level),
+            ViaPin::Cb1 => via.set_cb1(level),
+            ViaPin::Cb2 => via.set_cb2(level),
+            ViaPin::Port(pin) if !level => match pin.port {
+                Port::A => port.0 &= !(1 << pin.bit),
+                Port::B => port.1 &= !(1 << pin.bit),
+            },
+            ViaPin::Port(_) => {}
+        }
+    }
+
+    /// Level the VIA puts on the pin, high when it is not an output.
+    fn level(&self, via: &Via) -> bool {
+        match self {
+            ViaPin::Ca1 => true,
+            ViaPin::Ca2 => via.ca2_output(),
+            ViaPin::Cb1 => via.cb1_output(),
+            ViaPin::Cb2 => via.cb2_output(),
+            ViaPin::Port(pin) => pin.level(via.port_a(), via.port_b()),
+        }
+    }
+}
+
+/// How the keyboard's clock and data reach the VIA.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ps2Lines {
+    /// Shift registers collect each frame and present the byte on a port,
+    /// then `strobe` pulses low.
+    Parallel { port: Port, strobe: ViaPin },
+    /// The clock and data lines go straight to the VIA, data is valid on the
+    /// falling edge of the clock.
+    Serial { clock: ViaPin, data: ViaPin },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ps2Wiring {
+    pub(crate) lines: Ps2Lines,
+    pub(crate) inhibit: Option<ViaPin>, // The 6502 holds it low to stop the keyboard sending
+}
+
+impl Ps2Wiring {
+    /// Bytes on port A with an interrupt on CA1, like the shift register build.
+    pub fn parallel() -> Ps2Wiring {
+        Ps2Wiring {
+            lines: Ps2Lines::Parallel { port: Port::A, strobe: ViaPin::Ca1 },
+            inhibit: None,
+        }
+    }
+
+    /// Clock on CA1 and data on PA0, for firmware that shifts the bits in itself.
+    pub fn serial() -> Ps2Wiring {
+        Ps2Wiring {
+            lines: Ps2Lines::Serial { clock: ViaPin::Ca1, data: ViaPin::Port(PortPin { port: Port::A, bit: 0 }) },
+            inhibit: None,
+        }
+    }
+
+    /// `parallel`, `serial`, or a list like `data=pa,strobe=ca1` or
+    /// `clock=cb1,data=pb0`, optionally with `inhibit=ca2`.
+    pub fn parse(spec: &str) -> Result<Ps2Wiring, String> {
+        match spec.to_lowercase().as_str() {
+            "parallel" => return Ok(Ps2Wiring::parallel()),
+            "serial" => return Ok(Ps2Wiring::serial()),
+            _ => {}
+        }
+        let (mut port, mut strobe, mut clock, mut data, mut inhibit) = (None, None, None, None, None);
+        for assignment in spec.split(',') {
+            let (name, pin) = assignment.trim().split_once('=')
+                .ok_or(format!("Expected line=pin, got '{}'", assignment))?;
+            let pin = pin.trim().to_lowercase();
+            match name.trim().to_lowercase().as_str() {
+                "data" if pin == "pa" => port = Some(Port::A),
+                "data" if pin == "pb" => port = Some(Port::B),
+                "data" => data = Some(ViaPin::parse(&pin)?),
+                "strobe" => strobe = Some(ViaPin::parse(&pin)?),
+                "clock" | "clk" => clock = Some(ViaPin::parse(&pin)?),
+                "inhibit" => inhibit = Some(ViaPin::parse(&pin)?),
+                name => return Err(format!("Unknown PS/2 line: {}", name)),
+            }
+        }
+        let lines = match (port, strobe, clock, data) {
+            (Some(port), Some(strobe), None, None) => Ps2Lines::Parallel { port, strobe },
+            (None, None, Some(clock), Some(data)) => Ps2Lines::Serial { clock, data },
+            _ => return Err(String::from("Wire either data=<port> and strobe, or clock and data=<pin>")),
+        };
+        Ok(Ps2Wiring { lines, inhibit })
+    }
+}
+
+/// Set 2 make codes by the lower case SDL key name. Keys with an E0 prefix
+/// have it in the first byte.
+const SCANCODES: [(&str, [u8; 2]); 102] = [
+    ("a", [0, 0x1C]), ("b", [0, 0x32]), ("c", [0, 0x21]), ("d", [0, 0x23]),
+    ("e", [0, 0x24]), ("f", [0, 0x2B]), ("g", [0, 0x34]), ("h", [0, 0x33]),
+    ("i", [0, 0x43]), ("j", [0, 0x3B]), ("k", [0, 0x42]), ("l", [0, 0x4B]),
+    ("m", [0, 0x3A]), ("n", [0, 0x31]), ("o", [0, 0x44]), ("p", [0, 0x4D]),
+    ("q", [0, 0x15]), ("r", [0, 0x2D]), ("s", [0, 0x1B]), ("t", [0, 0x2C]),
+    ("u", [0, 0x3C]), ("v", [0, 0x2A]), ("w", [0, 0x1D]), ("x", [0, 0x22]),
+    ("y", [0, 0x35]), ("z", [0, 0x1A]),
+    ("0", [0, 0x45]), ("1", [0, 0x16]), ("2", [0, 0x1E]), ("3", [0, 0x26]),
+    ("4", [0, 0x25]), ("5", [0, 0x2E]), ("6", [0, 0x36]), ("7", [0, 0x3D]),
+    ("8", [0, 0x3E]), ("9", [0, 0x46]),
+    ("`", [0, 0x0E]), ("-", [0, 0x4E]), ("=", [0, 0x55]), ("\\", [0, 0x5D]),
+    ("[", [0, 0x54]), ("]", [0, 0x5B]), (";", [0, 0x4C]), ("'", [0, 0x52]),
+    (",", [0, 0x41]), (".", [0, 0x49]), ("/", [0, 0x4A]),
+    ("backspace", [0, 0x66]), ("space", [0, 0x29]), ("tab", [0, 0x0D]),
+    ("capslock", [0, 0x58]), ("return", [0, 0x5A]), ("escape", [0, 0x76]),
+    ("left shift", [0, 0x12]), ("left ctrl", [0, 0x14]), ("left alt", [0, 0x11]),
+    ("left gui", [0xE0, 0x1F]), ("right shift", [0, 0x59]), ("right ctrl", [0xE0, 0x14]),
+    ("right alt", [0xE0, 0x11]), ("right gui", [0xE0, 0x27]), ("application", [0xE0, 0x2F]),
+    ("f1", [0, 0x05]), ("f2", [0, 0x06]), ("f3", [0, 0x04]), ("f4", [0, 0x0C]),
+    ("f5", [0, 0x03]), ("f6", [0, 0x0B]), ("f7", [0, 0x83]), ("f8", [0, 0x0A]),
+    ("f9", [0, 0x01]), ("f10", [0, 0x09]), ("f11", [0, 0x78]), ("f12", [0, 0x07]),
+    ("scrolllock", [0, 0x7E]), ("insert", [0xE0, 0x70]), ("home", [0xE0, 0x6C]),
+    ("pageup", [0xE0, 0x7D]), ("delete", [0xE0, 0x71]), ("end", [0xE0, 0x69]),
+    ("pagedown", [0xE0, 0x7A]), ("up", [0xE0, 0x75]), ("left", [0xE0, 0x6B]),
+    ("down", [0xE0, 0x72]), ("right", [0xE0, 0x74]), ("numlock", [0, 0x77]),
+    ("keypad /", [0xE0, 0x4A]), ("keypad *", [0, 0x7C]), ("keypad -", [0, 0x7B]),
+    ("keypad +", [0, 0x79]), ("keypad enter", [0xE0, 0x5A]), ("keypad .", [0, 0x71]),
+    ("keypad 0", [0, 0x70]), ("keypad 1", [0, 0x69]), ("keypad 2", [0, 0x72]),
+    ("keypad 3", [0, 0x7A]), ("keypad 4", [0, 0x6B]), ("keypad 5", [0, 0x73]),
+    ("keypad 6", [0, 0x74]), ("keypad 7", [0, 0x6C]), ("keypad 8", [0, 0x75]),
+    ("keypad 9", [0, 0x7D]),
+];
+
+/// The bytes a PS/2 keyboard sends in scancode set 2 when a key goes down or
+/// up, empty for keys it doesn't have.
+pub fn scancodes(key: &str, pressed: bool) -> Vec<u8> {
+    match (key, pressed) {
+        ("printscreen", true) => return vec![0xE0, 0x12, 0xE0, 0x7C],
+        ("printscreen", false) => return vec![0xE0, 0xF0, 0x7C, 0xE0, 0xF0, 0x12],
+        // Pause sends its make and break codes together and nothing on release
+        ("pause", true) => return vec![0xE1, 0x14, 0x77, 0xE1, 0xF0, 0x14, 0xF0, 0x77],
+        ("pause", false) => return Vec::new(),
+        _ => {}
+    }
+    let Some((_, [prefix, code])) = SCANCODES.iter().find(|(name, _)| *name == key) else {
+        return Vec::new();
+    };
+    let mut bytes = Vec::new();
+    if *prefix != 0 {
+        bytes.push(*prefix);
+    }
+    if !pressed {
+        bytes.push(0xF0);
+    }
+    bytes.push(*code);
+    bytes
+}
+
+/// A PS/2 keyboard sending to the 6502, timed in CPU cycles.
+pub struct Ps2Keyboard {
+    pub(crate) wiring: Ps2Wiring,
+    pub(crate) frequency: u64, // CPU clock, used to turn the PS/2 timing into cycles
+    pub(crate) queue: VecDeque<u8>,
+    frame: Option<(u16, u64)>, // Start, data, parity and stop bits being sent, and cycles into the frame
+    wait: u64, // Cycles until the next byte may start
+    port: u8, // Byte on the port in parallel mode
+    strobe: u64, // Cycles left until the end of the strobe pulse
+    pub(crate) cycles: u64, // The cycle the next tick is for
+}
+
+impl Ps2Keyboard {
+    // The keyboard clocks at about 12.5 kHz
+    pub const BIT_TIME_US: u64 = 80;
+    // Pause between the bytes of a sequence
+    pub const BYTE_GAP_US: u64 = 500;
+    pub const STROBE_US: u64 = 5;
+
+    pub fn new(wiring: Ps2Wiring) -> Ps2Keyboard {
+        Ps2Keyboard {
+            wiring,
+            frequency: 1_000_000,
+            queue: VecDeque::new(),
+            frame: None,
+            wait: 0,
+            port: 0xFF,
+            strobe: 0,
+            cycles: 0,
+        }
+    }
+
+    fn cycles(&self, microseconds: u64) -> u64 {
+        (microseconds * self.frequency / 1_000_000).max(1)
+    }
+
+    pub fn key(&mut self, key: &str, pressed: bool) {
+        self.queue.extend(scancodes(key, pressed));
+    }
+
+    /// Odd parity, start bit low and stop bit high.
+    fn frame(byte: u8) -> u16 {
+        let parity = byte.count_ones().is_multiple_of(2);
+        ((byte as u16) << 1) | ((parity as u16) << 9) | (1 << 10)
+    }
+
+    /// Clock and data line levels, both idle high.
+    fn lines(&self) -> (bool, bool) {
+        match self.frame {
+            Some((frame, cycle)) => {
+                let bit_time = self.cycles(Ps2Keyboard::BIT_TIME_US);
+                let bit = (cycle / bit_time).min(10);
+                // Data changes while the clock is high, the host samples on the falling edge
+                (cycle % bit_time < bit_time / 2, frame & (1 << bit) > 0)
+            }
+            None => (true, true),
+        }
+    }
+
+    fn strobing(&self) -> bool {
+        self.strobe > 0 && self.strobe <= self.cycles(Ps2Keyboard::STROBE_US)
+    }
+
+    /// One CPU cycle. A frame cut short by the host inhibiting the keyboard is
+    /// sent again once it lets go.
+    pub fn tick(&mut self, inhibited: bool) {
+        self.cycles += 1;
+        self.strobe = self.strobe.saturating_sub(1);
+        if let Some((frame, cycle)) = self.frame {
+            if inhibited && matches!(self.wiring.lines, Ps2Lines::Serial { .. }) {
+                self.queue.push_front((frame >> 1) as u8);
+                self.frame = None;
+                return;
+            }
+            if cycle + 1 < 11 * self.cycles(Ps2Keyboard::BIT_TIME_US) {
+                self.frame = Some((frame, cycle + 1));
+                return;
+            }
+            self.frame = None;
+            // The strobe follows a cycle after the byte is on the port
+            self.port = (frame >> 1) as u8;
+            self.strobe = self.cycles(Ps2Keyboard::STROBE_US) + 1;
+            self.wait = self.cycles(Ps2Keyboard::BYTE_GAP_US);
+        } else if self.wait > 0 {
+            self.wait -= 1;
+        } else if !inhibited {
+            if let Some(byte) = self.queue.pop_front() {
+                self.frame = Some((Ps2Keyboard::frame(byte), 0));
+            }
+        }
+    }
+
+    /// The first cycle that needs a tick, with the host inhibiting the
+    /// keyboard or not: a line changes, the strobe starts or ends, a frame
+    /// ends or the next byte may start. `None` while there is nothing to send.
+    pub fn next_event(&self, inhibited: bool) -> Option<u64> {
+        let strobe_cycles = self.cycles(Ps2Keyboard::STROBE_US);
+        let strobe = match self.strobe {
+            0 => None,
+            strobe if strobe > strobe_cycles => Some(strobe - strobe_cycles - 1),
+            strobe => Some(strobe - 1),
+        };
+        let serial = matches!(self.wiring.lines, Ps2Lines::Serial { .. });
+        let frame = match self.frame {
+            Some(_) if inhibited && serial => Some(0),
+            Some((_, cycle)) => {
+                let bit_time = self.cycles(Ps2Keyboard::BIT_TIME_US);
+                let end = 11 * bit_time - 1 - cycle;
+                if serial {
+                    // The clock changes every half bit
+                    let (half, into_bit) = (bit_time / 2, cycle % bit_time);
+                    let edge = if into_bit < half { half - into_bit } else { bit_time - into_bit };
+                    Some((edge - 1).min(end))
+                } else {
+                    Some(end)
+                }
+            }
+            None if inhibited || self.queue.is_empty() => None,
+            None => Some(self.wait),
+        };
+        strobe.into_iter().chain(frame).min().map(|ticks| self.cycles + ticks)
+    }
+
+    /// Runs the keyboard on to `cycles`, over cycles `next_event` has no tick in.
+    pub fn catch_up(&mut self, cycles: u64) {
+        let elapsed = cycles.saturating_sub(self.cycles);
+        self.strobe = self.strobe.saturating_sub(elapsed);
+        match self.frame.as_mut() {
+            Some((_, cycle)) => *cycle += elapsed,
+            None => self.wait = self.wait.saturating_sub(elapsed),
+        }
+        self.cycles = self.cycles.max(cycles);
+    }
+
+    /// Whether the host holds the inhibit line low.
+    pub fn inhibited(&self, via: &Via) -> bool {
+        self.wiring.inhibit.is_some_and(|pin| !pin.level(via))
+    }
+
+    /// Puts the keyboard's lines on the VIA and runs it for a cycle. Returns
+    /// the levels it leaves on port A and B.
+    pub fn connect(&mut self, via: &mut Via) -> (u8, u8) {
+        let inhibited = self.inhibited(via);
+        self.tick(inhibited);
+        let mut port = (0xFF, 0xFF);
+        match self.wiring.lines {
+            Ps2Lines::Parallel { port: Port::A, strobe } => {
+                port.0 = self.port;
+                strobe.drive(via, !self.strobing(), &mut port);
+            }
+            Ps2Lines::Parallel { port: Port::B, strobe } => {
+                port.1 = self.port;
+                strobe.drive(via, !self.strobing(), &mut port);
+            }
+            Ps2Lines::Serial { clock, data } => {
+                let (clock_level, data_level) = self.lines();
+                data.drive(via, data_level, &mut port);
+                clock.drive(via, clock_level, &mut port);
+            }
+        }
+        port
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lcd_wiring::{Port, PortPin};
+    use crate::ps2::{Ps2Keyboard, Ps2Lines, Ps2Wiring, ViaPin, scancodes};
+    use crate::via::Via;
+
+    #[test]
+    fn test_scancodes() {
+        assert_eq!(scancodes("a", true), vec![0x1C]);
+        assert_eq!(scancodes("a", false), vec![0xF0, 0x1C]);
+        assert_eq!(scancodes("up", true), vec![0xE0, 0x75]);
+        assert_eq!(scancodes("up", false), vec![0xE0, 0xF0, 0x75]);
+        assert_eq!(scancodes("pause", true).len(), 8);
+        assert!(scancodes("pause", false).is_empty());
+        assert!(scancodes("audio play", true).is_empty());
+    }
+
+    #[test]
+    fn test_serial_frame() {
+        let mut keyboard = Ps2Keyboard::new(Ps2Wiring::serial());
+        keyboard.key("a", true);
+        let mut bits = Vec::new();
+        let mut clock = true;
+        for _ in 0..1000 {
+            keyboard.tick(false);
+            let (level, data) = keyboard.lines();
+            if clock && !level {
+                bits.push(data);
+            }
+            clock = level;
+        }
+        // Start, 0x1C LSB first, odd parity, stop
+        assert_eq!(bits, vec![false, false, false, true, true, true, false, false, false, false, true]);
+        assert_eq!(keyboard.lines(), (true, true));
+    }
+
+    #[test]
+    fn test_next_event() {
+        let mut keyboard = Ps2Keyboard::new(Ps2Wiring::serial());
+        assert_eq!(keyboard.next_event(false), None);
+        keyboard.key("a", true);
+        // Ticked only on the edges, the frame is the same
+        let mut bits = Vec::new();
+        let mut ticks = 0;
+        while let Some(cycle) = keyboard.next_event(false) {
+            keyboard.catch_up(cycle);
+            let (clock, _) = keyboard.lines();
+            keyboard.tick(false);
+            ticks += 1;
+            let (level, data) = keyboard.lines();
+            if clock && !level {
+                bits.push(data);
+            }
+        }
+        assert_eq!(bits, vec![false, false, false, true, true, true, false, false, false, false, true]);
+        assert!(ticks < 30);
+        assert_eq!(keyboard.lines(), (true, true));
+    }
+
+    #[test]
+    fn test_parallel() {
+        let mut keyboard = Ps2Keyboard::new(Ps2Wiring::parallel());
+        let mut via = Via::new();
+        keyboard.key("a", false);
+        // Interrupt on the falling edge of CA1
+        via.write(Via::IER, 0b1000_0010);
+        via.write(Via::PCR, 0);
+        for _ in 0..880 {
+            assert_eq!(keyboard.connect(&mut via), (0xFF, 0xFF));
+        }
+        let (port_a, _) = keyboard.connect(&mut via);
+        assert_eq!(port_a, 0xF0);
+        assert!(!via.irq());
+        via.set_port_a_input(port_a);
+        keyboard.connect(&mut via);
+        assert!(via.irq());
+        assert_eq!(via.read(Via::ORA), 0xF0);
+        // The second byte follows after the gap and another frame
+        for _ in 0..(500 + 879) {
+            keyboard.connect(&mut via);
+        }
+        assert_eq!(keyboard.connect(&mut via), (0x1C, 0xFF));
+        keyboard.connect(&mut via);
+        assert!(via.irq());
+    }
+
+    #[test]
+    fn test_inhibit() {
+        let mut wiring = Ps2Wiring::serial();
+        wiring.inhibit = Some(ViaPin::Port(PortPin { port: Port::B, bit: 0 }));
+        let mut keyboard = Ps2Keyboard::new(wiring);
+        keyboard.key("a", true);
+        let mut via = Via::new();
+        // PB0 an output driven low
+        via.write(Via::DDRB, 0b0000_0001);
+        via.write(Via::ORB, 0);
+        for _ in 0..2000 {
+            assert_eq!(keyboard.connect(&mut via), (0xFF, 0xFF));
+        }
+        via.write(Via::ORB, 1);
+        keyboard.connect(&mut via);
+        // Start bit on PA0
+        keyboard.connect(&mut via);
+        assert_eq!(keyboard.connect(&mut via), (0xFE, 0xFF));
+        // Inhibiting mid-frame aborts it, the byte is sent again later
+        via.write(Via::ORB, 0);
+        keyboard.connect(&mut via);
+        assert_eq!(keyboard.queue.front(), Some(&0x1C));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ps2Wiring::parse("parallel"), Ok(Ps2Wiring::parallel()));
+        let wiring = Ps2Wiring::parse("data=pb, strobe=cb1, inhibit=ca2").unwrap();
+        assert_eq!(wiring.lines, Ps2Lines::Parallel { port: Port::B, strobe: ViaPin::Cb1 });
+        assert_eq!(wiring.inhibit, Some(ViaPin::Ca2));
+        let wiring = Ps2Wiring::parse("clock=cb1,data=pb7").unwrap();
+        assert_eq!(wiring.lines, Ps2Lines::Serial { clock: ViaPin::Cb1, data: ViaPin::Port(PortPin { port: Port::B, bit: 7 }) });
+        assert!(Ps2Wiring::parse("clock=cb1").is_err());
+        assert!(Ps2Wiring::parse("data=pa,strobe=ca1,clock=cb1").is_err());
+        assert!(Ps2Wiring::parse("data=pa,strobe=ca3").is_err());
+        assert!(Ps2Wiring::parse("reset=pa0").is_err());
+    }
+}
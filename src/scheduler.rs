@@ -0,0 +1,109 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// The time of the machine in cycles since power on, and the cycles devices
+/// asked to be called back at. Devices are known by their number on the bus.
+///
+/// A device has at most one call pending, scheduling it again replaces the
+/// earlier one. Replaced calls stay in the queue and are dropped when they
+/// come up.
+pub struct Scheduler {
+    cycles: u64,
+    events: BinaryHeap<Reverse<(u64, usize)>>,
+    pending: Vec<Option<u64>>, // The call of each device
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler { cycles: 0, events: BinaryHeap::new(), pending: Vec::new() }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Moves on to the next cycle.
+    pub fn advance(&mut self) {
+        self.cycles += 1;
+    }
+
+    /// Calls the device back at `cycle`, or not at all for `None`. A cycle
+    /// that has passed is due right away.
+    pub fn schedule(&mut self, device: usize, cycle: Option<u64>) {
+        if device >= self.pending.len() {
+            self.pending.resize(device + 1, None);
+        }
+        if self.pending[device] == cycle {
+            return;
+        }
+        self.pending[device] = cycle;
+        if let Some(cycle) = cycle {
+            self.events.push(Reverse((cycle, device)));
+        }
+    }
+
+    /// When the device is called next.
+    pub fn pending(&self, device: usize) -> Option<u64> {
+        self.pending.get(device).copied().flatten()
+    }
+
+    /// The cycle of the earliest call.
+    pub fn next_event(&mut self) -> Option<u64> {
+        while let Some(&Reverse((cycle, device))) = self.events.peek() {
+            if self.pending[device] == Some(cycle) {
+                return Some(cycle);
+            }
+            self.events.pop();
+        }
+        None
+    }
+
+    /// Takes a device whose call is due in the current cycle, the earliest first.
+    pub fn pop_due(&mut self) -> Option<usize> {
+        let cycle = self.next_event()?;
+        if cycle > self.cycles {
+            return None;
+        }
+        let Reverse((_, device)) = self.events.pop()?;
+        self.pending[device] = None;
+        Some(device)
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scheduler::Scheduler;
+
+    #[test]
+    fn test_schedule() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(0, Some(5));
+        scheduler.schedule(1, Some(2));
+        scheduler.schedule(2, Some(3));
+        // Moved later, and dropped
+        scheduler.schedule(1, Some(4));
+        scheduler.schedule(2, None);
+        assert_eq!(scheduler.next_event(), Some(4));
+        assert_eq!(scheduler.pending(1), Some(4));
+        assert_eq!(scheduler.pending(2), None);
+        let mut calls = Vec::new();
+        for _ in 0..6 {
+            while let Some(device) = scheduler.pop_due() {
+                calls.push((scheduler.cycles(), device));
+            }
+            scheduler.advance();
+        }
+        assert_eq!(calls, vec![(4, 1), (5, 0)]);
+        assert_eq!(scheduler.next_event(), None);
+        // Late calls are due at once
+        scheduler.schedule(3, Some(1));
+        assert_eq!(scheduler.pop_due(), Some(3));
+        assert_eq!(scheduler.pop_due(), None);
+    }
+}
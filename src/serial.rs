@@ -0,0 +1,347 @@
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::os::fd::FromRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::terminal;
+
+// Bytes the host end buffers before it stops clearing the 6502 to send
+const OUTPUT_BUFFER: usize = 256;
+
+/// Where the host end of the serial port is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SerialTarget {
+    /// The emulator's own stdin and stdout.
+    Stdio,
+    /// A new pseudo-terminal, for minicom, screen or file transfer tools.
+    Pty,
+    /// A raw TCP connection on a localhost port.
+    Tcp(u16),
+    /// The same, speaking telnet so a telnet client goes into character mode.
+    Telnet(u16),
+}
+
+impl SerialTarget {
+    /// `stdio`, `pty`, `tcp:<port>` or `telnet:<port>`.
+    pub fn parse(value: &str) -> Result<SerialTarget, String> {
+        let value = value.trim().to_lowercase();
+        let port = |port: &str| port.parse::<u16>().map_err(|_| format!("Invalid port: {}", port));
+        match value.split_once(':') {
+            None if value == "stdio" => Ok(SerialTarget::Stdio),
+            None if value == "pty" => Ok(SerialTarget::Pty),
+            Some(("tcp", number)) => Ok(SerialTarget::Tcp(port(number)?)),
+            Some(("telnet", number)) => Ok(SerialTarget::Telnet(port(number)?)),
+            _ => Err(format!("Expected stdio, pty, tcp:<port> or telnet:<port>, got '{}'", value)),
+        }
+    }
+}
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TelnetState {
+    Data,
+    Cr,
+    Iac,
+    Option,
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// The server side of the telnet protocol, just enough to pass bytes through.
+/// Negotiation from the client is dropped and Enter arrives as a lone CR.
+pub struct Telnet {
+    state: TelnetState,
+}
+
+impl Default for Telnet {
+    fn default() -> Telnet {
+        Telnet::new()
+    }
+}
+
+impl Telnet {
+    /// Sent on connecting: the server echoes and there are no go-aheads, which
+    /// puts the client in character at a time mode.
+    pub const GREETING: [u8; 6] = [IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD];
+
+    pub fn new() -> Telnet {
+        Telnet { state: TelnetState::Data }
+    }
+
+    /// A byte from the client, the data in it if there is any.
+    pub fn receive(&mut self, byte: u8) -> Option<u8> {
+        match (self.state, byte) {
+            // CR is followed by NUL or LF
+            (TelnetState::Cr, 0 | b'\n') => {
+                self.state = TelnetState::Data;
+                None
+            }
+            (TelnetState::Data | TelnetState::Cr, IAC) => {
+                self.state = TelnetState::Iac;
+                None
+            }
+            (TelnetState::Data | TelnetState::Cr, b'\r') => {
+                self.state = TelnetState::Cr;
+                Some(byte)
+            }
+            (TelnetState::Data | TelnetState::Cr, _) => {
+                self.state = TelnetState::Data;
+                Some(byte)
+            }
+            (TelnetState::Iac, IAC) => {
+                self.state = TelnetState::Data;
+                Some(IAC)
+            }
+            (TelnetState::Iac, WILL..=DONT) => {
+                self.state = TelnetState::Option;
+                None
+            }
+            (TelnetState::Iac, SB) => {
+                self.state = TelnetState::Subnegotiation;
+                None
+            }
+            (TelnetState::Iac | TelnetState::Option, _) => {
+                self.state = TelnetState::Data;
+                None
+            }
+            (TelnetState::Subnegotiation, IAC) => {
+                self.state = TelnetState::SubnegotiationIac;
+                None
+            }
+            (TelnetState::Subnegotiation, _) => None,
+            (TelnetState::SubnegotiationIac, SE) => {
+                self.state = TelnetState::Data;
+                None
+            }
+            (TelnetState::SubnegotiationIac, _) => {
+                self.state = TelnetState::Subnegotiation;
+                None
+            }
+        }
+    }
+
+    /// A byte for the client, with IAC doubled.
+    pub fn escape(byte: u8) -> Vec<u8> {
+        if byte == IAC { vec![IAC, IAC] } else { vec![byte] }
+    }
+}
+
+/// The host end of a serial line. Bytes are moved by threads of their own, so
+/// a slow or absent reader holds up CTS instead of the emulator.
+pub struct SerialLink {
+    connected: Arc<AtomicBool>,
+    input: Receiver<u8>,
+    output: SyncSender<u8>,
+    pending: VecDeque<u8>,
+}
+
+impl SerialLink {
+    /// Opens the host end. Stdin is only read with `read_stdin`, the keys may
+    /// be stepping the clock.
+    pub fn open(target: SerialTarget, read_stdin: bool) -> Result<SerialLink, String> {
+        let connected = Arc::new(AtomicBool::new(true));
+        let (transmitt_input, input) = mpsc::channel();
+        let (output, receive_output) = mpsc::sync_channel(OUTPUT_BUFFER);
+        match target {
+            SerialTarget::Stdio => {
+                if read_stdin {
+                    if terminal::is_terminal() {
+                        terminal::enable_raw_mode()
+                            .map_err(|e| format!("Could not set up the terminal: {}", e))?;
+                    }
+                    let keys = terminal::spawn_key_reader();
+                    thread::spawn(move || {
+                        for key in keys {
+                            if transmitt_input.send(key).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+                spawn_writer(io::stdout(), receive_output);
+            }
+            SerialTarget::Pty => {
+                let (master, name) = open_pty()?;
+                eprintln!("Serial port on {}", name);
+                let reader = master.try_clone().map_err(|e| format!("Could not open a pty: {}", e))?;
+                spawn_reader(reader, transmitt_input, None);
+                spawn_writer(master, receive_output);
+            }
+            SerialTarget::Tcp(port) | SerialTarget::Telnet(port) => {
+                let telnet = matches!(target, SerialTarget::Telnet(_));
+                let listener = TcpListener::bind(("127.0.0.1", port))
+                    .map_err(|e| format!("Could not listen on port {}: {}", port, e))?;
+                eprintln!("Serial port on {}:{}", if telnet { "telnet" } else { "tcp" }, port);
+                connected.store(false, Ordering::SeqCst);
+                let client = Arc::new(Mutex::new(None));
+                let writer_client = client.clone();
+                let connection = connected.clone();
+                // One client at a time, the next one is accepted when it hangs up
+                thread::spawn(move || {
+                    for stream in listener.incoming() {
+                        let Ok(mut stream) = stream else { continue };
+                        if telnet && stream.write_all(&Telnet::GREETING).is_err() {
+                            continue;
+                        }
+                        let Ok(writer) = stream.try_clone() else { continue };
+                        *client.lock().unwrap() = Some(writer);
+                        connection.store(true, Ordering::SeqCst);
+                        spawn_reader(stream, transmitt_input.clone(), telnet.then(Telnet::new)).join().unwrap();
+                        connection.store(false, Ordering::SeqCst);
+                        *client.lock().unwrap() = None;
+                    }
+                });
+                thread::spawn(move || {
+                    for byte in receive_output {
+                        let mut client = writer_client.lock().unwrap();
+                        if let Some(stream) = client.as_mut() {
+                            let bytes = if telnet { Telnet::escape(byte) } else { vec![byte] };
+                            if stream.write_all(&bytes).is_err() {
+                                *client = None;
+                            }
+                        }
+                    }
+                });
+            }
+        }
+        Ok(SerialLink { connected, input, output, pending: VecDeque::new() })
+    }
+
+    /// Something is there to talk to, drives DCD and DSR.
+    pub fn connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// CTS: connected, and the host has taken everything sent so far.
+    pub fn clear_to_send(&self) -> bool {
+        self.connected() && self.pending.is_empty()
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        self.pending.push_back(byte);
+        self.flush();
+    }
+
+    /// Hands what is waiting to the host, called every cycle.
+    pub fn flush(&mut self) {
+        while let Some(&byte) = self.pending.front() {
+            match self.output.try_send(byte) {
+                Ok(()) | Err(TrySendError::Disconnected(_)) => { self.pending.pop_front(); }
+                Err(TrySendError::Full(_)) => break,
+            }
+        }
+    }
+
+    /// The next byte from the host, if it has sent one.
+    pub fn receive(&self) -> Option<u8> {
+        self.input.try_recv().ok()
+    }
+}
+
+fn spawn_reader(mut reader: impl Read + Send + 'static, input: Sender<u8>, mut telnet: Option<Telnet>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0u8; 256];
+        while let Ok(count @ 1..) = reader.read(&mut buf) {
+            for &byte in &buf[..count] {
+                let byte = match telnet.as_mut() {
+                    Some(telnet) => telnet.receive(byte),
+                    None => Some(byte),
+                };
+                if let Some(byte) = byte {
+                    if input.send(byte).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn spawn_writer(mut writer: impl Write + Send + 'static, output: Receiver<u8>) {
+    thread::spawn(move || {
+        while let Ok(byte) = output.recv() {
+            // Write out whatever else is already waiting in one go
+            let mut bytes = vec![byte];
+            bytes.extend(output.try_iter());
+            if writer.write_all(&bytes).and_then(|_| writer.flush()).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Creates a pseudo-terminal in raw mode and returns its master side and the
+/// name of the device to connect to. The emulator keeps the other side open
+/// as well, so nothing is lost while no program has it open.
+fn open_pty() -> Result<(File, String), String> {
+    let error = || format!("Could not open a pty: {}", io::Error::last_os_error());
+    unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        if master < 0 {
+            return Err(error());
+        }
+        let master_file = File::from_raw_fd(master);
+        if libc::grantpt(master) != 0 || libc::unlockpt(master) != 0 {
+            return Err(error());
+        }
+        let mut name = [0 as libc::c_char; 64];
+        if libc::ptsname_r(master, name.as_mut_ptr(), name.len()) != 0 {
+            return Err(error());
+        }
+        let name = CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+        let mut mode: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(master, &mut mode) != 0 {
+            return Err(error());
+        }
+        libc::cfmakeraw(&mut mode);
+        if libc::tcsetattr(master, libc::TCSANOW, &mode) != 0 {
+            return Err(error());
+        }
+        let slave = File::options().read(true).write(true).open(&name)
+            .map_err(|e| format!("Could not open {}: {}", name, e))?;
+        std::mem::forget(slave);
+        Ok((master_file, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::serial::{SerialTarget, Telnet};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(SerialTarget::parse("stdio"), Ok(SerialTarget::Stdio));
+        assert_eq!(SerialTarget::parse("PTY"), Ok(SerialTarget::Pty));
+        assert_eq!(SerialTarget::parse("tcp:6551"), Ok(SerialTarget::Tcp(6551)));
+        assert_eq!(SerialTarget::parse("telnet:2323"), Ok(SerialTarget::Telnet(2323)));
+        assert!(SerialTarget::parse("tcp").is_err());
+        assert!(SerialTarget::parse("tcp:70000").is_err());
+        assert!(SerialTarget::parse("udp:1234").is_err());
+    }
+
+    #[test]
+    fn test_telnet() {
+        let mut telnet = Telnet::new();
+        let received: Vec<u8> = [
+            255, 253, 1, // DO ECHO
+            b'h', b'i', b'\r', 0, // Enter
+            255, 250, 31, 0, 80, 0, 24, 255, 240, // Window size
+            255, 255, b'\r', b'\n', b'\r', b'x',
+        ].iter().filter_map(|&byte| telnet.receive(byte)).collect();
+        assert_eq!(received, vec![b'h', b'i', b'\r', 255, b'\r', b'\r', b'x']);
+        assert_eq!(Telnet::escape(255), vec![255, 255]);
+        assert_eq!(Telnet::escape(b'a'), vec![b'a']);
+    }
+}
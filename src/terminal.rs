@@ -0,0 +1,85 @@
+use std::io::Read;
+use std::sync::mpsc::{Receiver, Sender};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::{io, process, thread};
+
+static ORIGINAL_MODE: Mutex<Option<libc::termios>> = Mutex::new(None);
+static FULL_SCREEN: AtomicBool = AtomicBool::new(false);
+
+pub const CTRL_C: u8 = 0x03;
+
+pub fn is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
+/// Puts the host terminal in non-canonical mode without echo, so single key
+/// presses can be read from stdin. Signal generation is turned off as well,
+/// Ctrl-C is handled by `spawn_key_reader` so the terminal is always restored.
+pub fn enable_raw_mode() -> io::Result<()> {
+    let mut original = ORIGINAL_MODE.lock().unwrap();
+    if original.is_some() {
+        return Ok(());
+    }
+    unsafe {
+        let mut mode: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut mode) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        *original = Some(mode);
+        mode.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        mode.c_cc[libc::VMIN] = 1;
+        mode.c_cc[libc::VTIME] = 0;
+        if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &mode) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Switches to the alternate screen with the cursor hidden, for a full-screen
+/// frontend. `restore_mode` switches back.
+pub fn enter_full_screen() {
+    FULL_SCREEN.store(true, Ordering::SeqCst);
+    print!("\x1b[?1049h\x1b[?25l");
+    io::stdout().flush().unwrap();
+}
+
+pub fn restore_mode() {
+    if FULL_SCREEN.swap(false, Ordering::SeqCst) {
+        print!("\x1b[?25h\x1b[?1049l");
+        io::stdout().flush().unwrap();
+    }
+    if let Some(mode) = ORIGINAL_MODE.lock().unwrap().take() {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &mode);
+        }
+    }
+}
+
+/// Restores the terminal and ends the emulator.
+pub fn exit(code: i32) -> ! {
+    restore_mode();
+    println!();
+    process::exit(code);
+}
+
+/// Reads key presses from stdin on a separate thread.
+/// Ctrl-C ends the emulator.
+pub fn spawn_key_reader() -> Receiver<u8> {
+    let (transmitt_key, receive_key): (Sender<u8>, Receiver<u8>) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buf = [0u8; 1];
+        while let Ok(1) = stdin.read(&mut buf) {
+            if buf[0] == CTRL_C {
+                exit(0);
+            }
+            if transmitt_key.send(buf[0]).is_err() {
+                break;
+            }
+        }
+    });
+    receive_key
+}
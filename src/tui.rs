@@ -0,0 +1,317 @@
+use std::io::{self, Write};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::clock::ClockCommand;
+use crate::cpu::DebugPort;
+use crate::disassembler;
+use crate::display::{Display, Frame};
+use crate::input::{self, MachineInput};
+use crate::leds::PortLeds;
+use crate::bus::Bus;
+use crate::terminal;
+
+const REFRESH: Duration = Duration::from_millis(50);
+const DISASSEMBLY_LINES: usize = 10;
+const DISASSEMBLY_WIDTH: usize = 34;
+const MEMORY_ROWS: u16 = 10;
+const MEMORY_ROW_BYTES: u16 = 8;
+// Leaves typing mode, like in telnet
+const CTRL_RIGHT_BRACKET: u8 = 0x1D;
+
+const HELP: &str = "space step  n instruction  p run/pause  r reset  +/- speed  d dots  [ ] memory  i type  q quit";
+
+/// A full-screen frontend in the terminal, for when there is no window: the
+/// LCD, the port LEDs, the registers, a disassembly from PC and a memory
+/// dump, redrawn a few times a second from the running machine.
+pub struct Tui {
+    pub(crate) dots: bool, // The LCD in block characters instead of text
+    pub paused: bool,
+    pub(crate) memory_start: u16,
+    typing: bool, // Keys go to the machine's keyboard until Ctrl-]
+    display: Option<Arc<Mutex<Display>>>,
+    leds: Arc<Mutex<PortLeds>>,
+    debug_port: Arc<Mutex<DebugPort>>,
+    bus: Arc<Mutex<Bus>>,
+}
+
+impl Tui {
+    pub fn new(display: Option<Arc<Mutex<Display>>>, leds: Arc<Mutex<PortLeds>>,
+               debug_port: Arc<Mutex<DebugPort>>, bus: Arc<Mutex<Bus>>) -> Tui {
+        Tui {
+            dots: false,
+            paused: false,
+            memory_start: 0,
+            typing: false,
+            display,
+            leds,
+            debug_port,
+            bus,
+        }
+    }
+
+    /// A character code as text, custom and non-ASCII characters are shaded.
+    fn lcd_char(code: u8) -> char {
+        match code {
+            0x20..=0x7D => code as char,
+            _ => '▒',
+        }
+    }
+
+    /// The dots of the LCD, two rows of dots to a line of half blocks.
+    fn dot_lines(frame: &Frame) -> Vec<String> {
+        let width = frame.width();
+        let dot = |x: usize, y: usize| y < frame.rows as usize * frame.char_height as usize && frame.dots[y * width + x];
+        let mut lines = Vec::new();
+        for row in 0..frame.rows as usize {
+            for pair in (0..frame.char_height as usize).step_by(2) {
+                let (top, bottom) = (row * frame.char_height as usize + pair, row * frame.char_height as usize + pair + 1);
+                let bottom_in_row = pair + 1 < frame.char_height as usize;
+                let mut line = String::new();
+                for x in 0..width {
+                    line.push(match (dot(x, top), bottom_in_row && dot(x, bottom)) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    });
+                    if x % 5 == 4 && x + 1 < width {
+                        line.push(' ');
+                    }
+                }
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    fn lcd_lines(&self, display: &Display) -> Vec<String> {
+        let lines = if self.dots {
+            Tui::dot_lines(&display.frame())
+        } else {
+            let mut text: Vec<String> = display.text().iter()
+                .map(|row| row.iter().map(|&code| Tui::lcd_char(code)).collect())
+                .collect();
+            // Off, the rows are blank
+            let rows = display.frame().rows as usize;
+            text.resize(rows, " ".repeat(display.columns as usize));
+            text
+        };
+        let width = lines.first().map_or(0, |line| line.chars().count());
+        let mut boxed = vec![format!("┌{}┐", "─".repeat(width))];
+        boxed.extend(lines.into_iter().map(|line| format!("│{}│", line)));
+        boxed.push(format!("└{}┘", "─".repeat(width)));
+        boxed
+    }
+
+    /// Everything on the screen, one string per line.
+    pub fn screen(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let state = match (self.typing, self.paused) {
+            (true, _) => "typing, Ctrl-] for the keys below",
+            (false, true) => "paused",
+            (false, false) => "running",
+        };
+        lines.push(format!("cpu6502 - {}", state));
+        if let Some(display) = &self.display {
+            let display = display.lock().unwrap();
+            lines.extend(self.lcd_lines(&display));
+        }
+        lines.push(self.leds.lock().unwrap().line());
+        let registers = self.debug_port.lock().unwrap().registers;
+        lines.push(format!("PC ${:04X}  A ${:02X}  X ${:02X}  Y ${:02X}  SP ${:02X}  P {}",
+                           registers.pc, registers.a, registers.x, registers.y, registers.sp, registers.flags()));
+        lines.push(String::new());
+
+        let bus = self.bus.lock().unwrap();
+        let mut disassembly = Vec::new();
+        let mut address = registers.pc;
+        for line in 0..DISASSEMBLY_LINES {
+            let instruction = disassembler::disassemble(address, |addr| bus.peek(addr));
+            let bytes: Vec<String> = instruction.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            disassembly.push(format!("{} {:04X}  {:<8}  {}", if line == 0 { '>' } else { ' ' },
+                                     address, bytes.join(" "), instruction.text));
+            address = instruction.next();
+        }
+        let mut dump = Vec::new();
+        for row in 0..MEMORY_ROWS {
+            let start = self.memory_start.wrapping_add(row * MEMORY_ROW_BYTES);
+            let bytes: Vec<Option<u8>> = (0..MEMORY_ROW_BYTES).map(|i| bus.peek(start.wrapping_add(i))).collect();
+            let hex: Vec<String> = bytes.iter().map(|byte| byte.map_or(String::from("--"), |byte| format!("{:02X}", byte))).collect();
+            let ascii: String = bytes.iter().map(|byte| match byte {
+                Some(byte @ 0x20..=0x7E) => *byte as char,
+                _ => '.',
+            }).collect();
+            dump.push(format!("{:04X}  {}  {}", start, hex.join(" "), ascii));
+        }
+        for line in 0..DISASSEMBLY_LINES.max(MEMORY_ROWS as usize) {
+            let left = disassembly.get(line).map_or("", String::as_str);
+            let right = dump.get(line).map_or("", String::as_str);
+            lines.push(format!("{:<width$} │ {}", left, right, width = DISASSEMBLY_WIDTH).trim_end().to_string());
+        }
+        lines.push(String::new());
+        lines.push(String::from(HELP));
+        lines
+    }
+
+    /// Acts on a key typed in the terminal. In typing mode it goes to the
+    /// machine instead.
+    fn key(&mut self, key: u8, clock_control: &Sender<ClockCommand>, input: &Sender<MachineInput>) {
+        if self.typing {
+            if key == CTRL_RIGHT_BRACKET {
+                self.typing = false;
+            } else {
+                for machine_input in input::typed(key) {
+                    input.send(machine_input).unwrap();
+                }
+            }
+            return;
+        }
+        let command = match key {
+            b' ' | b's' => Some(ClockCommand::Step),
+            b'n' => Some(ClockCommand::StepInstruction),
+            b'p' | b'h' => {
+                self.paused = !self.paused;
+                Some(ClockCommand::Toggle)
+            }
+            b'+' | b'=' => Some(ClockCommand::Faster),
+            b'-' | b'_' => Some(ClockCommand::Slower),
+            b'r' => {
+                // The bus holds RES low for a few cycles after a press
+                input.send(MachineInput::Reset(true)).unwrap();
+                input.send(MachineInput::Reset(false)).unwrap();
+                None
+            }
+            b'd' => {
+                self.dots = !self.dots;
+                None
+            }
+            b'[' => {
+                self.memory_start = self.memory_start.wrapping_sub(MEMORY_ROWS * MEMORY_ROW_BYTES);
+                None
+            }
+            b']' => {
+                self.memory_start = self.memory_start.wrapping_add(MEMORY_ROWS * MEMORY_ROW_BYTES);
+                None
+            }
+            b'i' => {
+                self.typing = true;
+                None
+            }
+            b'q' => terminal::exit(0),
+            _ => None,
+        };
+        if let Some(command) = command {
+            clock_control.send(command).unwrap();
+        }
+    }
+
+    pub fn run(&mut self, clock_control: Sender<ClockCommand>, input: Sender<MachineInput>) {
+        if terminal::is_terminal() {
+            terminal::enable_raw_mode().unwrap_or_else(|e| {
+                eprintln!("Could not set up the terminal: {}", e);
+                terminal::exit(2);
+            });
+        }
+        terminal::enter_full_screen();
+        let keys = terminal::spawn_key_reader();
+        loop {
+            while let Ok(key) = keys.try_recv() {
+                self.key(key, &clock_control, &input);
+            }
+            let screen = self.screen().join("\x1b[K\r\n");
+            let mut stdout = io::stdout();
+            write!(stdout, "\x1b[H{}\x1b[K\x1b[J", screen).unwrap();
+            stdout.flush().unwrap();
+            thread::sleep(REFRESH);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, mpsc};
+    use crate::clock::ClockCommand;
+    use crate::cpu::{DebugPort, Registers, StatusRegister};
+    use crate::display::{Display, Frame};
+    use crate::input::MachineInput;
+    use crate::leds::PortLeds;
+    use crate::bus::Bus;
+    use crate::memory_map::MemoryMap;
+    use crate::tui::Tui;
+
+    fn tui(display: Option<Display>) -> Tui {
+        let mut memory_map = MemoryMap::default_board();
+        memory_map.load_segment(0x8000, &[0xA9, 0xFF, 0x8D, 0x02, 0x60, 0x6A]).unwrap();
+        memory_map.load_segment(0x0000, b"Hi\x00").unwrap();
+        let registers = Registers { pc: 0x8000, sp: 0xFD, a: 0x50, x: 0, y: 0, p: StatusRegister::from(0b1010_0101) };
+        Tui::new(display.map(|display| Arc::new(Mutex::new(display))), Arc::new(Mutex::new(PortLeds::new(Vec::new()))),
+                 Arc::new(Mutex::new(DebugPort { registers, load: None })), Arc::new(Mutex::new(Bus::from_memory_map(memory_map, 1_000_000).unwrap())))
+    }
+
+    #[test]
+    fn test_screen() {
+        let screen = tui(None).screen();
+        assert_eq!(screen[0], "cpu6502 - running");
+        assert_eq!(screen[1], "PA ········  PB ········");
+        assert_eq!(screen[2], "PC $8000  A $50  X $00  Y $00  SP $FD  P Nv-bdIzC");
+        assert!(screen[4].starts_with("> 8000  A9 FF     LDA #$FF"));
+        assert!(screen[4].ends_with("│ 0000  48 69 00 00 00 00 00 00  Hi......"));
+        assert!(screen[5].starts_with("  8002  8D 02 60  STA $6002"));
+        assert!(screen[6].starts_with("  8005  6A        ROR A"));
+        assert_eq!(screen.last().map(|line| line.starts_with("space step")), Some(true));
+    }
+
+    #[test]
+    fn test_lcd() {
+        let mut display = Display::new();
+        display.display = true;
+        display.n = true;
+        display.drram[0] = b'O';
+        display.drram[1] = b'K';
+        display.drram[2] = 0x00;
+        let mut tui = tui(Some(display));
+        let screen = tui.screen();
+        assert_eq!(screen[1], format!("┌{}┐", "─".repeat(16)));
+        assert_eq!(screen[2], "│OK▒             │");
+        assert_eq!(screen[3], format!("│{}│", " ".repeat(16)));
+        tui.dots = true;
+        let screen = tui.screen();
+        // 8 rows of dots make 4 lines a row, each character is 5 dots and a space
+        assert_eq!(screen[2].chars().count(), 16 * 6 - 1 + 2);
+        assert_eq!(screen[10], format!("└{}┘", "─".repeat(16 * 6 - 1)));
+    }
+
+    #[test]
+    fn test_dot_lines() {
+        let frame = Frame { columns: 2, rows: 1, char_height: 3, dots: vec![
+            true, false, false, false, true, false, false, false, false, false,
+            true, true, false, false, false, false, false, false, false, true,
+            false, true, false, false, false, false, false, false, false, false,
+        ] };
+        assert_eq!(Tui::dot_lines(&frame), vec!["█▄  ▀     ▄", " ▀         "]);
+    }
+
+    #[test]
+    fn test_keys() {
+        let (transmitt_clock_control, receive_clock_control) = mpsc::channel();
+        let (transmitt_input, receive_input) = mpsc::channel();
+        let mut tui = tui(None);
+        for key in b"np]d" {
+            tui.key(*key, &transmitt_clock_control, &transmitt_input);
+        }
+        assert_eq!(receive_clock_control.try_iter().collect::<Vec<_>>(), [ClockCommand::StepInstruction, ClockCommand::Toggle]);
+        assert!(tui.paused && tui.dots);
+        assert_eq!(tui.memory_start, 0x50);
+        tui.key(b'r', &transmitt_clock_control, &transmitt_input);
+        assert_eq!(receive_input.try_iter().collect::<Vec<_>>(), [MachineInput::Reset(true), MachineInput::Reset(false)]);
+        // Typing goes to the machine until Ctrl-]
+        tui.key(b'i', &transmitt_clock_control, &transmitt_input);
+        tui.key(b'q', &transmitt_clock_control, &transmitt_input);
+        assert_eq!(receive_input.try_iter().last(), Some(MachineInput::Char(b'q')));
+        tui.key(0x1D, &transmitt_clock_control, &transmitt_input);
+        tui.key(b'[', &transmitt_clock_control, &transmitt_input);
+        assert_eq!(tui.memory_start, 0);
+    }
+}
@@ -0,0 +1,902 @@
+use crate::bus::BusDevice;
+
+/// CPU line the IRQ output of a device is wired to, `irq=irq|nmi|none` in the memory map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InterruptLine {
+    Irq,
+    Nmi,
+    Disconnected,
+}
+
+impl InterruptLine {
+    pub fn parse(value: &str) -> Result<InterruptLine, String> {
+        match value.to_lowercase().as_str() {
+            "irq" => Ok(InterruptLine::Irq),
+            "nmi" => Ok(InterruptLine::Nmi),
+            "none" => Ok(InterruptLine::Disconnected),
+            _ => Err(format!("Unknown interrupt line: {}", value)),
+        }
+    }
+}
+
+/// W65C22 Versatile Interface Adapter.
+///
+/// The VIA is ticked once per phi2 cycle, or on the bus only in the cycles
+/// `next_event` asks for: in the others the timers just count down, which
+/// `catch_up` does all at once. Devices wired to the ports drive
+/// the pins through `set_port_a_input`/`set_port_b_input` and the control
+/// lines through `set_ca1` and friends, pins that nothing drives read high.
+pub struct Via {
+    pub(crate) ora: u8,
+    pub(crate) orb: u8,
+    pub(crate) ddra: u8,
+    pub(crate) ddrb: u8,
+    // Levels driven onto the ports from outside
+    pub(crate) port_a_input: u8,
+    pub(crate) port_b_input: u8,
+    // Inputs latched on a CA1/CB1 active edge
+    pub(crate) ira_latch: u8,
+    pub(crate) irb_latch: u8,
+
+    pub(crate) t1_counter: u16,
+    pub(crate) t1_latch: u16,
+    pub(crate) t1_armed: bool,
+    pub(crate) t1_reload: bool,
+    pub(crate) pb7: bool,
+    pub(crate) t2_counter: u16,
+    pub(crate) t2_latch_low: u8,
+    pub(crate) t2_armed: bool,
+
+    pub(crate) sr: u8,
+    pub(crate) sr_bits: u8,
+    pub(crate) sr_active: bool,
+    pub(crate) sr_timer: u16,
+
+    pub(crate) acr: u8,
+    pub(crate) pcr: u8,
+    pub(crate) ifr: u8,
+    pub(crate) ier: u8,
+
+    pub(crate) ca1: bool,
+    pub(crate) ca2: bool,
+    pub(crate) cb1: bool,
+    pub(crate) cb2: bool,
+    // Output levels of the control lines when they are outputs
+    pub(crate) ca2_out: bool,
+    pub(crate) cb1_out: bool,
+    pub(crate) cb2_out: bool,
+    // Set for the one cycle a CA2/CB2 pulse output is low
+    pub(crate) ca2_pulse: bool,
+    pub(crate) cb2_pulse: bool,
+    pub(crate) cycles: u64, // The cycle the next tick is for
+}
+
+impl Default for Via {
+    fn default() -> Via {
+        Via::new()
+    }
+}
+
+impl Via {
+    pub const ORB: u8 = 0x0;
+    pub const ORA: u8 = 0x1;
+    pub const DDRB: u8 = 0x2;
+    pub const DDRA: u8 = 0x3;
+    pub const T1C_L: u8 = 0x4;
+    pub const T1C_H: u8 = 0x5;
+    pub const T1L_L: u8 = 0x6;
+    pub const T1L_H: u8 = 0x7;
+    pub const T2C_L: u8 = 0x8;
+    pub const T2C_H: u8 = 0x9;
+    pub const SR: u8 = 0xA;
+    pub const ACR: u8 = 0xB;
+    pub const PCR: u8 = 0xC;
+    pub const IFR: u8 = 0xD;
+    pub const IER: u8 = 0xE;
+    pub const ORA_NO_HANDSHAKE: u8 = 0xF;
+
+    pub const IRQ_CA2: u8 = 0b0000_0001;
+    pub const IRQ_CA1: u8 = 0b0000_0010;
+    pub const IRQ_SR: u8 = 0b0000_0100;
+    pub const IRQ_CB2: u8 = 0b0000_1000;
+    pub const IRQ_CB1: u8 = 0b0001_0000;
+    pub const IRQ_T2: u8 = 0b0010_0000;
+    pub const IRQ_T1: u8 = 0b0100_0000;
+    pub const IRQ_ANY: u8 = 0b1000_0000;
+
+    pub fn new() -> Via {
+        Via {
+            ora: 0,
+            orb: 0,
+            ddra: 0,
+            ddrb: 0,
+            port_a_input: 0xFF,
+            port_b_input: 0xFF,
+            ira_latch: 0,
+            irb_latch: 0,
+            t1_counter: 0xFFFF,
+            t1_latch: 0xFFFF,
+            t1_armed: false,
+            t1_reload: false,
+            pb7: true,
+            t2_counter: 0xFFFF,
+            t2_latch_low: 0xFF,
+            t2_armed: false,
+            sr: 0,
+            sr_bits: 0,
+            sr_active: false,
+            sr_timer: 0,
+            acr: 0,
+            pcr: 0,
+            ifr: 0,
+            ier: 0,
+            ca1: true,
+            ca2: true,
+            cb1: true,
+            cb2: true,
+            ca2_out: true,
+            cb1_out: true,
+            cb2_out: true,
+            ca2_pulse: false,
+            cb2_pulse: false,
+            cycles: 0,
+        }
+    }
+
+    /// RES clears all registers except the timers, latches and shift register.
+    pub fn reset(&mut self) {
+        let timers = (self.t1_counter, self.t1_latch, self.t2_counter, self.t2_latch_low, self.sr);
+        *self = Via {
+            port_a_input: self.port_a_input,
+            port_b_input: self.port_b_input,
+            ca1: self.ca1,
+            ca2: self.ca2,
+            cb1: self.cb1,
+            cb2: self.cb2,
+            cycles: self.cycles,
+            ..Via::new()
+        };
+        (self.t1_counter, self.t1_latch, self.t2_counter, self.t2_latch_low, self.sr) = timers;
+    }
+
+    /// Levels on the port A pins.
+    pub fn port_a(&self) -> u8 {
+        (self.ora & self.ddra) | (self.port_a_input & !self.ddra)
+    }
+
+    /// Levels on the port B pins, PB7 is driven by timer 1 when enabled in the ACR.
+    pub fn port_b(&self) -> u8 {
+        let mut value = (self.orb & self.ddrb) | (self.port_b_input & !self.ddrb);
+        if self.acr & 0b1000_0000 > 0 {
+            value = (value & 0b0111_1111) | if self.pb7 { 0b1000_0000 } else { 0 };
+        }
+        value
+    }
+
+    pub fn set_port_b_input(&mut self, value: u8) {
+        let pb6 = self.port_b() & 0b0100_0000 > 0;
+        self.port_b_input = value;
+        if pb6 && self.port_b() & 0b0100_0000 == 0 && self.acr & 0b0010_0000 > 0 {
+            // Timer 2 counts pulses on PB6
+            self.t2_counter = self.t2_counter.wrapping_sub(1);
+            if self.t2_counter == 0 && self.t2_armed {
+                self.t2_armed = false;
+                self.set_interrupt(Via::IRQ_T2);
+            }
+        }
+    }
+
+    /// True while the IRQ pin is pulled low.
+    pub fn irq(&self) -> bool {
+        self.ifr & self.ier & 0x7F > 0
+    }
+
+    fn set_interrupt(&mut self, flags: u8) {
+        self.ifr |= flags;
+    }
+
+    fn clear_interrupt(&mut self, flags: u8) {
+        self.ifr &= !flags;
+    }
+
+    // PCR control line modes, `control` being the three CA2 or CB2 bits
+    fn independent_interrupt(control: u8) -> bool {
+        control == 0b001 || control == 0b011
+    }
+
+    fn ca2_control(&self) -> u8 {
+        (self.pcr >> 1) & 0b111
+    }
+
+    fn cb2_control(&self) -> u8 {
+        (self.pcr >> 5) & 0b111
+    }
+
+    fn shift_mode(&self) -> u8 {
+        (self.acr >> 2) & 0b111
+    }
+
+    /// Clears CA1, and CA2 unless it is an independent interrupt input, and
+    /// starts a CA2 handshake or pulse.
+    fn port_a_access(&mut self) {
+        self.clear_interrupt(Via::IRQ_CA1);
+        if !Via::independent_interrupt(self.ca2_control()) {
+            self.clear_interrupt(Via::IRQ_CA2);
+        }
+        match self.ca2_control() {
+            0b100 => self.ca2_out = false,
+            0b101 => self.ca2_pulse = true,
+            _ => {}
+        }
+    }
+
+    fn port_b_access(&mut self, write: bool) {
+        self.clear_interrupt(Via::IRQ_CB1);
+        if !Via::independent_interrupt(self.cb2_control()) {
+            self.clear_interrupt(Via::IRQ_CB2);
+        }
+        // CB2 only handshakes on writes
+        if write {
+            match self.cb2_control() {
+                0b100 => self.cb2_out = false,
+                0b101 => self.cb2_pulse = true,
+                _ => {}
+            }
+        }
+    }
+
+    fn start_shift(&mut self) {
+        self.clear_interrupt(Via::IRQ_SR);
+        self.sr_bits = 0;
+        self.sr_active = self.shift_mode() != 0;
+        self.sr_timer = self.t2_latch_low as u16 + 1;
+    }
+
+    pub fn read(&mut self, register: u8) -> u8 {
+        match register & 0x0F {
+            Via::ORB => {
+                self.port_b_access(false);
+                let input = if self.acr & 0b0000_0010 > 0 { self.irb_latch } else { self.port_b() };
+                (self.orb & self.ddrb) | (input & !self.ddrb)
+            }
+            Via::ORA => {
+                self.port_a_access();
+                if self.acr & 0b0000_0001 > 0 { self.ira_latch } else { self.port_a() }
+            }
+            Via::DDRB => self.ddrb,
+            Via::DDRA => self.ddra,
+            Via::T1C_L => {
+                self.clear_interrupt(Via::IRQ_T1);
+                self.t1_counter as u8
+            }
+            Via::T1C_H => (self.t1_counter >> 8) as u8,
+            Via::T1L_L => self.t1_latch as u8,
+            Via::T1L_H => (self.t1_latch >> 8) as u8,
+            Via::T2C_L => {
+                self.clear_interrupt(Via::IRQ_T2);
+                self.t2_counter as u8
+            }
+            Via::T2C_H => (self.t2_counter >> 8) as u8,
+            Via::SR => {
+                let value = self.sr;
+                self.start_shift();
+                value
+            }
+            Via::ACR => self.acr,
+            Via::PCR => self.pcr,
+            Via::IFR => {
+                if self.irq() { self.ifr | Via::IRQ_ANY } else { self.ifr }
+            }
+            Via::IER => self.ier | 0b1000_0000,
+            Via::ORA_NO_HANDSHAKE => {
+                if self.acr & 0b0000_0001 > 0 { self.ira_latch } else { self.port_a() }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register & 0x0F {
+            Via::ORB => {
+                self.port_b_access(true);
+                self.orb = value;
+            }
+            Via::ORA => {
+                self.port_a_access();
+                self.ora = value;
+            }
+            Via::DDRB => self.ddrb = value,
+            Via::DDRA => self.ddra = value,
+            Via::T1C_L | Via::T1L_L => self.t1_latch = (self.t1_latch & 0xFF00) | value as u16,
+            Via::T1C_H => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | (value as u16) << 8;
+                self.t1_counter = self.t1_latch;
+                self.t1_armed = true;
+                self.t1_reload = false;
+                self.clear_interrupt(Via::IRQ_T1);
+                if self.acr & 0b1000_0000 > 0 {
+                    self.pb7 = false;
+                }
+            }
+            Via::T1L_H => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | (value as u16) << 8;
+                self.clear_interrupt(Via::IRQ_T1);
+            }
+            Via::T2C_L => self.t2_latch_low = value,
+            Via::T2C_H => {
+                self.t2_counter = (value as u16) << 8 | self.t2_latch_low as u16;
+                self.t2_armed = true;
+                self.clear_interrupt(Via::IRQ_T2);
+            }
+            Via::SR => {
+                self.sr = value;
+                self.start_shift();
+            }
+            Via::ACR => {
+                let free_run = self.acr & 0b0100_0000 > 0;
+                self.acr = value;
+                if !free_run && value & 0b1100_0000 == 0b1100_0000 {
+                    self.pb7 = true;
+                }
+            }
+            Via::PCR => {
+                self.pcr = value;
+                match self.ca2_control() {
+                    0b110 => self.ca2_out = false,
+                    0b111 | 0b100 | 0b101 => self.ca2_out = true,
+                    _ => {}
+                }
+                match self.cb2_control() {
+                    0b110 => self.cb2_out = false,
+                    0b111 | 0b100 | 0b101 => self.cb2_out = true,
+                    _ => {}
+                }
+            }
+            Via::IFR => self.ifr &= !(value & 0x7F),
+            Via::IER => {
+                if value & 0b1000_0000 > 0 {
+                    self.ier |= value & 0x7F;
+                } else {
+                    self.ier &= !(value & 0x7F);
+                }
+            }
+            Via::ORA_NO_HANDSHAKE => self.ora = value,
+            _ => unreachable!(),
+        }
+    }
+
+    /// One edge of the shift clock. Bits are shifted in on the rising edge, or
+    /// put on CB2 on the falling edge and taken by the receiver on the rising edge.
+    fn shift_edge(&mut self, rising: bool) {
+        if !self.sr_active {
+            return;
+        }
+        let shift_out = self.shift_mode() & 0b100 > 0;
+        if !rising {
+            if shift_out {
+                self.cb2_out = self.sr & 0b1000_0000 > 0;
+                self.sr = self.sr.rotate_left(1);
+            }
+            return;
+        }
+        if !shift_out {
+            self.sr = (self.sr << 1) | if self.cb2 { 1 } else { 0 };
+        }
+        self.sr_bits += 1;
+        if self.sr_bits == 8 {
+            self.sr_bits = 0;
+            // Free running output never stops
+            if self.shift_mode() != 0b100 {
+                self.sr_active = false;
+                self.set_interrupt(Via::IRQ_SR);
+            }
+        }
+    }
+
+    /// Toggles the internal shift clock on CB1.
+    fn shift_clock(&mut self) {
+        self.cb1_out = !self.cb1_out;
+        let rising = self.cb1_out;
+        self.shift_edge(rising);
+        if !self.sr_active {
+            self.cb1_out = true;
+        }
+    }
+
+    /// Advances the VIA one phi2 cycle.
+    pub fn tick(&mut self) {
+        self.ca2_pulse = false;
+        self.cb2_pulse = false;
+
+        // Timer 1
+        if self.t1_reload {
+            self.t1_counter = self.t1_latch;
+            self.t1_reload = false;
+        } else {
+            self.t1_counter = self.t1_counter.wrapping_sub(1);
+            if self.t1_counter == 0xFFFF && self.t1_armed {
+                self.set_interrupt(Via::IRQ_T1);
+                if self.acr & 0b0100_0000 > 0 {
+                    // Free run, reload from the latches and keep going
+                    self.t1_reload = true;
+                    self.pb7 = !self.pb7;
+                } else {
+                    self.t1_armed = false;
+                    self.pb7 = true;
+                }
+            }
+        }
+
+        // Timer 2, unless it is counting PB6 pulses
+        if self.acr & 0b0010_0000 == 0 {
+            self.t2_counter = self.t2_counter.wrapping_sub(1);
+            if self.t2_counter == 0xFFFF && self.t2_armed {
+                self.t2_armed = false;
+                self.set_interrupt(Via::IRQ_T2);
+            }
+        }
+
+        // Shift register
+        match self.shift_mode() {
+            // Under control of timer 2, or free running at the timer 2 rate
+            0b001 | 0b100 | 0b101 if self.sr_active => {
+                if self.sr_timer == 0 {
+                    self.sr_timer = self.t2_latch_low as u16 + 1;
+                    self.shift_clock();
+                } else {
+                    self.sr_timer -= 1;
+                }
+            }
+            // Under control of phi2
+            0b010 | 0b110 if self.sr_active => self.shift_clock(),
+            _ => {}
+        }
+        self.cycles += 1;
+    }
+
+    /// The first cycle that needs a tick: a pulse output ends, an armed timer
+    /// runs out or the shift register is clocked. `None` while the timers
+    /// only count.
+    pub fn next_event(&self) -> Option<u64> {
+        let pulse = self.ca2_pulse || self.cb2_pulse;
+        // A reloading timer 1 starts over from the latches
+        let t1 = if self.t1_reload { self.t1_latch as u64 + 1 } else { self.t1_counter as u64 };
+        let t2_counting = self.t2_armed && self.acr & 0b0010_0000 == 0;
+        let shift = match self.shift_mode() {
+            0b001 | 0b100 | 0b101 if self.sr_active => Some(self.sr_timer as u64),
+            0b010 | 0b110 if self.sr_active => Some(0),
+            _ => None,
+        };
+        // Ticks from now, a counter at n runs out in the n-th
+        [
+            pulse.then_some(0),
+            self.t1_armed.then_some(t1),
+            t2_counting.then_some(self.t2_counter as u64),
+            shift,
+        ].into_iter().flatten().min().map(|ticks| self.cycles + ticks)
+    }
+
+    /// Counts the timers down to `cycles`, over cycles `next_event` has no
+    /// tick in.
+    pub fn catch_up(&mut self, cycles: u64) {
+        let elapsed = cycles.saturating_sub(self.cycles);
+        if elapsed == 0 {
+            return;
+        }
+        // The counters wrap, the unarmed ones without anything happening
+        let mut t1_elapsed = elapsed;
+        if self.t1_reload {
+            self.t1_counter = self.t1_latch;
+            self.t1_reload = false;
+            t1_elapsed -= 1;
+        }
+        self.t1_counter = self.t1_counter.wrapping_sub(t1_elapsed as u16);
+        if self.acr & 0b0010_0000 == 0 {
+            self.t2_counter = self.t2_counter.wrapping_sub(elapsed as u16);
+        }
+        if self.sr_active && matches!(self.shift_mode(), 0b001 | 0b100 | 0b101) {
+            self.sr_timer -= elapsed as u16;
+        }
+        self.cycles = cycles;
+    }
+}
+
+impl BusDevice for Via {
+    fn read(&mut self, addr: u16) -> u8 {
+        Via::read(self, addr as u8)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        Via::write(self, addr as u8, value)
+    }
+
+    fn tick(&mut self, _cycles: u64) {
+        Via::tick(self)
+    }
+
+    fn next_event(&self, _cycles: u64) -> Option<u64> {
+        Via::next_event(self)
+    }
+
+    fn catch_up(&mut self, cycles: u64) {
+        Via::catch_up(self, cycles)
+    }
+
+    fn irq(&self) -> bool {
+        Via::irq(self)
+    }
+
+    fn reset(&mut self) {
+        Via::reset(self)
+    }
+}
+
+impl Via {
+    pub fn set_port_a_input(&mut self, value: u8) {
+        self.port_a_input = value;
+    }
+
+    /// Level of the CA2 pin when it is an output.
+    pub fn ca2_output(&self) -> bool {
+        self.ca2_out && !self.ca2_pulse
+    }
+
+    /// Level of the CB1 pin when it is the shift register clock output.
+    pub fn cb1_output(&self) -> bool {
+        self.cb1_out
+    }
+
+    /// Level of the CB2 pin when it is an output.
+    pub fn cb2_output(&self) -> bool {
+        self.cb2_out && !self.cb2_pulse
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        let positive = self.pcr & 0b0000_0001 > 0;
+        if level != self.ca1 && level == positive {
+            self.set_interrupt(Via::IRQ_CA1);
+            self.ira_latch = self.port_a();
+            // End of a read or write handshake
+            if self.ca2_control() == 0b100 {
+                self.ca2_out = true;
+            }
+        }
+        self.ca1 = level;
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        let control = self.ca2_control();
+        if control & 0b100 == 0 && level != self.ca2 && level == (control & 0b010 > 0) {
+            self.set_interrupt(Via::IRQ_CA2);
+        }
+        self.ca2 = level;
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        let positive = self.pcr & 0b0001_0000 > 0;
+        if level != self.cb1 {
+            if level == positive {
+                self.set_interrupt(Via::IRQ_CB1);
+                self.irb_latch = self.port_b();
+                if self.cb2_control() == 0b100 {
+                    self.cb2_out = true;
+                }
+            }
+            if self.shift_mode() & 0b011 == 0b011 {
+                // Shift register clocked by CB1, in on the rising edge, out on the falling edge
+                self.shift_edge(level);
+            }
+        }
+        self.cb1 = level;
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        let control = self.cb2_control();
+        if control & 0b100 == 0 && level != self.cb2 && level == (control & 0b010 > 0) {
+            self.set_interrupt(Via::IRQ_CB2);
+        }
+        self.cb2 = level;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::via::{InterruptLine, Via};
+
+    #[test]
+    fn test_interrupt_line() {
+        assert_eq!(InterruptLine::parse("NMI"), Ok(InterruptLine::Nmi));
+        assert_eq!(InterruptLine::parse("none"), Ok(InterruptLine::Disconnected));
+        assert!(InterruptLine::parse("firq").is_err());
+    }
+
+    #[test]
+    fn test_ports() {
+        let mut via = Via::new();
+        via.write(Via::DDRB, 0b1111_0000);
+        via.write(Via::ORB, 0b1010_1010);
+        via.set_port_b_input(0b0101_0101);
+        assert_eq!(via.port_b(), 0b1010_0101);
+        assert_eq!(via.read(Via::ORB), 0b1010_0101);
+        // Port A reads the pins, also for outputs
+        via.write(Via::DDRA, 0b1110_0000);
+        via.write(Via::ORA, 0b0110_0000);
+        assert_eq!(via.read(Via::ORA), 0b0111_1111);
+        assert_eq!(via.read(Via::DDRA), 0b1110_0000);
+    }
+
+    #[test]
+    fn test_input_latching() {
+        let mut via = Via::new();
+        via.write(Via::ACR, 0b0000_0001);
+        via.write(Via::PCR, 0b0000_0001);
+        via.set_port_a_input(0x42);
+        via.set_ca1(false);
+        via.set_ca1(true);
+        via.set_port_a_input(0x24);
+        assert_eq!(via.read(Via::ORA), 0x42);
+        via.write(Via::ACR, 0);
+        assert_eq!(via.read(Via::ORA), 0x24);
+    }
+
+    #[test]
+    fn test_ca1_interrupt() {
+        let mut via = Via::new();
+        via.write(Via::IER, 0b1000_0010);
+        assert_eq!(via.read(Via::IER), 0b1000_0010);
+        // Negative edge by default
+        via.set_ca1(true);
+        assert!(!via.irq());
+        via.set_ca1(false);
+        assert!(via.irq());
+        assert_eq!(via.read(Via::IFR), 0b1000_0010);
+        via.read(Via::ORA);
+        assert!(!via.irq());
+        assert_eq!(via.read(Via::IFR), 0);
+        // Disabled interrupts are flagged but don't pull IRQ
+        via.write(Via::IER, 0b0000_0010);
+        via.set_ca1(true);
+        via.set_ca1(false);
+        assert_eq!(via.read(Via::IFR), 0b0000_0010);
+        assert!(!via.irq());
+        via.write(Via::IFR, 0b0000_0010);
+        assert_eq!(via.read(Via::IFR), 0);
+    }
+
+    #[test]
+    fn test_ca2_independent_interrupt() {
+        let mut via = Via::new();
+        via.write(Via::PCR, 0b0000_0100);
+        via.set_ca2(false);
+        assert_eq!(via.read(Via::IFR), 0);
+        via.set_ca2(true);
+        assert_eq!(via.read(Via::IFR), Via::IRQ_CA2);
+        via.read(Via::ORA);
+        assert_eq!(via.read(Via::IFR), 0);
+        // Independent: reading port A leaves the flag alone
+        via.write(Via::PCR, 0b0000_0110);
+        via.set_ca2(false);
+        via.set_ca2(true);
+        via.read(Via::ORA);
+        assert_eq!(via.read(Via::IFR), Via::IRQ_CA2);
+    }
+
+    #[test]
+    fn test_ca2_handshake() {
+        let mut via = Via::new();
+        via.write(Via::PCR, 0b0000_1000);
+        assert!(via.ca2_output());
+        via.write(Via::ORA, 0x12);
+        assert!(!via.ca2_output());
+        via.set_ca1(false);
+        assert!(via.ca2_output());
+        // Pulse mode, low for one cycle
+        via.write(Via::PCR, 0b0000_1010);
+        via.read(Via::ORA);
+        assert!(!via.ca2_output());
+        via.tick();
+        assert!(via.ca2_output());
+        // Manual output
+        via.write(Via::PCR, 0b0000_1100);
+        assert!(!via.ca2_output());
+        via.write(Via::PCR, 0b0000_1110);
+        assert!(via.ca2_output());
+    }
+
+    #[test]
+    fn test_cb2_write_handshake() {
+        let mut via = Via::new();
+        via.write(Via::PCR, 0b1000_0000);
+        via.read(Via::ORB);
+        assert!(via.cb2_output());
+        via.write(Via::ORB, 0x12);
+        assert!(!via.cb2_output());
+        via.set_cb1(false);
+        assert!(via.cb2_output());
+        assert_eq!(via.read(Via::IFR), Via::IRQ_CB1);
+    }
+
+    #[test]
+    fn test_timer_1_one_shot() {
+        let mut via = Via::new();
+        via.write(Via::IER, 0b1100_0000);
+        via.write(Via::T1C_L, 0x03);
+        via.write(Via::T1C_H, 0x00);
+        assert_eq!(via.read(Via::T1C_L), 3);
+        for _ in 0..3 {
+            via.tick();
+            assert!(!via.irq());
+        }
+        assert_eq!(via.read(Via::T1C_L), 0);
+        via.tick();
+        assert!(via.irq());
+        assert_eq!(via.read(Via::T1C_H), 0xFF);
+        // Reading the low counter clears the flag, and one-shot doesn't fire again
+        via.read(Via::T1C_L);
+        assert!(!via.irq());
+        for _ in 0..0x10000 {
+            via.tick();
+        }
+        assert!(!via.irq());
+    }
+
+    #[test]
+    fn test_timer_1_free_run_pb7() {
+        let mut via = Via::new();
+        via.write(Via::DDRB, 0xFF);
+        via.write(Via::ACR, 0b1100_0000);
+        via.write(Via::T1C_L, 0x02);
+        via.write(Via::T1C_H, 0x00);
+        assert_eq!(via.port_b() & 0x80, 0);
+        let mut toggles = Vec::new();
+        let mut pb7 = false;
+        for cycle in 1..=20 {
+            via.tick();
+            if (via.port_b() & 0x80 > 0) != pb7 {
+                pb7 = !pb7;
+                toggles.push(cycle);
+            }
+        }
+        // First timeout after N + 1 cycles, then every N + 2
+        assert_eq!(toggles, vec![3, 7, 11, 15, 19]);
+        assert_eq!(via.read(Via::IFR) & Via::IRQ_T1, Via::IRQ_T1);
+        // Writing the latch doesn't restart the timer
+        via.write(Via::T1L_H, 0x00);
+        assert_eq!(via.read(Via::IFR) & Via::IRQ_T1, 0);
+    }
+
+    #[test]
+    fn test_timer_2() {
+        let mut via = Via::new();
+        via.write(Via::IER, 0b1010_0000);
+        via.write(Via::T2C_L, 0x01);
+        via.write(Via::T2C_H, 0x00);
+        via.tick();
+        assert!(!via.irq());
+        via.tick();
+        assert!(via.irq());
+        via.read(Via::T2C_L);
+        assert!(!via.irq());
+        for _ in 0..0x10000 {
+            via.tick();
+        }
+        assert!(!via.irq());
+    }
+
+    #[test]
+    fn test_timer_2_pulse_counting() {
+        let mut via = Via::new();
+        via.write(Via::ACR, 0b0010_0000);
+        via.write(Via::T2C_L, 0x02);
+        via.write(Via::T2C_H, 0x00);
+        for _ in 0..10 {
+            via.tick();
+        }
+        assert_eq!(via.read(Via::T2C_L), 2);
+        via.set_port_b_input(0b1011_1111);
+        via.set_port_b_input(0xFF);
+        assert_eq!(via.read(Via::IFR), 0);
+        via.set_port_b_input(0b1011_1111);
+        assert_eq!(via.read(Via::IFR), Via::IRQ_T2);
+    }
+
+    #[test]
+    fn test_shift_out_phi2() {
+        let mut via = Via::new();
+        via.write(Via::ACR, 0b0001_1000);
+        via.write(Via::SR, 0b1011_0010);
+        let mut bits = Vec::new();
+        for _ in 0..16 {
+            via.tick();
+            if via.cb1_output() {
+                bits.push(via.cb2_output() as u8);
+            }
+        }
+        assert_eq!(bits, vec![1, 0, 1, 1, 0, 0, 1, 0]);
+        assert_eq!(via.read(Via::IFR), Via::IRQ_SR);
+        via.tick();
+        via.tick();
+        assert_eq!(via.read(Via::SR), 0b1011_0010);
+        assert_eq!(via.read(Via::IFR), 0);
+    }
+
+    #[test]
+    fn test_shift_in_external_clock() {
+        let mut via = Via::new();
+        via.write(Via::ACR, 0b0000_1100);
+        via.read(Via::SR);
+        for bit in [0, 1, 1, 0, 1, 0, 0, 1] {
+            via.set_cb2(bit == 1);
+            via.set_cb1(false);
+            via.set_cb1(true);
+        }
+        assert_eq!(via.read(Via::IFR) & Via::IRQ_SR, Via::IRQ_SR);
+        assert_eq!(via.read(Via::SR), 0b0110_1001);
+    }
+
+    #[test]
+    fn test_shift_in_timer_2() {
+        let mut via = Via::new();
+        via.write(Via::T2C_L, 0x01);
+        via.write(Via::ACR, 0b0000_0100);
+        via.set_cb2(true);
+        via.read(Via::SR);
+        // Each half of the CB1 clock is N + 2 cycles
+        for _ in 0..(8 * 2 * 3 - 1) {
+            via.tick();
+        }
+        assert_eq!(via.ifr & Via::IRQ_SR, 0);
+        via.tick();
+        assert_eq!(via.ifr & Via::IRQ_SR, Via::IRQ_SR);
+        assert_eq!(via.sr, 0xFF);
+    }
+
+    #[test]
+    fn test_next_event() {
+        let mut via = Via::new();
+        assert_eq!(via.next_event(), None);
+        via.write(Via::DDRB, 0xFF);
+        via.write(Via::ACR, 0b1100_0000);
+        via.write(Via::T1C_L, 0x02);
+        via.write(Via::T1C_H, 0x00);
+        // Ticked only when asked, PB7 toggles in the same cycles as when ticked every cycle
+        let mut toggles = Vec::new();
+        while toggles.len() < 5 {
+            via.catch_up(via.next_event().unwrap());
+            let pb7 = via.port_b() & 0x80;
+            via.tick();
+            assert_ne!(via.port_b() & 0x80, pb7);
+            toggles.push(via.cycles);
+        }
+        assert_eq!(toggles, vec![3, 7, 11, 15, 19]);
+        // Counted down in between
+        via.catch_up(via.cycles + 2);
+        assert_eq!(via.read(Via::T1C_L), 1);
+        // Shifting in at the timer 2 rate takes as long
+        let mut via = Via::new();
+        via.write(Via::T2C_L, 0x01);
+        via.write(Via::ACR, 0b0000_0100);
+        via.set_cb2(true);
+        via.read(Via::SR);
+        while via.ifr & Via::IRQ_SR == 0 {
+            via.catch_up(via.next_event().unwrap());
+            via.tick();
+        }
+        assert_eq!(via.cycles, 8 * 2 * 3);
+        assert_eq!(via.sr, 0xFF);
+        assert_eq!(via.next_event(), None);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut via = Via::new();
+        via.write(Via::DDRA, 0xFF);
+        via.write(Via::T1C_L, 0x34);
+        via.write(Via::T1C_H, 0x12);
+        via.write(Via::IER, 0xFF);
+        via.reset();
+        assert_eq!(via.ddra, 0);
+        assert_eq!(via.ier, 0);
+        assert_eq!(via.t1_latch, 0x1234);
+    }
+}